@@ -0,0 +1,66 @@
+//! Compare the native rasterizer with the previous SVG -> resvg path.
+//!
+//!     cargo bench --bench raster
+//!     cargo bench --bench raster --features bench-resvg
+//!
+//! The second one requires the MPL-licensed resvg and usvg.
+
+use std::time::{Duration, Instant};
+use wallrnd::svg::Document;
+use wallrnd::Generator;
+
+const RUNS: u32 = 5;
+
+/// Same global settings as setup/wallrnd.toml with a single tiling
+fn config(shape: &str, nb_delaunay: usize) -> String {
+    format!(
+        "[global]
+width = 2732
+height = 1536
+size = 30.0
+[lines]
+width = 0.0
+[shapes]
+only = [\"{}\"]
+[data.tilings]
+nb_delaunay = {}
+[[entry]]
+shapes = [\"only\"]
+",
+        shape, nb_delaunay
+    )
+}
+
+fn time<T, F: FnMut() -> T>(mut f: F) -> Duration {
+    let start = Instant::now();
+    for _ in 0..RUNS {
+        std::hint::black_box(f());
+    }
+    start.elapsed() / RUNS
+}
+
+fn main() {
+    let scenes = [
+        ("hexagons", config("H", 0)),
+        ("pentagons", config("P", 0)),
+        ("delaunay", config("D", 20000)),
+    ];
+    for (name, cfg) in scenes.iter() {
+        let mut doc: Document = Generator::new()
+            .config_str(cfg.as_str())
+            .seed(0)
+            .time(1200)
+            .generate()
+            .expect("Generation failed");
+        println!("{} ({} paths)", name, doc.items.len());
+        println!("  native: {:?}", time(|| doc.rasterize().to_rgba8()));
+        doc.supersample = 2;
+        println!("  native x2: {:?}", time(|| doc.rasterize().to_rgba8()));
+        doc.supersample = 1;
+        #[cfg(feature = "bench-resvg")]
+        println!(
+            "  resvg:  {:?}",
+            time(|| doc.rasterize_with_resvg().expect("resvg failed"))
+        );
+    }
+}
@@ -3,8 +3,10 @@ use crate::prelude::*;
 use crate::scene::*;
 use crate::svg::*;
 use crate::tesselate::*;
-use rand::{rngs::ThreadRng, seq::SliceRandom, Rng};
-use std::rc::Rc;
+use crate::{Error, Result};
+use rand::{rngs::StdRng, seq::SliceRandom, Rng, SeedableRng};
+use serde_derive::{Deserialize, Serialize};
+use std::sync::Arc;
 
 /// General information on a scene
 pub struct SceneCfg {
@@ -12,16 +14,30 @@ pub struct SceneCfg {
     pub distance: usize,
     pub deviation: usize,
     pub frame: Frame,
+    pub resolution: Resolution,
+    /// Trim tiles to a margin around the frame
+    pub clip: bool,
+    /// Decimal digits of SVG coordinates
+    pub precision: Option<usize>,
+    /// Group SVG paths by style and merge neighbors of the same color
+    pub compact: bool,
+    /// Keep theme colors apart in SVG output so that it can be recolored
+    pub recolorable: bool,
     pub pattern: Pattern,
     pub tiling: Tiling,
+    pub rotation: isize,
     pub nb_pattern: usize,
     pub var_stripes: usize,
     pub size_tiling: f64,
+    pub sdiag_rhombus: f64,
     pub nb_delaunay: usize,
+    pub seed_delaunay: u64,
     pub width_pattern: f64,
     pub line_width: f64,
     pub line_color: Color,
     pub tightness_spiral: f64,
+    /// Settings given as ranges or lists and the values drawn for them
+    pub sampled: Vec<(String, toml::Value)>,
 }
 
 /// A trait to box scene items and make them generic.
@@ -30,16 +46,16 @@ trait Dynamic<C>
 where
     C: Contains + 'static,
 {
-    fn dynamic(self) -> Vec<Rc<dyn Contains>>;
+    fn dynamic(self) -> Vec<Arc<dyn Contains>>;
 }
 
 impl<C> Dynamic<C> for Vec<C>
 where
     C: Contains + 'static,
 {
-    fn dynamic(self) -> Vec<Rc<dyn Contains>> {
+    fn dynamic(self) -> Vec<Arc<dyn Contains>> {
         self.into_iter()
-            .map(|d| Rc::new(d) as Rc<dyn Contains>)
+            .map(|d| Arc::new(d) as Arc<dyn Contains>)
             .collect::<Vec<_>>()
     }
 }
@@ -47,60 +63,65 @@ where
 impl SceneCfg {
     /// Select a random color for a scene item.
     /// The actual color will depend on the Chooser<Color> with which it is mixed.
-    pub fn choose_color(&self, rng: &mut ThreadRng) -> ColorItem {
-        let ThemeItem(c, v, w, salt) = self
-            .theme
-            .choose(rng)
-            .unwrap_or_else(|| ThemeItem(Color(0, 0, 0), None, None, Salt::none()));
+    pub fn choose_color<R: Rng>(&self, rng: &mut R) -> ColorItem {
+        let (slot, ThemeItem(c, v, w, salt)) = match self.theme.choose_indexed(rng) {
+            Some((i, item)) => (Some(i), item),
+            None => (None, ThemeItem(Color(0, 0, 0), None, None, Salt::none())),
+        };
         ColorItem {
             shade: Color::random(rng),
             deviation: v.unwrap_or(self.deviation),
             distance: w.unwrap_or(self.distance),
             theme: c,
             salt,
+            slot,
         }
     }
 
     /// Match pattern to function that generates it
-    pub fn create_items(&self, rng: &mut ThreadRng, verbose: Verbosity) -> Vec<Rc<dyn Contains>> {
+    pub fn create_items<R: Rng>(
+        &self,
+        rng: &mut R,
+        diag: &dyn Diagnostics,
+    ) -> Vec<Arc<dyn Contains>> {
         match self.pattern {
-            Pattern::FreeCircles => create_free_circles(rng, &self, verbose).dynamic(),
-            Pattern::FreeTriangles => create_free_triangles(rng, &self, verbose).dynamic(),
-            Pattern::FreeStripes => create_free_stripes(rng, &self, verbose).dynamic(),
-            Pattern::FreeSpirals => create_free_spirals(rng, &self, verbose).dynamic(),
-            Pattern::ConcentricCircles => create_concentric_circles(rng, &self, verbose).dynamic(),
-            Pattern::ParallelStripes => create_parallel_stripes(rng, &self, verbose).dynamic(),
-            Pattern::CrossedStripes => create_crossed_stripes(rng, &self, verbose).dynamic(),
-            Pattern::ParallelWaves => create_waves(rng, &self, verbose).dynamic(),
-            Pattern::ParallelSawteeth => create_sawteeth(rng, &self, verbose).dynamic(),
+            Pattern::FreeCircles => create_free_circles(rng, self, diag).dynamic(),
+            Pattern::FreeTriangles => create_free_triangles(rng, self, diag).dynamic(),
+            Pattern::FreeStripes => create_free_stripes(rng, self, diag).dynamic(),
+            Pattern::FreeSpirals => create_free_spirals(rng, self, diag).dynamic(),
+            Pattern::ConcentricCircles => create_concentric_circles(rng, self, diag).dynamic(),
+            Pattern::ParallelStripes => create_parallel_stripes(rng, self, diag).dynamic(),
+            Pattern::CrossedStripes => create_crossed_stripes(rng, self, diag).dynamic(),
+            Pattern::ParallelWaves => create_waves(rng, self, diag).dynamic(),
+            Pattern::ParallelSawteeth => create_sawteeth(rng, self, diag).dynamic(),
         }
     }
 
-    /// Math tiling to function that generates it
-    pub fn make_tiling(&self, rng: &mut ThreadRng) -> Vec<(Pos, Path)> {
-        match self.tiling {
-            Tiling::Hexagons => tile_hexagons(&self.frame, self.size_tiling, rng.gen_range(0, 360)),
-            Tiling::Triangles => {
-                tile_triangles(&self.frame, self.size_tiling, rng.gen_range(0, 360))
-            }
+    /// Math tiling to function that generates it.
+    /// All random choices were already made when picking the configuration,
+    /// so the same SceneCfg always produces the same tiling.
+    pub fn make_tiling(&self) -> Result<Vec<(Pos, Path)>> {
+        Ok(match self.tiling {
+            Tiling::Hexagons => tile_hexagons(&self.frame, self.size_tiling, self.rotation),
+            Tiling::Triangles => tile_triangles(&self.frame, self.size_tiling, self.rotation),
             Tiling::HexagonsAndTriangles => {
-                tile_hybrid_hexagons_triangles(&self.frame, self.size_tiling, rng.gen_range(0, 360))
+                tile_hybrid_hexagons_triangles(&self.frame, self.size_tiling, self.rotation)
             }
             Tiling::SquaresAndTriangles => {
-                tile_hybrid_squares_triangles(&self.frame, self.size_tiling, rng.gen_range(0, 360))
+                tile_hybrid_squares_triangles(&self.frame, self.size_tiling, self.rotation)
             }
             Tiling::Rhombus => tile_rhombus(
                 &self.frame,
                 self.size_tiling,
-                (rng.gen::<f64>() * 0.6 + 0.4) * self.size_tiling,
-                rng.gen_range(0, 360),
+                self.sdiag_rhombus,
+                self.rotation,
             ),
-            Tiling::Delaunay => random_delaunay(&self.frame, rng, self.nb_delaunay),
+            Tiling::Delaunay => random_delaunay(
+                &self.frame,
+                &mut StdRng::seed_from_u64(self.seed_delaunay),
+                self.nb_delaunay,
+            )?,
             Tiling::Pentagons(n) => {
-                let n = match n {
-                    0 => rng.gen_range(1, 7),
-                    n => n,
-                };
                 let ptiler = match n {
                     1 => pentagons_type1,
                     2 => pentagons_type2,
@@ -108,10 +129,28 @@ impl SceneCfg {
                     4 => pentagons_type4,
                     5 => pentagons_type5,
                     6 => pentagons_type6,
-                    _ => unreachable!(),
+                    _ => return Err(Error::InvalidShape(format!("pentagons-{}", n))),
                 };
-                ptiler(&self.frame, self.size_tiling, rng.gen_range(0, 360))
+                ptiler(&self.frame, self.size_tiling, self.rotation)?
             }
+        })
+    }
+
+    /// Trim a tile to a margin around the frame if clipping is enabled,
+    /// None if nothing is left
+    pub fn clip_tile(&self, mut path: Path) -> Option<Path> {
+        if !self.clip {
+            return Some(path);
+        }
+        // Edges created by clipping are pushed out of view, otherwise their stroke
+        // would draw a line along the border. Strokes extend by half the line width,
+        // which leaves at least one unit between them and the frame.
+        let margin = self.line_width.max(0.1) + 1.;
+        path.data.0 = self.frame.clip(&path.data.0, margin);
+        if path.data.0.len() < 3 {
+            None
+        } else {
+            Some(path)
         }
     }
 }
@@ -132,9 +171,9 @@ pub enum Pattern {
 
 impl Pattern {
     /// Pick a random pattern (fallback if no other pattern choosing method is specified)
-    pub fn choose(rng: &mut ThreadRng) -> Self {
+    pub fn choose<R: Rng>(rng: &mut R) -> Self {
         use Pattern::*;
-        *vec![
+        *[
             FreeCircles,
             FreeTriangles,
             FreeStripes,
@@ -151,7 +190,8 @@ impl Pattern {
 }
 
 ///Available tilings, open to additions
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+#[serde(tag = "kind", content = "variant")]
 pub enum Tiling {
     Hexagons,
     Triangles,
@@ -163,10 +203,18 @@ pub enum Tiling {
 }
 
 impl Tiling {
+    /// Replace a choice among variants with a concrete variant
+    pub fn resolve<R: Rng>(self, rng: &mut R) -> Self {
+        match self {
+            Tiling::Pentagons(0) => Tiling::Pentagons(rng.gen_range(1, 7)),
+            t => t,
+        }
+    }
+
     /// Pick a random tiling (fallback if no other tiling choosing method is specified)
-    pub fn choose(rng: &mut ThreadRng) -> Self {
+    pub fn choose<R: Rng>(rng: &mut R) -> Self {
         use Tiling::*;
-        *vec![
+        *[
             Hexagons,
             Triangles,
             HexagonsAndTriangles,
@@ -1,16 +1,20 @@
-use rand::{rngs::ThreadRng, Rng};
+use rand::Rng;
 
 #[derive(Clone)]
 pub struct Chooser<T: Clone>(usize, Vec<(T, usize)>);
 
-impl<T: Clone> Chooser<T> {
+impl<T: Clone> Default for Chooser<T> {
     /// Empty Chooser
-    pub fn default() -> Self {
+    fn default() -> Self {
         Self(0, Vec::new())
     }
+}
 
-    /// Create Chooser from weighted items
+impl<T: Clone> Chooser<T> {
+    /// Create Chooser from weighted items.
+    /// Items with a weight of 0 can never be chosen and are dropped.
     pub fn new(mut v: Vec<(T, usize)>) -> Self {
+        v.retain(|(_, w)| *w > 0);
         let mut sum = 0;
         for (_, w) in &mut v {
             sum += *w;
@@ -24,23 +28,29 @@ impl<T: Clone> Chooser<T> {
     }
 
     /// Pick a random item (weighted)
-    pub fn choose(&self, rng: &mut ThreadRng) -> Option<T> {
+    pub fn choose<R: Rng>(&self, rng: &mut R) -> Option<T> {
+        self.choose_indexed(rng).map(|(_, item)| item)
+    }
+
+    /// Pick a random item (weighted) along with its position in `extract()`
+    pub fn choose_indexed<R: Rng>(&self, rng: &mut R) -> Option<(usize, T)> {
         if self.1.is_empty() {
             None
         } else {
             let choice = rng.gen_range(0, self.0);
-            Some(self.dichotomy(choice, 0, self.1.len()))
+            let i = self.dichotomy(choice, 0, self.1.len());
+            Some((i, self.1[i].0.clone()))
         }
     }
 
-    fn dichotomy(&self, target: usize, inf: usize, sup: usize) -> T {
+    fn dichotomy(&self, target: usize, inf: usize, sup: usize) -> usize {
         if inf == sup {
-            self.1[inf].0.clone()
+            inf
         } else if inf + 1 == sup {
-            if self.1[inf].1 < target {
-                self.1[inf + 1].0.clone()
+            if self.1[inf].1 <= target {
+                inf + 1
             } else {
-                self.1[inf].0.clone()
+                inf
             }
         } else {
             let mid = (sup + inf) / 2;
@@ -62,10 +72,51 @@ impl<T: Clone> Chooser<T> {
         cpy
     }
 
-    /// Add new item
+    /// Mix of two choosers, such that an item of `other` is picked
+    /// `a` times for every `b` times that an item of `self` is
+    pub fn blend(&self, other: &Self, a: usize, b: usize) -> Self {
+        self.blend_exact(other, a, b)
+            .unwrap_or_else(|| self.blend_scaled(other, a, b))
+    }
+
+    /// Integer weights, None if they do not fit
+    fn blend_exact(&self, other: &Self, a: usize, b: usize) -> Option<Self> {
+        let mut mix = Self::default();
+        let parts = [
+            (self, other.0.checked_mul(b)?),
+            (other, self.0.checked_mul(a)?),
+        ];
+        for (chooser, factor) in &parts {
+            for (item, w) in chooser.extract() {
+                let w = w.checked_mul(*factor)?;
+                mix.0.checked_add(w)?;
+                mix.push(item, w);
+            }
+        }
+        Some(mix)
+    }
+
+    /// Same proportions as `blend_exact` with weights rounded to a fixed total
+    fn blend_scaled(&self, other: &Self, a: usize, b: usize) -> Self {
+        const TOTAL: f64 = 1e9;
+        let mut mix = Self::default();
+        let (a, b) = (a as f64, b as f64);
+        for (chooser, part) in &[(self, b / (a + b)), (other, a / (a + b))] {
+            for (item, w) in chooser.extract() {
+                let share = w as f64 / chooser.0 as f64 * part;
+                // Items that can be picked stay so
+                if share > 0. {
+                    mix.push(item, ((share * TOTAL).round() as usize).max(1));
+                }
+            }
+        }
+        mix
+    }
+
+    /// Add new item, ignored if its weight is 0
     pub fn push(&mut self, item: T, w: usize) {
         if w == 0 {
-            panic!("In call to Chooser::push, 0 is not a valid weight");
+            return;
         }
         self.0 += w;
         self.1.push((item, self.0));
@@ -78,3 +129,64 @@ impl<T: Clone> Chooser<T> {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn choices_match_weights() {
+        for weights in &[
+            vec![1, 3],
+            vec![2, 1, 5, 1],
+            vec![1; 7],
+            vec![4, 0, 2, 9, 3],
+        ] {
+            let chooser = Chooser::new(weights.iter().cloned().enumerate().collect());
+            let mut counts = vec![0; weights.len()];
+            for target in 0..chooser.0 {
+                counts[chooser.dichotomy(target, 0, chooser.1.len())] += 1;
+            }
+            let kept = weights
+                .iter()
+                .filter(|&&w| w > 0)
+                .cloned()
+                .collect::<Vec<_>>();
+            assert_eq!(counts[..kept.len()], kept[..]);
+        }
+    }
+
+    #[test]
+    fn blend_keeps_proportions() {
+        let old = Chooser::new(vec![('a', 1), ('b', 3)]);
+        let new = Chooser::new(vec![('c', 5)]);
+        // New items are picked once for every three times that old ones are
+        let mix = old.blend(&new, 1, 3);
+        assert_eq!(mix.extract(), vec![('a', 15), ('b', 45), ('c', 20)]);
+        // Nothing of the new theme at the start of the transition
+        assert_eq!(
+            old.blend(&new, 0, 10).extract(),
+            vec![('a', 50), ('b', 150)]
+        );
+    }
+
+    #[test]
+    fn blend_large_weights() {
+        let big = usize::MAX / 4;
+        let old = Chooser::new(vec![('a', big), ('b', big)]);
+        let new = Chooser::new(vec![('c', big), ('d', 1)]);
+        let mix = old.blend(&new, 1, 1);
+        let w = mix.extract();
+        assert_eq!(w.len(), 4);
+        // Halves of the mix, with the tiny weight still there
+        assert_eq!(w[0].1, w[1].1);
+        assert_eq!(w[0].1 + w[1].1, w[2].1 + w[3].1 - 1);
+        assert_eq!(w[3].1, 1);
+        let many = Chooser::new(
+            (0..1000)
+                .map(|i| (char::from(i as u8), big / 1000))
+                .collect(),
+        );
+        assert_eq!(many.blend(&old, 1440, 1).extract().len(), 1002);
+    }
+}
@@ -1,13 +1,14 @@
-use rand::{rngs::ThreadRng, Rng};
+use rand::Rng;
+use serde_derive::{Deserialize, Serialize};
 use std::convert::TryInto;
 use std::fmt;
 
-#[derive(Clone, Copy, Debug)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
 pub struct Color(pub usize, pub usize, pub usize);
 
 impl Color {
     /// Ensure that all RGB values are within [[1; 100]]
-    fn validate(mut self) -> Self {
+    pub(crate) fn validate(mut self) -> Self {
         self.0 = self.0.min(255);
         self.1 = self.1.min(255);
         self.2 = self.2.min(255);
@@ -15,24 +16,25 @@ impl Color {
     }
 
     /// Random noise
-    pub fn variate(mut self, rng: &mut ThreadRng, amount: usize) -> Self {
+    pub fn variate<R: Rng + ?Sized>(mut self, rng: &mut R, amount: usize) -> Self {
         if amount > 0 {
             let amount = amount as isize;
-            self.0 = (self.0 as isize + rng.gen_range(-amount as isize, amount as isize))
+            self.0 = (self.0 as isize + rng.gen_range(-amount, amount))
                 .try_into()
                 .unwrap_or(0);
-            self.1 = (self.1 as isize + rng.gen_range(-amount as isize, amount as isize))
+            self.1 = (self.1 as isize + rng.gen_range(-amount, amount))
                 .try_into()
                 .unwrap_or(0);
-            self.2 = (self.2 as isize + rng.gen_range(-amount as isize, amount as isize))
+            self.2 = (self.2 as isize + rng.gen_range(-amount, amount))
                 .try_into()
                 .unwrap_or(0);
         }
         self
     }
 
-    /// Weighted mix with other color
+    /// Weighted mix with other color, `distance` is capped at 100
     pub fn meanpoint(mut self, th: Self, distance: usize) -> Self {
+        let distance = distance.min(100);
         self.0 = (self.0 * distance + th.0 * (100 - distance)) / 100;
         self.1 = (self.1 * distance + th.1 * (100 - distance)) / 100;
         self.2 = (self.2 * distance + th.2 * (100 - distance)) / 100;
@@ -40,7 +42,7 @@ impl Color {
     }
 
     /// Generate color
-    pub fn random(rng: &mut ThreadRng) -> Self {
+    pub fn random<R: Rng>(rng: &mut R) -> Self {
         Self(
             rng.gen_range(0, 255),
             rng.gen_range(0, 255),
@@ -56,3 +58,11 @@ impl fmt::Display for Color {
         write!(f, "rgb({},{},{})", c.0, c.1, c.2)
     }
 }
+
+/// Shorter SVG color format: `#rrggbb`
+impl fmt::LowerHex for Color {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let c = self.validate();
+        write!(f, "#{:02x}{:02x}{:02x}", c.0, c.1, c.2)
+    }
+}
@@ -0,0 +1,279 @@
+//! Smaller SVG output: paths are grouped by style, neighbors of the same color
+//! are merged and coordinates are relative.
+
+use crate::prelude::*;
+use crate::svg::{rounding, Document, Path};
+use std::collections::HashMap;
+use std::fmt;
+
+/// Paths drawn with the same attributes
+struct Group<'d> {
+    fill: Color,
+    stroke: Color,
+    paths: Vec<&'d Path>,
+}
+
+/// Vertices closer than this are considered equal when merging
+const KEY_SCALE: f64 = 1000.;
+
+type Key = (i64, i64);
+
+fn key(Pos(x, y): Pos) -> Key {
+    (
+        (x * KEY_SCALE).round() as i64,
+        (y * KEY_SCALE).round() as i64,
+    )
+}
+
+/// Twice the signed area
+fn area(poly: &[Pos]) -> f64 {
+    let mut sum = 0.;
+    for (i, a) in poly.iter().enumerate() {
+        let b = poly[(i + 1) % poly.len()];
+        sum += a.0 * b.1 - b.0 * a.1;
+    }
+    sum
+}
+
+/// Union of non-overlapping polygons as closed loops: edges shared by two polygons
+/// cancel out and the others are chained back together.
+/// Holes come out with the opposite orientation, so the result is meant to be
+/// filled with the nonzero rule. None if the remaining edges do not form loops.
+fn merge(polys: &[&[Pos]]) -> Option<Vec<Vec<Pos>>> {
+    let mut edges: Vec<(Key, Key)> = Vec::new();
+    let mut alive = Vec::new();
+    let mut vertices = HashMap::new();
+    let mut by_ends: HashMap<(Key, Key), Vec<usize>> = HashMap::new();
+    for poly in polys {
+        let mut poly = poly.to_vec();
+        // Shared edges can only cancel out if all polygons are oriented the same way
+        if area(&poly) < 0. {
+            poly.reverse();
+        }
+        for (i, &a) in poly.iter().enumerate() {
+            let b = poly[(i + 1) % poly.len()];
+            let (ka, kb) = (key(a), key(b));
+            if ka == kb {
+                continue;
+            }
+            vertices.entry(ka).or_insert(a);
+            match by_ends.get_mut(&(kb, ka)).and_then(|v| v.pop()) {
+                Some(opposite) => alive[opposite] = false,
+                None => {
+                    by_ends.entry((ka, kb)).or_default().push(edges.len());
+                    edges.push((ka, kb));
+                    alive.push(true);
+                }
+            }
+        }
+    }
+    // Outgoing edges of each vertex, in order of creation to keep the output stable
+    let mut outgoing: HashMap<Key, Vec<usize>> = HashMap::new();
+    for (n, &(a, _)) in edges.iter().enumerate().rev() {
+        if alive[n] {
+            outgoing.entry(a).or_default().push(n);
+        }
+    }
+    let mut loops = Vec::new();
+    for n in 0..edges.len() {
+        if !alive[n] {
+            continue;
+        }
+        let start = edges[n].0;
+        let mut lp = Vec::new();
+        let mut current = n;
+        loop {
+            alive[current] = false;
+            let (a, b) = edges[current];
+            lp.push(vertices[&a]);
+            if b == start {
+                break;
+            }
+            current = loop {
+                let next = outgoing.get_mut(&b)?.pop()?;
+                if alive[next] {
+                    break next;
+                }
+            };
+        }
+        if lp.len() >= 3 {
+            loops.push(lp);
+        }
+    }
+    Some(loops)
+}
+
+/// Path data with relative coordinates, several loops in a single path
+struct Relative<'a> {
+    loops: &'a [Vec<Pos>],
+    precision: Option<usize>,
+}
+
+impl fmt::Display for Relative<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let round = rounding(self.precision);
+        for lp in self.loops {
+            // Differences of rounded values do not accumulate rounding errors
+            let Pos(x0, y0) = lp[0];
+            let (mut x, mut y) = (round(x0), round(y0));
+            write!(f, "M{},{}l", x, y)?;
+            for (i, &Pos(nx, ny)) in lp.iter().enumerate().skip(1) {
+                let (nx, ny) = (round(nx), round(ny));
+                let sep = if i == 1 { "" } else { " " };
+                write!(f, "{}{},{}", sep, round(nx - x), round(ny - y))?;
+                x = nx;
+                y = ny;
+            }
+            write!(f, "z")?;
+        }
+        Ok(())
+    }
+}
+
+impl Document {
+    /// Paths that share a style, in order of first appearance
+    fn groups(&self) -> Vec<Group<'_>> {
+        let mut groups: Vec<Group> = Vec::new();
+        let mut index = HashMap::new();
+        for path in &self.items {
+            let n = *index
+                .entry((path.fill_color, path.stroke_color))
+                .or_insert_with(|| {
+                    groups.push(Group {
+                        fill: path.fill_color,
+                        stroke: path.stroke_color,
+                        paths: Vec::new(),
+                    });
+                    groups.len() - 1
+                });
+            groups[n].paths.push(path);
+        }
+        groups
+    }
+
+    /// SVG with one path per style, used instead of `Display` when `compact` is set
+    pub(crate) fn fmt_compact(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        // Documents built by `Wallpaper::render` use a single stroke width
+        let width = self.items.first().map(|p| p.stroke_width).unwrap_or(0.);
+        let uniform = self.items.iter().all(|p| p.stroke_width == width);
+        if uniform {
+            writeln!(f, "<g stroke-width=\"{}\">", width)?;
+        }
+        for group in self.groups() {
+            let polys = group
+                .paths
+                .iter()
+                .map(|p| p.data.0.as_slice())
+                .collect::<Vec<_>>();
+            // Merging removes the strokes between tiles,
+            // which is only invisible if they are the same color as the fill
+            let merged = if group.fill == group.stroke {
+                merge(&polys)
+            } else {
+                None
+            };
+            let loops = merged.unwrap_or_else(|| polys.iter().map(|p| p.to_vec()).collect());
+            write!(
+                f,
+                "<path fill=\"{:x}\" stroke=\"{:x}\" ",
+                group.fill, group.stroke
+            )?;
+            if !uniform {
+                write!(f, "stroke-width=\"{}\" ", group.paths[0].stroke_width)?;
+            }
+            writeln!(
+                f,
+                "d=\"{}\"/>",
+                Relative {
+                    loops: &loops,
+                    precision: self.precision,
+                }
+            )?;
+        }
+        if uniform {
+            writeln!(f, "</g>")?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn square(x: f64, y: f64) -> Vec<Pos> {
+        vec![
+            Pos(x, y),
+            Pos(x + 1., y),
+            Pos(x + 1., y + 1.),
+            Pos(x, y + 1.),
+        ]
+    }
+
+    fn merged(polys: &[Vec<Pos>]) -> Vec<Vec<Pos>> {
+        merge(&polys.iter().map(Vec::as_slice).collect::<Vec<_>>()).unwrap()
+    }
+
+    #[test]
+    fn shared_edges_cancel_out() {
+        let loops = merged(&[square(0., 0.), square(1., 0.)]);
+        assert_eq!(loops.len(), 1);
+        assert_eq!(loops[0].len(), 6);
+        assert!((area(&loops[0]) - 4.).abs() < 1e-9);
+        // The same with opposite orientations
+        let mut reversed = square(1., 0.);
+        reversed.reverse();
+        let loops = merged(&[square(0., 0.), reversed]);
+        assert_eq!(loops.len(), 1);
+        assert!((area(&loops[0]) - 4.).abs() < 1e-9);
+    }
+
+    #[test]
+    fn nearly_equal_vertices_match() {
+        let mut shifted = square(1., 0.);
+        shifted[0].0 += 1e-5;
+        shifted[3].0 -= 1e-5;
+        assert_eq!(merged(&[square(0., 0.), shifted]).len(), 1);
+    }
+
+    #[test]
+    fn disjoint_polygons_stay_apart() {
+        let loops = merged(&[square(0., 0.), square(5., 5.)]);
+        assert_eq!(loops.len(), 2);
+        assert!(loops.iter().all(|lp| lp.len() == 4));
+    }
+
+    #[test]
+    fn ring_has_a_hole() {
+        let ring = (0..3)
+            .flat_map(|i| (0..3).map(move |j| (i, j)))
+            .filter(|&(i, j)| (i, j) != (1, 1))
+            .map(|(i, j)| square(i as f64, j as f64))
+            .collect::<Vec<_>>();
+        let loops = merged(&ring);
+        assert_eq!(loops.len(), 2);
+        // The hole is oriented the other way, nonzero filling leaves it empty
+        let mut areas = loops.iter().map(|lp| area(lp) / 2.).collect::<Vec<_>>();
+        areas.sort_by(f64::total_cmp);
+        assert!((areas[0] + 1.).abs() < 1e-9);
+        assert!((areas[1] - 9.).abs() < 1e-9);
+    }
+
+    #[test]
+    fn relative_coordinates() {
+        let loops = [
+            vec![Pos(0., 0.), Pos(1.25, 0.), Pos(1.25, 2.)],
+            vec![Pos(10., 10.), Pos(11., 10.), Pos(10., 11.)],
+        ];
+        let rel = |precision| {
+            Relative {
+                loops: &loops,
+                precision,
+            }
+            .to_string()
+        };
+        assert_eq!(rel(None), "M0,0l1.25,0 0,2zM10,10l1,0 -1,1z");
+        assert_eq!(rel(Some(1)), "M0,0l1.3,0 0,2zM10,10l1,0 -1,1z");
+        assert_eq!(rel(Some(0)), "M0,0l1,0 0,2zM10,10l1,0 -1,1z");
+    }
+}
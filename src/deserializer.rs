@@ -1,8 +1,14 @@
 use crate::cfg::SceneCfg;
 use crate::prelude::*;
-use rand::{rngs::ThreadRng, seq::SliceRandom};
+use crate::schedule::{minutes_between, parse_span, Moment, Selector, SELECTORS};
+use crate::sun::{parse_sun_span, Location};
+use crate::svg::MAX_PRECISION;
+use crate::{layers, Error, Result};
+use chrono::NaiveDate;
+use rand::{seq::SliceRandom, Rng};
 use serde_derive::Deserialize;
 use std::collections::HashMap;
+use std::path::{Path, PathBuf};
 use toml::{map::Map, Value};
 
 const BASE_WEIGHT: usize = 10;
@@ -16,22 +22,30 @@ pub struct MetaConfig {
     pub themes: Option<ConfigThemes>,
     pub shapes: Option<ConfigShapes>,
     pub data: Option<ConfigData>,
+    pub location: Option<ConfigLocation>,
     pub entry: Option<Vec<ConfigEntry>>,
 }
 
 /// Global options
-#[derive(Deserialize, Default, Debug)]
+#[derive(Deserialize, Default, Debug, Clone)]
 pub struct ConfigGlobal {
-    pub deviation: Option<usize>,
-    pub weight: Option<usize>, // Artifact of previous name
-    pub distance: Option<usize>,
-    pub size: Option<f64>,
-    pub width: Option<usize>,
-    pub height: Option<usize>,
+    pub deviation: Option<Param<usize>>,
+    pub weight: Option<Param<usize>>, // Artifact of previous name
+    pub distance: Option<Param<usize>>,
+    pub size: Option<Param<f64>>,
+    pub width: Option<Param<usize>>,
+    pub height: Option<Param<usize>>,
+    pub output_width: Option<Param<usize>>,
+    pub output_height: Option<Param<usize>>,
+    pub supersample: Option<Param<usize>>,
+    pub clip: Option<bool>,
+    pub precision: Option<Param<usize>>,
+    pub compact: Option<bool>,
+    pub recolorable: Option<bool>,
 }
 
 /// Lines appearance
-#[derive(Deserialize, Default, Debug)]
+#[derive(Deserialize, Default, Debug, Clone)]
 pub struct ConfigLines {
     pub width: Option<f64>,
     pub color: Option<String>,
@@ -73,78 +87,403 @@ pub struct ConfigShapes {
 }
 
 /// Group together pattern options and tiling options
-#[derive(Deserialize, Default, Debug)]
+#[derive(Deserialize, Default, Debug, Clone)]
 pub struct ConfigData {
     pub patterns: Option<ConfigPatterns>,
     pub tilings: Option<ConfigTilings>,
 }
 
 /// Tiling options
-#[derive(Deserialize, Default, Debug)]
+#[derive(Deserialize, Default, Debug, Clone)]
 pub struct ConfigTilings {
-    pub size_hex: Option<f64>,
-    pub size_tri: Option<f64>,
-    pub size_hex_and_tri: Option<f64>,
-    pub size_squ_and_tri: Option<f64>,
-    pub size_rho: Option<f64>,
-    pub size_pen: Option<f64>,
-    pub nb_delaunay: Option<usize>,
+    pub size_hex: Option<Param<f64>>,
+    pub size_tri: Option<Param<f64>>,
+    pub size_hex_and_tri: Option<Param<f64>>,
+    pub size_squ_and_tri: Option<Param<f64>>,
+    pub size_rho: Option<Param<f64>>,
+    pub size_pen: Option<Param<f64>>,
+    pub nb_delaunay: Option<Param<usize>>,
 }
 
 /// Pattern options
-#[derive(Deserialize, Default, Debug)]
+#[derive(Deserialize, Default, Debug, Clone)]
 pub struct ConfigPatterns {
-    pub nb_free_circles: Option<usize>,
-    pub nb_free_spirals: Option<usize>,
-    pub nb_free_stripes: Option<usize>,
-    pub nb_crossed_stripes: Option<usize>,
-    pub nb_parallel_stripes: Option<usize>,
-    pub nb_concentric_circles: Option<usize>,
-    pub nb_free_triangles: Option<usize>,
-    pub nb_parallel_waves: Option<usize>,
-    pub nb_parallel_sawteeth: Option<usize>,
-    pub var_parallel_stripes: Option<usize>,
-    pub var_crossed_stripes: Option<usize>,
-    pub width_spiral: Option<f64>,
-    pub width_stripe: Option<f64>,
-    pub width_wave: Option<f64>,
-    pub width_sawtooth: Option<f64>,
-    pub tightness_spiral: Option<f64>,
+    pub nb_free_circles: Option<Param<usize>>,
+    pub nb_free_spirals: Option<Param<usize>>,
+    pub nb_free_stripes: Option<Param<usize>>,
+    pub nb_crossed_stripes: Option<Param<usize>>,
+    pub nb_parallel_stripes: Option<Param<usize>>,
+    pub nb_concentric_circles: Option<Param<usize>>,
+    pub nb_free_triangles: Option<Param<usize>>,
+    pub nb_parallel_waves: Option<Param<usize>>,
+    pub nb_parallel_sawteeth: Option<Param<usize>>,
+    pub var_parallel_stripes: Option<Param<usize>>,
+    pub var_crossed_stripes: Option<Param<usize>>,
+    pub width_spiral: Option<Param<f64>>,
+    pub width_stripe: Option<Param<f64>>,
+    pub width_wave: Option<Param<f64>>,
+    pub width_sawtooth: Option<Param<f64>>,
+    pub tightness_spiral: Option<Param<f64>>,
+}
+
+/// Position used by `sun` spans of entries, in degrees
+#[derive(Deserialize, Default, Debug)]
+pub struct ConfigLocation {
+    pub latitude: Option<f64>,
+    pub longitude: Option<f64>,
+}
+
+/// Either `span = "HHMM-HHMM"` or `span = ["HHMM-HHMM", ...]`, same for `sun`
+#[derive(Deserialize, Debug)]
+#[serde(untagged, expecting = "expected a span or an array of spans")]
+pub enum Spans {
+    One(String),
+    Many(Vec<String>),
+}
+
+impl Spans {
+    pub fn list(&self) -> &[String] {
+        match self {
+            Spans::One(s) => std::slice::from_ref(s),
+            Spans::Many(v) => v,
+        }
+    }
+}
+
+/// A number, a range `[min, max]` or a list of `[value, weight]` pairs,
+/// in which case a value is drawn for each image
+#[derive(Deserialize, Debug, Clone)]
+#[serde(
+    untagged,
+    expecting = "expected a number, a range [min, max] or a list of [value, weight] pairs"
+)]
+pub enum Param<T> {
+    Fixed(T),
+    Range(T, T),
+    Weighted(Vec<(T, usize)>),
+}
+
+/// Types of numeric settings
+pub trait Number: Copy + PartialOrd + Clone {
+    /// Uniformly between `lo` and `hi`: integers include `hi`, floats exclude it
+    fn between<R: Rng>(rng: &mut R, lo: Self, hi: Self) -> Self;
+    fn to_value(self) -> Value;
+}
+
+impl Number for usize {
+    fn between<R: Rng>(rng: &mut R, lo: Self, hi: Self) -> Self {
+        rng.gen_range(lo, hi + 1)
+    }
+
+    fn to_value(self) -> Value {
+        Value::Integer(self as i64)
+    }
+}
+
+impl Number for f64 {
+    fn between<R: Rng>(rng: &mut R, lo: Self, hi: Self) -> Self {
+        if lo < hi {
+            rng.gen_range(lo, hi)
+        } else {
+            lo
+        }
+    }
+
+    fn to_value(self) -> Value {
+        Value::Float(self)
+    }
+}
+
+impl<T: Number> Param<T> {
+    /// None if the list is empty
+    fn sample<R: Rng>(&self, rng: &mut R) -> Option<T> {
+        match self {
+            Param::Fixed(x) => Some(*x),
+            Param::Range(a, b) if a <= b => Some(T::between(rng, *a, *b)),
+            Param::Range(a, b) => Some(T::between(rng, *b, *a)),
+            Param::Weighted(list) => Chooser::new(list.clone()).choose(rng),
+        }
+    }
+}
+
+/// Values drawn for the settings that were given as ranges or lists
+#[derive(Default)]
+struct Draws(Vec<(String, Value)>);
+
+impl Draws {
+    /// Value of the setting `key`, which is drawn and recorded if it is not a single number
+    fn get<T: Number, R: Rng>(
+        &mut self,
+        rng: &mut R,
+        key: &str,
+        param: &Option<Param<T>>,
+    ) -> Option<T> {
+        let param = param.as_ref()?;
+        let value = param.sample(rng)?;
+        if !matches!(param, Param::Fixed(_)) {
+            self.0.push((key.to_string(), value.to_value()));
+        }
+        Some(value)
+    }
+}
+
+/// Parsing the text directly rather than merged tables gives errors with line numbers
+fn has_include(src: &str) -> bool {
+    matches!(src.parse::<Value>(), Ok(Value::Table(t)) if t.contains_key("include"))
+}
+
+/// Settings of an entry that replace the ones of the whole configuration
+trait Overlay: Sized {
+    /// Values of `over` where they are given, those of `self` otherwise
+    fn overlay(self, over: Self) -> Self;
+}
+
+impl<T: Overlay> Overlay for Option<T> {
+    fn overlay(self, over: Self) -> Self {
+        match (self, over) {
+            (Some(base), Some(over)) => Some(base.overlay(over)),
+            (base, None) => base,
+            (None, over) => over,
+        }
+    }
+}
+
+macro_rules! overlay_fields {
+    ($name:ident { $($field:ident),* $(,)? }) => {
+        impl Overlay for $name {
+            fn overlay(self, over: Self) -> Self {
+                Self {
+                    $($field: over.$field.or(self.$field),)*
+                }
+            }
+        }
+    };
+}
+
+#[rustfmt::skip]
+overlay_fields!(ConfigGlobal {
+    deviation, weight, distance, size, width, height, output_width, output_height,
+    supersample, clip, precision, compact, recolorable,
+});
+
+#[rustfmt::skip]
+overlay_fields!(ConfigLines {
+    width, color, del_width, del_color, hex_width, hex_color, tri_width, tri_color,
+    rho_width, rho_color, hex_and_tri_width, hex_and_tri_color,
+    squ_and_tri_width, squ_and_tri_color, pen_width, pen_color,
+});
+
+#[rustfmt::skip]
+overlay_fields!(ConfigTilings {
+    size_hex, size_tri, size_hex_and_tri, size_squ_and_tri, size_rho, size_pen, nb_delaunay,
+});
+
+#[rustfmt::skip]
+overlay_fields!(ConfigPatterns {
+    nb_free_circles, nb_free_spirals, nb_free_stripes, nb_crossed_stripes,
+    nb_parallel_stripes, nb_concentric_circles, nb_free_triangles, nb_parallel_waves,
+    nb_parallel_sawteeth, var_parallel_stripes, var_crossed_stripes, width_spiral,
+    width_stripe, width_wave, width_sawtooth, tightness_spiral,
+});
+
+impl Overlay for ConfigData {
+    fn overlay(self, over: Self) -> Self {
+        Self {
+            patterns: self.patterns.overlay(over.patterns),
+            tilings: self.tilings.overlay(over.tilings),
+        }
+    }
+}
+
+/// Either `"name"` or `["name", weight]` in the `themes` and `shapes` of an entry
+#[derive(Deserialize, Debug)]
+#[serde(untagged, expecting = "expected a name or a pair [name, weight]")]
+pub enum WeightedName {
+    Plain(String),
+    Weighted(String, usize),
+}
+
+impl WeightedName {
+    pub fn pair(&self) -> (&str, usize) {
+        match self {
+            WeightedName::Plain(s) => (s, BASE_WEIGHT),
+            WeightedName::Weighted(s, w) => (s, *w),
+        }
+    }
 }
 
 /// Entry for a single theme/time combination
 #[derive(Deserialize, Debug)]
 pub struct ConfigEntry {
-    pub span: Option<String>,
+    pub span: Option<Spans>,
+    pub sun: Option<Spans>,
+    pub weekdays: Option<Vec<String>>,
+    pub months: Option<Vec<String>>,
+    pub dates: Option<Vec<String>>,
+    pub seasons: Option<Vec<String>>,
+    pub weight: Option<usize>,
     pub distance: Option<usize>,
-    pub themes: Option<Vec<String>>,
-    pub shapes: Option<Vec<String>>,
+    pub themes: Option<Vec<WeightedName>>,
+    pub shapes: Option<Vec<WeightedName>>,
     pub line_color: Option<String>,
+    pub transition: Option<usize>,
+    pub global: Option<ConfigGlobal>,
+    pub lines: Option<ConfigLines>,
+    pub data: Option<ConfigData>,
 }
 
 impl MetaConfig {
     /// Parse from TOML.
     /// Heavy lifting done by external crates
-    pub fn from_string(src: String, verbose: Verbosity) -> Self {
-        toml::from_str(src.as_str()).unwrap_or_else(|e| {
-            if verbose.warn {
-                println!("No valid config file found, picking default settings");
-                println!("Message: {}", e);
-            }
+    pub fn from_string(src: String, diag: &dyn Diagnostics) -> Self {
+        Self::parse(&src).unwrap_or_else(|e| {
+            diag.warn(format_args!(
+                "No valid config file found, picking default settings\nMessage: {}",
+                e
+            ));
             MetaConfig::default()
         })
     }
 
+    /// Parse from TOML, reporting errors.
+    /// Files that it includes are relative to the current directory.
+    pub fn parse(src: &str) -> Result<Self> {
+        if has_include(src) {
+            Self::from_table(layers::parse(src, Path::new("."))?)
+        } else {
+            Ok(toml::from_str(src)?)
+        }
+    }
+
+    /// Read a configuration file and the files that it includes
+    pub fn load(path: &Path) -> Result<Self> {
+        let src = std::fs::read_to_string(path)?;
+        if has_include(&src) {
+            Self::from_table(layers::load(path)?)
+        } else {
+            Ok(toml::from_str(&src)?)
+        }
+    }
+
+    /// Read configuration files on top of each other, later ones take precedence
+    pub fn load_all(paths: &[PathBuf]) -> Result<Self> {
+        match paths {
+            [path] => Self::load(path),
+            _ => Self::from_table(layers::load_all(paths)?),
+        }
+    }
+
+    /// Configuration from TOML that was already parsed and merged
+    pub fn from_table(table: Map<String, Value>) -> Result<Self> {
+        Ok(Value::Table(table).try_into()?)
+    }
+
+    /// Get list of named colors
+    pub fn color_list(&self, diag: &dyn Diagnostics) -> ColorList {
+        let mut colors = HashMap::new();
+        if let Some(ConfigColors { list }) = &self.colors {
+            for name in list.keys() {
+                match color_from_value(&list[name], &colors) {
+                    Ok(c) => {
+                        diag.details(format_args!(
+                            "Added new color to list: '{} = {}'",
+                            &name, &c
+                        ));
+                        colors.insert(name.clone(), c);
+                    }
+                    Err(e) => diag.warn(format_args!("{}", e)),
+                }
+            }
+        }
+        colors
+    }
+
+    /// Get list of named themes
+    pub fn theme_list(&self, colors: &ColorList, diag: &dyn Diagnostics) -> ThemeList {
+        let mut themes = HashMap::new();
+        if let Some(ConfigThemes { list }) = &self.themes {
+            for name in list.keys() {
+                match theme_from_value(&list[name], colors, &themes, diag) {
+                    Ok(th) => {
+                        diag.details(format_args!("Added new theme to list: '{}'", &name));
+                        themes.insert(name.clone(), th);
+                    }
+                    Err(e) => diag.warn(format_args!("{}", e)),
+                }
+            }
+        }
+        themes
+    }
+
+    /// List of allowed shape combinations
+    pub fn shape_list(&self, diag: &dyn Diagnostics) -> ShapeList {
+        let mut shapes = HashMap::new();
+        if let Some(ConfigShapes { list }) = &self.shapes {
+            for name in list.keys() {
+                diag.details(format_args!("Added new shapes to list: '{}'", &name));
+                let sh = shapes_from_value(&list[name], &shapes, diag);
+                shapes.insert(name.clone(), sh);
+            }
+        }
+        shapes
+    }
+
+    /// Position given in `[location]`, None if it is missing or incomplete
+    pub fn location(&self, diag: &dyn Diagnostics) -> Option<Location> {
+        let loc = self.location.as_ref()?;
+        match (loc.latitude, loc.longitude) {
+            (Some(latitude), Some(longitude))
+                if latitude.abs() <= 90. && longitude.abs() <= 180. =>
+            {
+                Some(Location {
+                    latitude,
+                    longitude,
+                })
+            }
+            (Some(_), Some(_)) => {
+                diag.warn(format_args!(
+                    "location: latitude or longitude out of range, sun positions are unavailable"
+                ));
+                None
+            }
+            _ => {
+                diag.warn(format_args!(
+                    "location: latitude and longitude are both required, sun positions are unavailable"
+                ));
+                None
+            }
+        }
+    }
+
     /// Choose options at random according to configuration
-    pub fn pick_cfg(self, rng: &mut ThreadRng, time: usize, verbose: Verbosity) -> SceneCfg {
+    pub fn pick_cfg<R: Rng>(
+        mut self,
+        rng: &mut R,
+        now: Moment,
+        diag: &dyn Diagnostics,
+    ) -> SceneCfg {
+        let Chosen {
+            theme,
+            shapes: shape,
+            line_color: line_color_override,
+            distance: entry_distance,
+            global,
+            lines,
+            data,
+            fade,
+        } = choose_theme_shapes(rng, &self.entry, now, self.location(diag), diag);
+        // Settings of the entry replace those of the whole configuration
+        self.global = self.global.overlay(global);
+        self.lines = self.lines.overlay(lines);
+        self.data = self.data.overlay(data);
+        let mut draws = Draws::default();
+
         // Read default/overriden global options
         let (deviation, distance, size, width, height) = {
             let (deviation, distance, size, width, height);
-            match self.global {
+            match &self.global {
                 None => {
-                    if verbose.info {
-                        println!("Default global");
-                    }
+                    diag.info(format_args!("Default global"));
                     deviation = DEVIATION;
                     distance = DISTANCE;
                     size = SIZE;
@@ -152,53 +491,46 @@ impl MetaConfig {
                     height = HEIGHT;
                 }
                 Some(g) => {
-                    match g.deviation {
+                    match draws.get(rng, "global.deviation", &g.deviation) {
                         None => {
-                            if verbose.info {
-                                println!("Default global.deviation");
-                            }
+                            diag.info(format_args!("Default global.deviation"));
                             deviation = DEVIATION;
                         }
                         Some(d) => deviation = d,
                     }
-                    match g.distance {
+                    match draws.get(rng, "global.distance", &g.distance) {
                         None => {
-                            distance = g.weight.unwrap_or_else(|| {
-                                if verbose.info {
-                                    println!("Default global.distance");
-                                }
-                                DISTANCE
-                            });
+                            distance =
+                                draws
+                                    .get(rng, "global.weight", &g.weight)
+                                    .unwrap_or_else(|| {
+                                        diag.info(format_args!("Default global.distance"));
+                                        DISTANCE
+                                    });
                         }
                         Some(w) => distance = w,
                     }
-                    match g.size {
+                    match draws.get(rng, "global.size", &g.size) {
                         None => {
-                            if verbose.info {
-                                println!("Default global.size");
-                            }
+                            diag.info(format_args!("Default global.size"));
                             size = SIZE;
                         }
                         Some(s) => {
                             size = s;
                         }
                     }
-                    match g.width {
+                    match draws.get(rng, "global.width", &g.width) {
                         None => {
-                            if verbose.info {
-                                println!("Default global.width");
-                            }
+                            diag.info(format_args!("Default global.width"));
                             width = WIDTH;
                         }
                         Some(w) => {
                             width = w;
                         }
                     }
-                    match g.height {
+                    match draws.get(rng, "global.height", &g.height) {
                         None => {
-                            if verbose.info {
-                                println!("Default global.height");
-                            }
+                            diag.info(format_args!("Default global.height"));
                             height = HEIGHT;
                         }
                         Some(s) => {
@@ -207,84 +539,49 @@ impl MetaConfig {
                     }
                 }
             }
-            if verbose.details {
-                println!(
-                    "Global settings:
+            diag.details(format_args!(
+                "Global settings:
     Deviation   (color)    {}
     Distance      (color)    {}
     Size        (tiles)    {}
     Width       (image)    {}
     Height      (image)    {}",
-                    deviation, distance, size, width, height
-                );
-            }
+                deviation, distance, size, width, height
+            ));
             (deviation, distance, size, width, height)
         };
-
-        // Get list of named colors
-        let colors = {
-            let mut colors = HashMap::new();
-            if let Some(ConfigColors { list }) = self.colors {
-                for name in list.keys() {
-                    match color_from_value(&list[name], &colors) {
-                        Ok(c) => {
-                            if verbose.details {
-                                println!("Added new color to list: '{} = {}'", &name, &c);
-                            }
-                            colors.insert(name.clone(), c);
-                        }
-                        Err(s) => {
-                            if verbose.warn {
-                                println!("{}", s);
-                            }
-                        }
+        let resolution = match &self.global {
+            None => Resolution::default(),
+            Some(g) => Resolution {
+                width: draws.get(rng, "global.output_width", &g.output_width),
+                height: draws.get(rng, "global.output_height", &g.output_height),
+                supersample: match draws.get(rng, "global.supersample", &g.supersample) {
+                    None => 1,
+                    Some(0) => {
+                        diag.warn(format_args!("global.supersample must be at least 1"));
+                        1
                     }
-                }
-            }
-            colors
+                    Some(n) => n,
+                },
+            },
         };
 
-        // Get list of named themes
-        let mut themes = {
-            let mut themes = HashMap::new();
-            if let Some(ConfigThemes { list }) = self.themes {
-                for name in list.keys() {
-                    match theme_from_value(&list[name], &colors, &themes, verbose) {
-                        Ok(th) => {
-                            if verbose.details {
-                                println!("Added new theme to list: '{}'", &name);
-                            }
-                            themes.insert(name.clone(), th);
-                        }
-                        Err(s) => {
-                            if verbose.warn {
-                                println!("{}", s);
-                            }
-                        }
-                    }
-                }
-            }
-            themes
-        };
+        let colors = self.color_list(diag);
+        let mut themes = self.theme_list(&colors, diag);
+        let shapes = self.shape_list(diag);
 
-        // List of allowed shape combinations
-        let shapes = {
-            let mut shapes = HashMap::new();
-            if let Some(ConfigShapes { list }) = self.shapes {
-                for name in list.keys() {
-                    if verbose.details {
-                        println!("Added new shapes to list: '{}'", &name);
-                    }
-                    shapes.insert(name.clone(), shapes_from_value(&list[name], &shapes));
-                }
+        diag.info(format_args!("Chosen theme: '{}'", &theme));
+        let distance = match entry_distance {
+            Some(d) => {
+                diag.info(format_args!("Distance {} set by the entry", d));
+                d
             }
-            shapes
+            None => distance,
         };
-
-        let (theme, shape, line_color_override) = choose_theme_shapes(rng, &self.entry, time);
-        if verbose.info {
-            println!("Chosen theme: '{}'", &theme);
+        if distance > 100 {
+            diag.warn(format_args!("Distance {} must be at most 100", distance));
         }
+        let distance = distance.min(100);
 
         let (tiling, pattern) = match shapes.get(&shape) {
             None => (Tiling::choose(rng), Pattern::choose(rng)),
@@ -293,12 +590,11 @@ impl MetaConfig {
                 t.0.choose(rng).unwrap_or_else(|| Pattern::choose(rng)),
             ),
         };
-        if verbose.info {
-            println!(
-                "Pattern '{:?}' and tiling '{:?}' chosen from shapes '{}'",
-                pattern, tiling, shape
-            );
-        }
+        let tiling = tiling.resolve(rng);
+        diag.info(format_args!(
+            "Pattern '{:?}' and tiling '{:?}' chosen from shapes '{}'",
+            pattern, tiling, shape
+        ));
 
         // Get pattern-specific information according to picked shapes
         let (nb_pattern, var_stripes, width_pattern, tightness_spiral) = {
@@ -311,38 +607,94 @@ impl MetaConfig {
             {
                 match pattern {
                     Pattern::FreeCircles => {
-                        nb_pattern = p.nb_free_circles.unwrap_or(NB_FREE_CIRCLES);
+                        nb_pattern = draws
+                            .get(rng, "data.patterns.nb_free_circles", &p.nb_free_circles)
+                            .unwrap_or(NB_FREE_CIRCLES);
                     }
                     Pattern::FreeTriangles => {
-                        nb_pattern = p.nb_free_triangles.unwrap_or(NB_FREE_TRIANGLES);
+                        nb_pattern = draws
+                            .get(rng, "data.patterns.nb_free_triangles", &p.nb_free_triangles)
+                            .unwrap_or(NB_FREE_TRIANGLES);
                     }
                     Pattern::FreeStripes => {
-                        nb_pattern = p.nb_free_stripes.unwrap_or(NB_FREE_STRIPES);
-                        width_pattern = p.width_stripe.unwrap_or(WIDTH_STRIPE);
+                        nb_pattern = draws
+                            .get(rng, "data.patterns.nb_free_stripes", &p.nb_free_stripes)
+                            .unwrap_or(NB_FREE_STRIPES);
+                        width_pattern = draws
+                            .get(rng, "data.patterns.width_stripe", &p.width_stripe)
+                            .unwrap_or(WIDTH_STRIPE);
                     }
                     Pattern::FreeSpirals => {
-                        nb_pattern = p.nb_free_spirals.unwrap_or(NB_FREE_SPIRALS);
-                        width_pattern = p.width_spiral.unwrap_or(WIDTH_SPIRAL);
-                        tightness_spiral = p.tightness_spiral.unwrap_or(TIGHTNESS_SPIRAL);
+                        nb_pattern = draws
+                            .get(rng, "data.patterns.nb_free_spirals", &p.nb_free_spirals)
+                            .unwrap_or(NB_FREE_SPIRALS);
+                        width_pattern = draws
+                            .get(rng, "data.patterns.width_spiral", &p.width_spiral)
+                            .unwrap_or(WIDTH_SPIRAL);
+                        tightness_spiral = draws
+                            .get(rng, "data.patterns.tightness_spiral", &p.tightness_spiral)
+                            .unwrap_or(TIGHTNESS_SPIRAL);
                     }
                     Pattern::ConcentricCircles => {
-                        nb_pattern = p.nb_concentric_circles.unwrap_or(NB_CONCENTRIC_CIRCLES);
+                        nb_pattern = draws
+                            .get(
+                                rng,
+                                "data.patterns.nb_concentric_circles",
+                                &p.nb_concentric_circles,
+                            )
+                            .unwrap_or(NB_CONCENTRIC_CIRCLES);
                     }
                     Pattern::ParallelStripes => {
-                        nb_pattern = p.nb_parallel_stripes.unwrap_or(NB_PARALLEL_STRIPES);
-                        var_stripes = p.var_parallel_stripes.unwrap_or(VAR_PARALLEL_STRIPES);
+                        nb_pattern = draws
+                            .get(
+                                rng,
+                                "data.patterns.nb_parallel_stripes",
+                                &p.nb_parallel_stripes,
+                            )
+                            .unwrap_or(NB_PARALLEL_STRIPES);
+                        var_stripes = draws
+                            .get(
+                                rng,
+                                "data.patterns.var_parallel_stripes",
+                                &p.var_parallel_stripes,
+                            )
+                            .unwrap_or(VAR_PARALLEL_STRIPES);
                     }
                     Pattern::CrossedStripes => {
-                        nb_pattern = p.nb_crossed_stripes.unwrap_or(NB_CROSSED_STRIPES);
-                        var_stripes = p.var_crossed_stripes.unwrap_or(VAR_CROSSED_STRIPES);
+                        nb_pattern = draws
+                            .get(
+                                rng,
+                                "data.patterns.nb_crossed_stripes",
+                                &p.nb_crossed_stripes,
+                            )
+                            .unwrap_or(NB_CROSSED_STRIPES);
+                        var_stripes = draws
+                            .get(
+                                rng,
+                                "data.patterns.var_crossed_stripes",
+                                &p.var_crossed_stripes,
+                            )
+                            .unwrap_or(VAR_CROSSED_STRIPES);
                     }
                     Pattern::ParallelWaves => {
-                        nb_pattern = p.nb_parallel_waves.unwrap_or(NB_PARALLEL_WAVES);
-                        width_pattern = p.width_wave.unwrap_or(WIDTH_WAVE);
+                        nb_pattern = draws
+                            .get(rng, "data.patterns.nb_parallel_waves", &p.nb_parallel_waves)
+                            .unwrap_or(NB_PARALLEL_WAVES);
+                        width_pattern = draws
+                            .get(rng, "data.patterns.width_wave", &p.width_wave)
+                            .unwrap_or(WIDTH_WAVE);
                     }
                     Pattern::ParallelSawteeth => {
-                        nb_pattern = p.nb_parallel_sawteeth.unwrap_or(NB_PARALLEL_SAWTEETH);
-                        width_pattern = p.width_sawtooth.unwrap_or(WIDTH_SAWTOOTH);
+                        nb_pattern = draws
+                            .get(
+                                rng,
+                                "data.patterns.nb_parallel_sawteeth",
+                                &p.nb_parallel_sawteeth,
+                            )
+                            .unwrap_or(NB_PARALLEL_SAWTEETH);
+                        width_pattern = draws
+                            .get(rng, "data.patterns.width_sawtooth", &p.width_sawtooth)
+                            .unwrap_or(WIDTH_SAWTOOTH);
                     }
                 }
             } else {
@@ -377,25 +729,23 @@ impl MetaConfig {
                     }
                 }
             }
-            if verbose.details {
-                println!(
-                    "Number of patterns: {}
+            diag.details(format_args!(
+                "Number of patterns: {}
 Variability of stripes orientation: {}
 Width of pattern: {}",
-                    nb_pattern, var_stripes, width_pattern
-                );
-            }
+                nb_pattern, var_stripes, width_pattern
+            ));
             (nb_pattern, var_stripes, width_pattern, tightness_spiral)
         };
 
         if themes.is_empty() {
-            if verbose.warn {
-                println!("No themes available. Populating with random theme");
-            }
+            diag.warn(format_args!(
+                "No themes available. Populating with random theme"
+            ));
             if colors.is_empty() {
-                if verbose.warn {
-                    println!("No colors available. Populating with random color");
-                }
+                diag.warn(format_args!(
+                    "No colors available. Populating with random color"
+                ));
                 themes.insert(
                     String::from("-default-"),
                     Chooser::new(vec![(
@@ -409,7 +759,7 @@ Width of pattern: {}",
                     Chooser::new(vec![(
                         ThemeItem(
                             *colors
-                                .get(*colors.keys().collect::<Vec<_>>().choose(rng).unwrap())
+                                .get(*sorted_keys(&colors).choose(rng).unwrap())
                                 .unwrap(),
                             None,
                             None,
@@ -429,13 +779,48 @@ Width of pattern: {}",
             }) = self.data
             {
                 match tiling {
-                    Tiling::Hexagons => (t.size_hex.unwrap_or(size), 0),
-                    Tiling::Triangles => (t.size_tri.unwrap_or(size), 0),
-                    Tiling::HexagonsAndTriangles => (t.size_hex_and_tri.unwrap_or(size), 0),
-                    Tiling::SquaresAndTriangles => (t.size_squ_and_tri.unwrap_or(size), 0),
-                    Tiling::Rhombus => (t.size_rho.unwrap_or(size), 0),
-                    Tiling::Pentagons(_) => (t.size_pen.unwrap_or(size), 0),
-                    Tiling::Delaunay => (0.0, t.nb_delaunay.unwrap_or(NB_DELAUNAY)),
+                    Tiling::Hexagons => (
+                        draws
+                            .get(rng, "data.tilings.size_hex", &t.size_hex)
+                            .unwrap_or(size),
+                        0,
+                    ),
+                    Tiling::Triangles => (
+                        draws
+                            .get(rng, "data.tilings.size_tri", &t.size_tri)
+                            .unwrap_or(size),
+                        0,
+                    ),
+                    Tiling::HexagonsAndTriangles => (
+                        draws
+                            .get(rng, "data.tilings.size_hex_and_tri", &t.size_hex_and_tri)
+                            .unwrap_or(size),
+                        0,
+                    ),
+                    Tiling::SquaresAndTriangles => (
+                        draws
+                            .get(rng, "data.tilings.size_squ_and_tri", &t.size_squ_and_tri)
+                            .unwrap_or(size),
+                        0,
+                    ),
+                    Tiling::Rhombus => (
+                        draws
+                            .get(rng, "data.tilings.size_rho", &t.size_rho)
+                            .unwrap_or(size),
+                        0,
+                    ),
+                    Tiling::Pentagons(_) => (
+                        draws
+                            .get(rng, "data.tilings.size_pen", &t.size_pen)
+                            .unwrap_or(size),
+                        0,
+                    ),
+                    Tiling::Delaunay => (
+                        0.0,
+                        draws
+                            .get(rng, "data.tilings.nb_delaunay", &t.nb_delaunay)
+                            .unwrap_or(NB_DELAUNAY),
+                    ),
                 }
             } else {
                 match tiling {
@@ -449,13 +834,11 @@ Width of pattern: {}",
                 }
             }
         };
-        if verbose.details {
-            println!(
-                "Tiling size: {}
+        diag.details(format_args!(
+            "Tiling size: {}
 Delaunay triangles count: {}",
-                size_tiling, nb_delaunay
-            );
-        }
+            size_tiling, nb_delaunay
+        ));
         let (line_width, line_color_default) = {
             if let Some(lines) = self.lines {
                 lines.get_settings(tiling, &colors)
@@ -463,32 +846,86 @@ Delaunay triangles count: {}",
                 (LINE_WIDTH, LINE_COLOR)
             }
         };
-        if verbose.details {
-            println!(
-                "Line width: {}
+        diag.details(format_args!(
+            "Line width: {}
 Line color: {}",
-                line_width, line_color_default
-            );
+            line_width, line_color_default
+        ));
+
+        let mut theme = themes
+            .get(&theme)
+            .unwrap_or_else(|| {
+                themes
+                    .get(*sorted_keys(&themes).choose(rng).unwrap())
+                    .unwrap()
+            })
+            .clone();
+        if let Some(Fade {
+            from,
+            elapsed,
+            length,
+        }) = fade
+        {
+            match themes.get(&from) {
+                Some(previous) => {
+                    diag.info(format_args!(
+                        "Transition from theme '{}', {} of {} minutes elapsed",
+                        from, elapsed, length
+                    ));
+                    theme = previous.blend(&theme, elapsed, length - elapsed);
+                }
+                None => diag.warn(format_args!(
+                    "Cannot transition from undefined theme '{}'",
+                    from
+                )),
+            }
+        }
+
+        let precision = self
+            .global
+            .as_ref()
+            .and_then(|g| draws.get(rng, "global.precision", &g.precision))
+            .map(|p| {
+                if p > MAX_PRECISION {
+                    diag.warn(format_args!(
+                        "global.precision must be at most {}",
+                        MAX_PRECISION
+                    ));
+                }
+                p.min(MAX_PRECISION)
+            });
+        for (key, value) in &draws.0 {
+            diag.info(format_args!("Sampled {} = {}", key, value));
         }
 
         SceneCfg {
+            sampled: draws.0,
             deviation,
             distance,
-            theme: themes
-                .get(&theme)
-                .unwrap_or_else(|| {
-                    themes
-                        .get(*themes.keys().collect::<Vec<_>>().choose(rng).unwrap())
-                        .unwrap()
-                })
-                .clone(),
+            theme,
             frame: Frame {
                 x: 0,
                 y: 0,
                 w: width,
                 h: height,
             },
+            resolution,
+            clip: self.global.as_ref().and_then(|g| g.clip).unwrap_or(false),
+            precision,
+            compact: self
+                .global
+                .as_ref()
+                .and_then(|g| g.compact)
+                .unwrap_or(false),
+            recolorable: self
+                .global
+                .as_ref()
+                .and_then(|g| g.recolorable)
+                .unwrap_or(false),
             tiling,
+            rotation: rng.gen_range(0, 360),
+            sdiag_rhombus: (rng.gen::<f64>() * 0.6 + 0.4) * size_tiling,
+            seed_delaunay: rng.gen(),
             line_width,
             line_color: color_from_value(&Value::String(line_color_override), &colors)
                 .unwrap_or_else(|_| {
@@ -506,8 +943,17 @@ Line color: {}",
     }
 }
 
+/// Keys of a map in a reproducible order, so that choosing among them
+/// only depends on the state of the random number generator
+fn sorted_keys<T>(map: &HashMap<String, T>) -> Vec<&String> {
+    let mut keys = map.keys().collect::<Vec<_>>();
+    keys.sort();
+    keys
+}
+
 /// Parse a color code: decimal (0-255) or hex (00-FF)
-fn color_from_value(val: &Value, dict: &HashMap<String, Color>) -> Result<Color, String> {
+pub(crate) fn color_from_value(val: &Value, dict: &HashMap<String, Color>) -> Result<Color> {
+    let invalid = || Error::InvalidColor(val.to_string());
     match val {
         Value::String(s) => {
             if let Some(color) = dict.get(s.as_str()) {
@@ -519,51 +965,30 @@ fn color_from_value(val: &Value, dict: &HashMap<String, Color>) -> Result<Color,
                 let b = usize::from_str_radix(&s[5..7], 16);
                 match (r, g, b) {
                     (Ok(r), Ok(g), Ok(b)) => Ok(Color(r, g, b)),
-                    _ => Err(format!(
-                        "{:?} is not a valid color format.\nUse [0, 0, 255] or \"#0000FF\"",
-                        s
-                    )),
+                    _ => Err(invalid()),
                 }
             } else {
-                Err(format!(
-                    "{:?} is not a valid color format.\nUse [0, 0, 255] or \"#0000FF\"",
-                    s
-                ))
+                Err(invalid())
             }
         }
-        Value::Array(arr) => {
-            if arr.len() != 3 {
-                return Err(format!(
-                    "{:?} is not a valid color format.\nUse [0, 0, 255] or \"#0000FF\"",
-                    val
-                ));
+        Value::Array(arr) => match &arr[..] {
+            [Value::Integer(r), Value::Integer(g), Value::Integer(b)] => {
+                Ok(Color(*r as usize, *g as usize, *b as usize))
             }
-            match &arr[0..3] {
-                [Value::Integer(r), Value::Integer(g), Value::Integer(b)] => {
-                    Ok(Color(*r as usize, *g as usize, *b as usize))
-                }
-                _ => Err(format!(
-                    "{:?} is not a valid color format.\nUse [0, 0, 255] or \"#0000FF\"",
-                    arr
-                )),
-            }
-        }
-        _ => Err(format!(
-            "{:?} is not a valid color format.\nUse [0, 0, 255] or \"#0000FF\"",
-            val
-        )),
+            _ => Err(invalid()),
+        },
+        _ => Err(invalid()),
     }
 }
 
 fn theme_item_from_value(
     val: &Value,
     dict: &HashMap<String, Color>,
-    verbose: Verbosity,
+    diag: &dyn Diagnostics,
 ) -> (ThemeItem, usize) {
     let warn_invalid = |x| {
-        if verbose.warn {
-            println!(
-                "Invalid item ({:?})
+        diag.warn(format_args!(
+            "Invalid item ({:?})
 Provide one of:
 - a named color (\"blue\")
 - a hex code (\"#0000FF\")
@@ -572,9 +997,8 @@ Provide one of:
 - any of the above along with a distance override (\"<COLOR> !DISTANCE\")
 - a map item ({{ color, variability, weight, distance }})
 Note that the format [<R>, <G>, <B>] is not accepted here",
-                x
-            );
-        }
+            x
+        ));
     };
     match val {
         Value::String(s) => {
@@ -588,30 +1012,27 @@ Note that the format [<R>, <G>, <B>] is not accepted here",
                 }
                 if &item[0..1] == "x" {
                     wht = item[1..].parse().unwrap_or_else(|_| {
-                        if verbose.warn {
-                            println!("Not a valid ponderation: {}", &item[1..]);
-                        }
+                        diag.warn(format_args!("Not a valid ponderation: {}", &item[1..]));
                         BASE_WEIGHT
                     });
                 } else if &item[0..1] == "~" {
                     var = item[1..].parse::<usize>().map(Some).unwrap_or_else(|_| {
-                        if verbose.warn {
-                            println!("Not a valid variability: {}", &item[1..]);
-                        }
+                        diag.warn(format_args!("Not a valid variability: {}", &item[1..]));
                         None
                     });
                 } else if &item[0..1] == "!" {
-                    dist = item[1..].parse::<usize>().map(Some).unwrap_or_else(|_| {
-                        if verbose.warn {
-                            println!("Not a valid distance: {}", &item[1..]);
+                    dist = match item[1..].parse::<usize>() {
+                        Ok(d) if d <= 100 => Some(d),
+                        _ => {
+                            diag.warn(format_args!("Not a valid distance: {}", &item[1..]));
+                            None
                         }
-                        None
-                    });
+                    };
                 } else {
                     match color_from_value(&Value::String(item.to_string()), dict) {
                         Ok(c) => color = c,
                         Err(e) => {
-                            warn_invalid(e);
+                            warn_invalid(e.to_string());
                         }
                     }
                 }
@@ -623,7 +1044,7 @@ Note that the format [<R>, <G>, <B>] is not accepted here",
                 Some(val) => match color_from_value(val, dict) {
                     Ok(c) => c,
                     Err(e) => {
-                        warn_invalid(e);
+                        warn_invalid(e.to_string());
                         Color(0, 0, 0)
                     }
                 },
@@ -633,21 +1054,17 @@ Note that the format [<R>, <G>, <B>] is not accepted here",
                 Some(Value::Integer(v)) => Some(*v),
                 Some(Value::Float(v)) => Some(v.round() as i64),
                 Some(x) => {
-                    if verbose.warn {
-                        println!("Not a valid variability: {:?}", x);
-                    }
+                    diag.warn(format_args!("Not a valid variability: {:?}", x));
                     None
                 }
                 None => None,
             })
             .map(|n| n.max(0) as usize);
             let dist = (match map.get("distance") {
-                Some(Value::Integer(d)) => Some(*d),
-                Some(Value::Float(d)) => Some(d.round() as i64),
+                Some(Value::Integer(d)) if (0..=100).contains(d) => Some(*d),
+                Some(Value::Float(d)) if (0. ..=100.).contains(d) => Some(d.round() as i64),
                 Some(x) => {
-                    if verbose.warn {
-                        println!("Not a valid distance: {:?}", x);
-                    }
+                    diag.warn(format_args!("Not a valid distance: {:?}", x));
                     None
                 }
                 None => None,
@@ -657,9 +1074,7 @@ Note that the format [<R>, <G>, <B>] is not accepted here",
                 Some(Value::Integer(w)) => *w.max(&0) as usize,
                 Some(Value::Float(w)) => w.round().max(0.0) as usize,
                 Some(x) => {
-                    if verbose.warn {
-                        println!("Not a valid weight: {:?}", x);
-                    }
+                    diag.warn(format_args!("Not a valid weight: {:?}", x));
                     BASE_WEIGHT
                 }
                 None => BASE_WEIGHT,
@@ -673,10 +1088,8 @@ Note that the format [<R>, <G>, <B>] is not accepted here",
                             let color = tbl
                                 .get("color")
                                 .map(|v| {
-                                    color_from_value(v, &dict).unwrap_or_else(|_| {
-                                        if verbose.warn {
-                                            println!("Invalid color: {:?}", v)
-                                        }
+                                    color_from_value(v, dict).unwrap_or_else(|_| {
+                                        diag.warn(format_args!("Invalid color: {:?}", v));
                                         Color(0, 0, 0)
                                     })
                                 })
@@ -686,32 +1099,17 @@ Note that the format [<R>, <G>, <B>] is not accepted here",
                                 Some(Value::Float(f)) => *f,
                                 Some(Value::Integer(n)) => *n as f64,
                                 Some(v) => {
-                                    if verbose.warn {
-                                        println!("Not a valid likeliness: {:?}", v);
-                                    }
+                                    diag.warn(format_args!("Not a valid likeliness: {:?}", v));
                                     1.0
                                 }
                             };
                             let variability = match tbl.get("variability") {
                                 None => 0,
-                                Some(Value::Integer(n)) => {
-                                    if *n > 0 {
-                                        *n as usize
-                                    } else {
-                                        0
-                                    }
-                                }
-                                Some(Value::Float(f)) => {
-                                    if *f > 0. {
-                                        f.round() as usize
-                                    } else {
-                                        0
-                                    }
-                                }
+                                Some(Value::Integer(n)) if *n > 0 => *n as usize,
+                                Some(Value::Float(f)) if *f > 0. => f.round() as usize,
+                                Some(Value::Integer(_)) | Some(Value::Float(_)) => 0,
                                 Some(v) => {
-                                    if verbose.warn {
-                                        println!("Not a valid variability: {:?}", v);
-                                    }
+                                    diag.warn(format_args!("Not a valid variability: {:?}", v));
                                     0
                                 }
                             };
@@ -725,9 +1123,7 @@ Note that the format [<R>, <G>, <B>] is not accepted here",
                     salt
                 }
                 _ => {
-                    if verbose.warn {
-                        println!("Invalid Salt. Expected an array.");
-                    }
+                    diag.warn(format_args!("Invalid Salt. Expected an array."));
                     Salt::none()
                 }
             };
@@ -748,8 +1144,8 @@ fn theme_from_value(
     v: &Value,
     colors: &ColorList,
     themes: &ThemeList,
-    verbose: Verbosity,
-) -> Result<Chooser<ThemeItem>, String> {
+    diag: &dyn Diagnostics,
+) -> Result<Chooser<ThemeItem>> {
     let mut items = Vec::new();
     if let Value::String(s) = v {
         if let Some(th) = themes.get(s) {
@@ -765,25 +1161,23 @@ fn theme_from_value(
                         continue;
                     }
                 }
-                let (item, weight) = theme_item_from_value(x, colors, verbose);
+                let (item, weight) = theme_item_from_value(x, colors, diag);
                 items.push((item, weight));
             }
             Ok(Chooser::new(items))
         }
-        _ => Err(format!(
-            "{:?} is not a valid theme.
-Provide a theme item or an array of theme items",
-            v
-        )),
+        _ => Err(Error::InvalidTheme(v.to_string())),
     }
 }
 
 fn shapes_from_value(
     val: &Value,
-    shapes: &HashMap<String, (Chooser<Pattern>, Chooser<Tiling>)>,
+    shapes: &ShapeList,
+    diag: &dyn Diagnostics,
 ) -> (Chooser<Pattern>, Chooser<Tiling>) {
     let mut tilings = Chooser::new(vec![]);
     let mut patterns = Chooser::new(vec![]);
+    let warn = |e: Error| diag.warn(format_args!("{}", e));
     match val {
         Value::Array(arr) => {
             for x in arr {
@@ -794,119 +1188,272 @@ fn shapes_from_value(
                             tilings.append(t.extract());
                             patterns.append(p.extract());
                         } else {
-                            add_shape(&s[..], BASE_WEIGHT, &mut tilings, &mut patterns);
+                            add_shape(s, BASE_WEIGHT, &mut tilings, &mut patterns)
+                                .unwrap_or_else(warn);
                         }
                     }
-                    Value::Array(a) => {
-                        if a.len() == 2 {
-                            match &a[..] {
-                                [Value::String(s), Value::Integer(w)] if *w > 0 => {
-                                    add_shape(&s[..], *w as usize, &mut tilings, &mut patterns)
-                                }
-                                _ => println!("{} is not a valid shape.", x),
-                            }
-                        } else {
-                            println!("{} is not a valid shape.", x);
+                    Value::Array(a) => match &a[..] {
+                        [Value::String(s), Value::Integer(w)] if *w > 0 => {
+                            add_shape(s, *w as usize, &mut tilings, &mut patterns)
+                                .unwrap_or_else(warn)
                         }
-                    }
-                    _ => println!("{} is not a valid shape.", x),
+                        _ => warn(Error::InvalidShape(x.to_string())),
+                    },
+                    _ => warn(Error::InvalidShape(x.to_string())),
                 }
             }
         }
-        _ => println!("{} is not an array of shapes.", val),
+        _ => diag.warn(format_args!("{} is not an array of shapes", val)),
     }
     (patterns, tilings)
 }
 
-/// Read shape from one of its names
-fn add_shape(s: &str, w: usize, tilings: &mut Chooser<Tiling>, patterns: &mut Chooser<Pattern>) {
-    match &s[..] {
-        "H" | "hex." | "hexagons" => tilings.push(Tiling::Hexagons, w),
-        "T" | "tri." | "triangles" => tilings.push(Tiling::Triangles, w),
-        "H&T" | "hex.&tri." | "hexagons&squares" => tilings.push(Tiling::HexagonsAndTriangles, w),
-        "S&T" | "squ.&tri." | "squares&triangles" => tilings.push(Tiling::SquaresAndTriangles, w),
-        "R" | "rho." | "rhombus" => tilings.push(Tiling::Rhombus, w),
-        "D" | "del." | "delaunay" => tilings.push(Tiling::Delaunay, w),
-        "P" | "pen." | "pentagons" => tilings.push(Tiling::Pentagons(0), w),
-        "P1" | "pen.1" | "pentagons-1" => tilings.push(Tiling::Pentagons(1), w),
-        "P2" | "pen.2" | "pentagons-2" => tilings.push(Tiling::Pentagons(2), w),
-        "P3" | "pen.3" | "pentagons-3" => tilings.push(Tiling::Pentagons(3), w),
-        "P4" | "pen.4" | "pentagons-4" => tilings.push(Tiling::Pentagons(4), w),
-        "P5" | "pen.5" | "pentagons-5" => tilings.push(Tiling::Pentagons(5), w),
-        "P6" | "pen.6" | "pentagons-6" => tilings.push(Tiling::Pentagons(6), w),
-        "FC" | "f-cir." | "free-circles" => patterns.push(Pattern::FreeCircles, w),
-        "FT" | "f-tri." | "free-triangles" => patterns.push(Pattern::FreeTriangles, w),
-        "FR" | "f-str." | "free-stripes" => patterns.push(Pattern::FreeStripes, w),
-        "FP" | "f-spi." | "free-spirals" => patterns.push(Pattern::FreeSpirals, w),
-        "CC" | "c-cir." | "concentric-circles" => patterns.push(Pattern::ConcentricCircles, w),
-        "PS" | "p-str." | "parallel-stripes" => patterns.push(Pattern::ParallelStripes, w),
-        "CS" | "c-str." | "crossed-stripes" => patterns.push(Pattern::CrossedStripes, w),
-        "PW" | "p-wav." | "parallel-waves" => patterns.push(Pattern::ParallelWaves, w),
-        "PT" | "p-saw." | "parallel-sawteeth" => patterns.push(Pattern::ParallelSawteeth, w),
-        _ => println!("{} is not recognized as a shape", s),
-    }
-}
-
-fn choose_theme_shapes(
-    rng: &mut ThreadRng,
+/// A single tiling or pattern
+#[derive(Debug, Clone, Copy)]
+pub enum Shape {
+    Tiling(Tiling),
+    Pattern(Pattern),
+}
+
+/// All accepted names of each shape: short, abbreviated and full
+#[rustfmt::skip]
+pub const SHAPE_NAMES: &[([&str; 3], Shape)] = &[
+    (["H", "hex.", "hexagons"], Shape::Tiling(Tiling::Hexagons)),
+    (["T", "tri.", "triangles"], Shape::Tiling(Tiling::Triangles)),
+    (["H&T", "hex.&tri.", "hexagons&squares"], Shape::Tiling(Tiling::HexagonsAndTriangles)),
+    (["S&T", "squ.&tri.", "squares&triangles"], Shape::Tiling(Tiling::SquaresAndTriangles)),
+    (["R", "rho.", "rhombus"], Shape::Tiling(Tiling::Rhombus)),
+    (["D", "del.", "delaunay"], Shape::Tiling(Tiling::Delaunay)),
+    (["P", "pen.", "pentagons"], Shape::Tiling(Tiling::Pentagons(0))),
+    (["P1", "pen.1", "pentagons-1"], Shape::Tiling(Tiling::Pentagons(1))),
+    (["P2", "pen.2", "pentagons-2"], Shape::Tiling(Tiling::Pentagons(2))),
+    (["P3", "pen.3", "pentagons-3"], Shape::Tiling(Tiling::Pentagons(3))),
+    (["P4", "pen.4", "pentagons-4"], Shape::Tiling(Tiling::Pentagons(4))),
+    (["P5", "pen.5", "pentagons-5"], Shape::Tiling(Tiling::Pentagons(5))),
+    (["P6", "pen.6", "pentagons-6"], Shape::Tiling(Tiling::Pentagons(6))),
+    (["FC", "f-cir.", "free-circles"], Shape::Pattern(Pattern::FreeCircles)),
+    (["FT", "f-tri.", "free-triangles"], Shape::Pattern(Pattern::FreeTriangles)),
+    (["FR", "f-str.", "free-stripes"], Shape::Pattern(Pattern::FreeStripes)),
+    (["FP", "f-spi.", "free-spirals"], Shape::Pattern(Pattern::FreeSpirals)),
+    (["CC", "c-cir.", "concentric-circles"], Shape::Pattern(Pattern::ConcentricCircles)),
+    (["PS", "p-str.", "parallel-stripes"], Shape::Pattern(Pattern::ParallelStripes)),
+    (["CS", "c-str.", "crossed-stripes"], Shape::Pattern(Pattern::CrossedStripes)),
+    (["PW", "p-wav.", "parallel-waves"], Shape::Pattern(Pattern::ParallelWaves)),
+    (["PT", "p-saw.", "parallel-sawteeth"], Shape::Pattern(Pattern::ParallelSawteeth)),
+];
+
+impl Shape {
+    /// Read shape from one of its names
+    pub fn from_name(s: &str) -> Option<Self> {
+        SHAPE_NAMES
+            .iter()
+            .find(|(names, _)| names.contains(&s))
+            .map(|(_, shape)| *shape)
+    }
+}
+
+/// Add shape to the matching chooser
+fn add_shape(
+    s: &str,
+    w: usize,
+    tilings: &mut Chooser<Tiling>,
+    patterns: &mut Chooser<Pattern>,
+) -> Result<()> {
+    match Shape::from_name(s) {
+        Some(Shape::Tiling(t)) => tilings.push(t, w),
+        Some(Shape::Pattern(p)) => patterns.push(p, w),
+        None => return Err(Error::InvalidShape(format!("{:?}", s))),
+    }
+    Ok(())
+}
+
+/// Theme that a transition starts from
+struct Fade {
+    from: String,
+    /// Minutes since the start of the transition
+    elapsed: usize,
+    /// Length of the transition in minutes
+    length: usize,
+}
+
+/// Settings asked for by the entry that applies, empty if there is none
+#[derive(Default)]
+struct Chosen {
+    theme: String,
+    shapes: String,
+    line_color: String,
+    distance: Option<usize>,
+    global: Option<ConfigGlobal>,
+    lines: Option<ConfigLines>,
+    data: Option<ConfigData>,
+    fade: Option<Fade>,
+}
+
+/// Entries that apply at `now`, weighted
+fn valid_entries<'e>(
+    entries: &'e [ConfigEntry],
+    now: Moment,
+    location: Option<Location>,
+    diag: &dyn Diagnostics,
+) -> Chooser<&'e ConfigEntry> {
+    let mut valid = Chooser::new(vec![]);
+    for e in entries {
+        if e.elapsed(now, location, diag).is_some() && e.matches_date(now.date, diag) {
+            valid.push(e, e.weight.unwrap_or(BASE_WEIGHT));
+        }
+    }
+    valid
+}
+
+/// Pick one of `names` according to their weights, empty if there are none
+fn choose_name<R: Rng>(rng: &mut R, names: &Option<Vec<WeightedName>>) -> String {
+    names
+        .as_ref()
+        .and_then(|v| Chooser::new(v.iter().map(WeightedName::pair).collect()).choose(rng))
+        .map(String::from)
+        .unwrap_or_else(|| String::from(""))
+}
+
+fn choose_theme_shapes<R: Rng>(
+    rng: &mut R,
     entry: &Option<Vec<ConfigEntry>>,
-    time: usize,
-) -> (String, String, String) {
-    match entry {
-        None => (String::from(""), String::from(""), String::from("")),
-        Some(v) => {
-            let mut valid = Chooser::new(vec![]);
-            for e in v {
-                let markers = e
-                    .span
-                    .as_ref()
-                    .unwrap_or(&"-".to_string())
-                    .split('-')
-                    .map(String::from)
-                    .collect::<Vec<_>>();
-                let start = markers
-                    .get(0)
-                    .as_ref()
-                    .unwrap_or(&&String::from("0"))
-                    .parse::<usize>()
-                    .unwrap_or(0);
-                let end = markers
-                    .get(1)
-                    .as_ref()
-                    .unwrap_or(&&String::from("2400"))
-                    .parse::<usize>()
-                    .unwrap_or(2400);
-                if start <= time && time <= end {
-                    valid.push(e, e.distance.unwrap_or(BASE_WEIGHT));
-                }
+    now: Moment,
+    location: Option<Location>,
+    diag: &dyn Diagnostics,
+) -> Chosen {
+    let entries = match entry {
+        None => return Chosen::default(),
+        Some(v) => v,
+    };
+    let chosen_entry = match valid_entries(entries, now, location, diag).choose(rng) {
+        None => return Chosen::default(),
+        Some(e) => e,
+    };
+    let mut chosen = Chosen {
+        theme: choose_name(rng, &chosen_entry.themes),
+        shapes: choose_name(rng, &chosen_entry.shapes),
+        line_color: chosen_entry
+            .line_color
+            .as_ref()
+            .map(|s| s.to_string())
+            .unwrap_or_else(|| String::from("")),
+        distance: chosen_entry.distance,
+        global: chosen_entry.global.clone(),
+        lines: chosen_entry.lines.clone(),
+        data: chosen_entry.data.clone(),
+        fade: None,
+    };
+    // Blend with the theme of the entry that applied just before the span started
+    let length = chosen_entry.transition.unwrap_or(0);
+    if length == 0 {
+        return chosen;
+    }
+    match chosen_entry.elapsed(now, location, diag) {
+        Some(Some(elapsed)) if elapsed < length => {
+            let before = now.minutes_before(elapsed + 1);
+            let previous = valid_entries(entries, before, location, diag).extract();
+            if let Some(previous) = previous_entry(previous, chosen_entry) {
+                chosen.fade = Some(Fade {
+                    from: choose_name(rng, &previous.themes),
+                    elapsed,
+                    length,
+                });
             }
-            match valid.choose(rng) {
-                None => (String::from(""), String::from(""), String::from("")),
-                Some(chosen_entry) => {
-                    let chosen_theme = match &chosen_entry.themes {
-                        None => String::from(""),
-                        Some(th) => th
-                            .choose(rng)
-                            .map(String::from)
-                            .unwrap_or_else(|| String::from("")),
-                    };
-                    let chosen_shapes = match &chosen_entry.shapes {
-                        None => String::from(""),
-                        Some(sh) => sh
-                            .choose(rng)
-                            .map(String::from)
-                            .unwrap_or_else(|| String::from("")),
-                    };
-                    let line_color = chosen_entry
-                        .line_color
-                        .as_ref()
-                        .map(|s| s.to_string())
-                        .unwrap_or_else(|| String::from(""));
-                    (chosen_theme, chosen_shapes, line_color)
+        }
+        _ => (),
+    }
+    chosen
+}
+
+/// Entry that a transition to `chosen` starts from: the heaviest of `candidates`
+/// other than `chosen`, the first one in the configuration if several are as heavy.
+/// Whether there is a transition does not depend on random choices.
+fn previous_entry<'e>(
+    candidates: Vec<(&'e ConfigEntry, usize)>,
+    chosen: &ConfigEntry,
+) -> Option<&'e ConfigEntry> {
+    candidates
+        .into_iter()
+        .filter(|(e, _)| !std::ptr::eq(*e, chosen))
+        .fold(None, |best, (e, w)| match best {
+            Some((_, bw)) if bw >= w => best,
+            _ => Some((e, w)),
+        })
+        .map(|(e, _)| e)
+}
+
+impl ConfigEntry {
+    /// None if the entry does not apply at the time of `now`, otherwise the number of
+    /// minutes since the start of the most recent span that contains it (None if no span is given).
+    /// Both `span` and `sun` give spans, invalid ones never match.
+    fn elapsed(
+        &self,
+        now: Moment,
+        location: Option<Location>,
+        diag: &dyn Diagnostics,
+    ) -> Option<Option<usize>> {
+        if self.span.is_none() && self.sun.is_none() {
+            return Some(None);
+        }
+        let clock = self
+            .span
+            .iter()
+            .flat_map(Spans::list)
+            .filter_map(|s| match parse_span(s) {
+                Ok(span) => Some(span),
+                Err(err) => {
+                    diag.warn(format_args!("entry.span: {}", err));
+                    None
+                }
+            });
+        // Sun positions move the span a little every day
+        let sun = self.sun.iter().flat_map(Spans::list).filter_map(|s| {
+            match (parse_sun_span(s), location) {
+                (Ok(span), Some(loc)) => span.on(&loc, now.date, now.utc_offset),
+                (Ok(_), None) => {
+                    diag.warn(format_args!(
+                        "entry.sun: {:?} requires a [location] with a latitude and a longitude",
+                        s
+                    ));
+                    None
+                }
+                (Err(err), _) => {
+                    diag.warn(format_args!("entry.sun: {}", err));
+                    None
                 }
             }
+        });
+        clock
+            .chain(sun)
+            .filter(|span| span.contains(now.time as u32))
+            .map(|span| minutes_between(span.start as usize, now.time))
+            .min()
+            .map(Some)
+    }
+
+    fn selector(&self, sel: Selector) -> Option<&Vec<String>> {
+        match sel {
+            Selector::Weekdays => self.weekdays.as_ref(),
+            Selector::Months => self.months.as_ref(),
+            Selector::Dates => self.dates.as_ref(),
+            Selector::Seasons => self.seasons.as_ref(),
         }
     }
+
+    /// Every date selector that is present has an item that accepts `date`.
+    /// Invalid items never match.
+    fn matches_date(&self, date: NaiveDate, diag: &dyn Diagnostics) -> bool {
+        SELECTORS.iter().all(|&sel| match self.selector(sel) {
+            None => true,
+            Some(items) => items.iter().any(|item| match sel.parse(item) {
+                Ok(range) => range.contains(sel.value(date)),
+                Err(e) => {
+                    diag.warn(format_args!("entry.{}: {}", sel.key(), e));
+                    false
+                }
+            }),
+        })
+    }
 }
 
 impl ConfigLines {
@@ -961,3 +1508,354 @@ const TIGHTNESS_SPIRAL: f64 = 0.5;
 const NB_DELAUNAY: usize = 1000;
 const LINE_WIDTH: f64 = 1.0;
 const LINE_COLOR: Color = Color(0, 0, 0);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::{rngs::StdRng, SeedableRng};
+
+    fn samples<T: Number>(param: &Param<T>) -> Vec<T> {
+        let mut rng = StdRng::seed_from_u64(0);
+        (0..1000).map(|_| param.sample(&mut rng).unwrap()).collect()
+    }
+
+    #[test]
+    fn fixed_is_constant() {
+        assert!(samples(&Param::Fixed(7_usize)).iter().all(|&x| x == 7));
+        assert!(samples(&Param::Fixed(0.5)).iter().all(|&x| x == 0.5));
+    }
+
+    #[test]
+    fn integer_range_is_closed() {
+        let drawn = samples(&Param::Range(3_usize, 5));
+        assert!(drawn.iter().all(|x| (3..=5).contains(x)));
+        assert!(drawn.contains(&3) && drawn.contains(&5));
+        // Bounds in the wrong order are swapped
+        assert!(samples(&Param::Range(5_usize, 3))
+            .iter()
+            .all(|x| (3..=5).contains(x)));
+    }
+
+    #[test]
+    fn float_range_excludes_upper_bound() {
+        assert!(samples(&Param::Range(1., 2.))
+            .iter()
+            .all(|&x| (1. ..2.).contains(&x)));
+        assert!(samples(&Param::Range(2., 1.))
+            .iter()
+            .all(|&x| (1. ..2.).contains(&x)));
+        assert!(samples(&Param::Range(1.5, 1.5)).iter().all(|&x| x == 1.5));
+    }
+
+    #[test]
+    fn weighted_follows_weights() {
+        let drawn = samples(&Param::Weighted(vec![(1_usize, 1), (2, 0), (3, 3)]));
+        assert!(!drawn.contains(&2));
+        let ones = drawn.iter().filter(|&&x| x == 1).count();
+        assert!((150..350).contains(&ones));
+        let empty: Param<usize> = Param::Weighted(Vec::new());
+        assert!(empty.sample(&mut StdRng::seed_from_u64(0)).is_none());
+    }
+
+    #[test]
+    fn only_draws_are_recorded() {
+        let mut rng = StdRng::seed_from_u64(0);
+        let mut draws = Draws::default();
+        assert_eq!(
+            draws.get(&mut rng, "width", &Some(Param::Fixed(10_usize))),
+            Some(10)
+        );
+        assert_eq!(draws.get::<usize, _>(&mut rng, "height", &None), None);
+        let size = draws.get(&mut rng, "size", &Some(Param::Range(1., 2.)));
+        assert_eq!(
+            draws.0,
+            vec![("size".to_string(), Value::Float(size.unwrap()))]
+        );
+    }
+
+    fn entry(src: &str) -> ConfigEntry {
+        toml::from_str(src).unwrap()
+    }
+
+    fn at(time: usize) -> Moment {
+        Moment {
+            date: NaiveDate::from_ymd_opt(2024, 1, 1).unwrap(),
+            time,
+            utc_offset: 0,
+        }
+    }
+
+    #[test]
+    fn lists_of_spans() {
+        let warnings = std::cell::Cell::new(0);
+        let diag = |_: Level, _: std::fmt::Arguments| warnings.set(warnings.get() + 1);
+        let e = entry("span = [\"0600-0900\", \"2100-0100\"]");
+        let elapsed = |time| e.elapsed(at(time), None, &diag);
+        assert_eq!(elapsed(700), Some(Some(60)));
+        assert_eq!(elapsed(2200), Some(Some(60)));
+        assert_eq!(elapsed(30), Some(Some(210)));
+        assert_eq!(elapsed(1200), None);
+        assert_eq!(elapsed(200), None);
+        assert_eq!(
+            entry("span = \"2100-0559\"").elapsed(at(300), None, &diag),
+            Some(Some(360))
+        );
+        // Without spans an entry always applies
+        assert_eq!(entry("").elapsed(at(1200), None, &diag), Some(None));
+        assert_eq!(warnings.get(), 0);
+        // Invalid spans never match
+        let e = entry("span = [\"0575-1200\", \"1100-1300\"]");
+        assert_eq!(e.elapsed(at(1000), None, &diag), None);
+        assert_eq!(e.elapsed(at(1200), None, &diag), Some(Some(60)));
+        assert_eq!(warnings.get(), 2);
+    }
+
+    const TRANSITION: &str = r##"
+[colors]
+old = "#FF0000"
+new = "#0000FF"
+[themes]
+old = ["old"]
+new = ["new"]
+other = ["old"]
+[[entry]]
+span = "0000-1159"
+themes = ["old"]
+weight = 30
+[[entry]]
+span = "0000-1159"
+themes = ["other"]
+weight = 10
+[[entry]]
+span = "1200-2359"
+themes = ["new"]
+transition = 60
+"##;
+
+    /// Share of the new theme in the theme of an image generated at `time`
+    fn share_of_new(time: usize, seed: u64) -> f64 {
+        let cfg = MetaConfig::parse(TRANSITION).unwrap().pick_cfg(
+            &mut StdRng::seed_from_u64(seed),
+            at(time),
+            &|_: Level, _: std::fmt::Arguments| (),
+        );
+        let (mut new, mut total) = (0, 0);
+        for (ThemeItem(color, ..), w) in cfg.theme.extract() {
+            total += w;
+            if color == Color(0, 0, 255) {
+                new += w;
+            } else {
+                assert_eq!(color, Color(255, 0, 0));
+            }
+        }
+        new as f64 / total as f64
+    }
+
+    #[test]
+    fn transitions_are_gradual() {
+        for seed in 0..10 {
+            assert_eq!(share_of_new(1200, seed), 0.);
+            assert_eq!(share_of_new(1215, seed), 0.25);
+            assert_eq!(share_of_new(1230, seed), 0.5);
+            assert_eq!(share_of_new(1245, seed), 0.75);
+            assert_eq!(share_of_new(1300, seed), 1.);
+            // The old theme is only a transition
+            assert_eq!(share_of_new(1100, seed), 0.);
+        }
+    }
+
+    #[test]
+    fn previous_entry_is_the_heaviest() {
+        let entries = toml::from_str::<MetaConfig>(TRANSITION)
+            .unwrap()
+            .entry
+            .unwrap();
+        let (old, other, new) = (&entries[0], &entries[1], &entries[2]);
+        let pick = |candidates: Vec<(&ConfigEntry, usize)>, chosen| {
+            previous_entry(candidates, chosen)
+                .map(|e| e.themes.as_ref().unwrap()[0].pair().0.to_string())
+        };
+        assert_eq!(
+            pick(vec![(other, 10), (old, 30), (new, 10)], new),
+            Some("old".to_string())
+        );
+        assert_eq!(
+            pick(vec![(old, 10), (other, 10)], new),
+            Some("old".to_string())
+        );
+        assert_eq!(
+            pick(vec![(other, 10), (old, 10)], new),
+            Some("other".to_string())
+        );
+        // Never the entry itself
+        assert_eq!(
+            pick(vec![(new, 50), (other, 10)], new),
+            Some("other".to_string())
+        );
+        assert_eq!(pick(vec![(new, 10)], new), None);
+    }
+
+    const OVERRIDES: &str = r##"
+[global]
+deviation = 20
+distance = 40
+width = 1000
+height = 600
+[lines]
+width = 0.5
+color = "#000000"
+[colors]
+red = "#FF0000"
+[themes]
+red = ["red"]
+[shapes]
+hex = ["hexagons", "free-circles"]
+[data.patterns]
+nb_free_circles = 5
+[data.tilings]
+size_hex = 12.0
+[[entry]]
+span = "0000-1159"
+themes = ["red"]
+shapes = ["hex"]
+[entry.global]
+width = 500
+[entry.lines]
+hex_width = 2.0
+[entry.data.patterns]
+nb_free_circles = 9
+[[entry]]
+span = "1200-2359"
+themes = ["red"]
+shapes = ["hex"]
+"##;
+
+    #[test]
+    fn entries_override_named_settings() {
+        let pick = |time| {
+            MetaConfig::parse(OVERRIDES).unwrap().pick_cfg(
+                &mut StdRng::seed_from_u64(0),
+                at(time),
+                &|_: Level, _: std::fmt::Arguments| (),
+            )
+        };
+        let cfg = pick(1000);
+        assert!(matches!(
+            (cfg.tiling, cfg.pattern),
+            (Tiling::Hexagons, Pattern::FreeCircles)
+        ));
+        assert_eq!((cfg.frame.w, cfg.frame.h), (500, 600));
+        assert_eq!((cfg.deviation, cfg.distance), (20, 40));
+        assert_eq!(cfg.line_width, 2.0);
+        assert_eq!(cfg.nb_pattern, 9);
+        assert_eq!(cfg.size_tiling, 12.0);
+        // The other entry sees the settings of the whole configuration
+        let cfg = pick(1300);
+        assert_eq!((cfg.frame.w, cfg.frame.h), (1000, 600));
+        assert_eq!((cfg.deviation, cfg.distance), (20, 40));
+        assert_eq!(cfg.line_width, 0.5);
+        assert_eq!(cfg.nb_pattern, 5);
+        assert_eq!(cfg.size_tiling, 12.0);
+    }
+
+    #[test]
+    fn weighted_names() {
+        let e = entry(r#"themes = ["a", ["b", 30]]"#);
+        let pairs = e.themes.as_ref().unwrap().iter().map(WeightedName::pair);
+        assert_eq!(pairs.collect::<Vec<_>>(), [("a", BASE_WEIGHT), ("b", 30)]);
+        for src in &[
+            r#"themes = [["b"]]"#,
+            r#"themes = [["b", -1]]"#,
+            r#"themes = [["b", 1, 2]]"#,
+            r#"themes = [[30, "b"]]"#,
+        ] {
+            assert!(toml::from_str::<ConfigEntry>(src).is_err(), "{}", src);
+        }
+        // 30 out of 40
+        let mut rng = StdRng::seed_from_u64(0);
+        let b = (0..4000)
+            .filter(|_| choose_name(&mut rng, &e.themes) == "b")
+            .count();
+        assert!((2800..3200).contains(&b), "{}", b);
+        assert_eq!(choose_name(&mut rng, &None), "");
+    }
+
+    #[test]
+    fn entry_weights() {
+        let entries = toml::from_str::<MetaConfig>(
+            r#"
+[[entry]]
+themes = ["light"]
+weight = 30
+[[entry]]
+themes = ["default"]
+[[entry]]
+themes = ["never"]
+weight = 0
+"#,
+        )
+        .unwrap()
+        .entry
+        .unwrap();
+        let diag = |_: Level, _: std::fmt::Arguments| ();
+        let valid = valid_entries(&entries, at(1200), None, &diag);
+        let weights = valid.extract().into_iter().map(|(_, w)| w);
+        // Entries of weight 0 are never chosen
+        assert_eq!(weights.collect::<Vec<_>>(), [30, BASE_WEIGHT]);
+        let mut rng = StdRng::seed_from_u64(0);
+        let mut counts = HashMap::new();
+        for _ in 0..4000 {
+            let e = valid.choose(&mut rng).unwrap();
+            *counts.entry(choose_name(&mut rng, &e.themes)).or_insert(0) += 1;
+        }
+        let light = counts["light"];
+        assert!((2800..3200).contains(&light), "{}", light);
+        assert!(!counts.contains_key("never"));
+    }
+
+    #[test]
+    fn entry_distance() {
+        let distance = |entry: &str| {
+            let warnings = std::cell::Cell::new(0);
+            let diag = |level: Level, _: std::fmt::Arguments| {
+                if level == Level::Warn {
+                    warnings.set(warnings.get() + 1)
+                }
+            };
+            let src = format!(
+                r##"
+[global]
+distance = 40
+[colors]
+a = "#FF0000"
+[themes]
+a = ["a"]
+[[entry]]
+themes = ["a"]
+{}
+"##,
+                entry
+            );
+            let cfg = MetaConfig::parse(&src).unwrap().pick_cfg(
+                &mut StdRng::seed_from_u64(0),
+                at(1200),
+                &diag,
+            );
+            (cfg.distance, warnings.get())
+        };
+        assert_eq!(distance(""), (40, 0));
+        assert_eq!(distance("distance = 60"), (60, 0));
+        assert_eq!(distance("distance = 0"), (0, 0));
+        assert_eq!(distance("distance = 150"), (100, 1));
+    }
+
+    #[test]
+    fn params_parse() {
+        let parse = |src: &str| toml::from_str::<ConfigGlobal>(src).unwrap().size.unwrap();
+        assert!(matches!(parse("size = 15.0"), Param::Fixed(x) if x == 15.));
+        assert!(matches!(parse("size = [10.0, 20.0]"), Param::Range(a, b) if a == 10. && b == 20.));
+        assert!(
+            matches!(parse("size = [[10.0, 1], [20.0, 3]]"), Param::Weighted(v) if v.len() == 2)
+        );
+    }
+}
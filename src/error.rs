@@ -0,0 +1,146 @@
+use std::fmt;
+use std::io;
+
+/// Everything that can go wrong in the library
+#[derive(Debug)]
+pub enum Error {
+    /// Reading or writing a file failed
+    Io(io::Error),
+    /// Invalid TOML, or TOML that does not have the expected layout
+    Parse(toml::de::Error),
+    /// A scene could not be written as TOML
+    Serialize(toml::ser::Error),
+    /// The scene file was written by an incompatible version
+    SceneVersion { found: Option<i64>, expected: usize },
+    /// A value is neither a named color, "#RRGGBB" nor [R, G, B]
+    InvalidColor(String),
+    /// A theme is not an array of theme items
+    InvalidTheme(String),
+    /// A shape name or shape item is not recognized
+    InvalidShape(String),
+    /// A verbosity flag other than 'IWPDA'
+    InvalidVerbosity(char),
+    /// Not enough distinct points to build a Delaunay triangulation
+    Triangulation { points: usize },
+    /// The sides of a polygon do not close it
+    MalformedShape(String),
+    /// The extension of the destination does not match any known format
+    UnsupportedFormat(String),
+    /// A quality was given for a format that does not have one
+    Lossless(String),
+    /// The requested format was not compiled in
+    MissingFeature {
+        format: &'static str,
+        feature: &'static str,
+    },
+    /// The image could not be converted to the requested format
+    Render(String),
+    /// An SVG that was not written as recolorable
+    NoPalette,
+    /// Something went wrong in a configuration file or in one of the files it includes
+    Include { path: String, error: Box<Error> },
+    /// A configuration file includes itself, directly or not
+    IncludeCycle(String),
+    /// `include` is neither a path nor an array of paths
+    InvalidInclude(String),
+}
+
+pub type Result<T> = std::result::Result<T, Error>;
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        use Error::*;
+        match self {
+            Io(e) => write!(f, "{}", e),
+            Parse(e) => write!(f, "{}", e),
+            Serialize(e) => write!(f, "{}", e),
+            SceneVersion { found, expected } => match found {
+                Some(v) => write!(
+                    f,
+                    "Unsupported scene file version {}, expected {}",
+                    v, expected
+                ),
+                None => write!(f, "Scene file has no version, expected {}", expected),
+            },
+            InvalidColor(s) => write!(
+                f,
+                "{} is not a valid color format. Use [0, 0, 255] or \"#0000FF\"",
+                s
+            ),
+            InvalidTheme(s) => write!(
+                f,
+                "{} is not a valid theme. Provide a theme item or an array of theme items",
+                s
+            ),
+            InvalidShape(s) => write!(f, "{} is not a valid shape", s),
+            InvalidVerbosity(c) => write!(
+                f,
+                "Unknown verbosity option '{}', use one or more of 'IWPDA'",
+                c
+            ),
+            Triangulation { points } => write!(
+                f,
+                "Cannot triangulate {} points, at least 3 that are not aligned are needed",
+                points
+            ),
+            MalformedShape(s) => write!(f, "Cannot build a {}, its sides do not meet", s),
+            UnsupportedFormat(dest) => write!(
+                f,
+                "Cannot guess the format of {:?}, supported formats are svg, svgz, png, jpg, webp, bmp and ppm",
+                dest
+            ),
+            Lossless(format) => write!(
+                f,
+                "Format {} is lossless, quality only applies to jpg",
+                format
+            ),
+            MissingFeature { format, feature } => write!(
+                f,
+                "{} is not supported with the current feature flags -- Make sure to include the feature '{}' to access this option -- See 'https://doc.rust-lang.org/cargo/reference/features.html' to learn how to do it",
+                format, feature
+            ),
+            Render(s) => write!(f, "{}", s),
+            NoPalette => write!(
+                f,
+                "No theme colors found, the image was not generated as recolorable"
+            ),
+            Include { path, error } => write!(f, "{}: {}", path, error),
+            IncludeCycle(path) => write!(f, "{} includes itself", path),
+            InvalidInclude(s) => write!(
+                f,
+                "{} is not a valid include, use a path or an array of paths",
+                s
+            ),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::Io(e) => Some(e),
+            Error::Parse(e) => Some(e),
+            Error::Serialize(e) => Some(e),
+            Error::Include { error, .. } => Some(error.as_ref()),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for Error {
+    fn from(e: io::Error) -> Self {
+        Error::Io(e)
+    }
+}
+
+impl From<toml::de::Error> for Error {
+    fn from(e: toml::de::Error) -> Self {
+        Error::Parse(e)
+    }
+}
+
+impl From<toml::ser::Error> for Error {
+    fn from(e: toml::ser::Error) -> Self {
+        Error::Serialize(e)
+    }
+}
@@ -0,0 +1,165 @@
+use crate::{Error, Result};
+use std::fmt;
+use std::str::FromStr;
+
+/// Default quality of lossy formats
+pub const QUALITY: u8 = 90;
+
+/// File formats that a document can be written to
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Format {
+    /// Vector image, no rasterization involved
+    Svg,
+    /// Gzip-compressed SVG, requires the feature 'make-svgz'
+    Svgz,
+    /// Requires the feature 'make-png'
+    Png,
+    /// Requires the feature 'make-jpeg', quality ranges from 1 to 100
+    Jpeg { quality: u8 },
+    /// Always lossless, requires the feature 'make-webp'
+    WebP,
+    /// Uncompressed 24-bit bitmap
+    Bmp,
+    /// Binary portable pixmap (P6)
+    Ppm,
+}
+
+/// Names accepted by `--format` and recognized as extensions
+pub const FORMAT_NAMES: &[(&[&str], Format)] = &[
+    (&["svg"], Format::Svg),
+    (&["svgz"], Format::Svgz),
+    (&["png"], Format::Png),
+    (&["jpg", "jpeg"], Format::Jpeg { quality: QUALITY }),
+    (&["webp"], Format::WebP),
+    (&["bmp"], Format::Bmp),
+    (&["ppm"], Format::Ppm),
+];
+
+impl Format {
+    /// Guess the format from the extension of `dest`,
+    /// ignoring a trailing `.tmp` as used for atomic writes
+    pub fn from_path(dest: &str) -> Result<Self> {
+        let name = dest.strip_suffix(".tmp").unwrap_or(dest);
+        std::path::Path::new(name)
+            .extension()
+            .and_then(|ext| ext.to_str())
+            .and_then(|ext| ext.to_lowercase().parse().ok())
+            .ok_or_else(|| Error::UnsupportedFormat(dest.to_string()))
+    }
+
+    /// Fails for lossless formats, which have no quality setting
+    pub fn with_quality(self, quality: u8) -> Result<Self> {
+        match self {
+            Format::Jpeg { .. } => Ok(Format::Jpeg { quality }),
+            other => Err(Error::Lossless(other.to_string())),
+        }
+    }
+
+    pub fn is_lossy(self) -> bool {
+        matches!(self, Format::Jpeg { .. })
+    }
+
+    pub fn extension(self) -> &'static str {
+        match self {
+            Format::Svg => "svg",
+            Format::Svgz => "svgz",
+            Format::Png => "png",
+            Format::Jpeg { .. } => "jpg",
+            Format::WebP => "webp",
+            Format::Bmp => "bmp",
+            Format::Ppm => "ppm",
+        }
+    }
+}
+
+impl FromStr for Format {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        FORMAT_NAMES
+            .iter()
+            .find(|(names, _)| names.contains(&s))
+            .map(|&(_, f)| f)
+            .ok_or_else(|| Error::UnsupportedFormat(s.to_string()))
+    }
+}
+
+impl fmt::Display for Format {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.extension())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::prelude::*;
+    use crate::svg::Document;
+
+    #[test]
+    fn formats_from_extensions() {
+        assert_eq!(Format::from_path("x.png").unwrap(), Format::Png);
+        assert_eq!(Format::from_path("x.png.tmp").unwrap(), Format::Png);
+        assert_eq!(
+            Format::from_path("dir.svg/X.JPEG").unwrap(),
+            Format::Jpeg { quality: QUALITY }
+        );
+        for dest in &["x.gif", "x.tmp", "png", "x.png.bak"] {
+            assert!(
+                matches!(Format::from_path(dest), Err(Error::UnsupportedFormat(_))),
+                "{}",
+                dest
+            );
+        }
+    }
+
+    #[test]
+    fn quality_of_lossy_formats_only() {
+        let jpeg = Format::Jpeg { quality: QUALITY };
+        assert_eq!(jpeg.with_quality(50).unwrap(), Format::Jpeg { quality: 50 });
+        for format in &[Format::Png, Format::WebP, Format::Svg, Format::Bmp] {
+            match format.with_quality(50) {
+                Err(e @ Error::Lossless(_)) => assert!(e.to_string().contains(format.extension())),
+                _ => panic!("{} accepted a quality", format),
+            }
+        }
+    }
+
+    #[test]
+    fn missing_features_leave_no_file() {
+        let dir = tempfile::TempDir::new().unwrap();
+        let doc = Document::new(Frame {
+            x: 0,
+            y: 0,
+            w: 4,
+            h: 4,
+        });
+        let formats = [
+            (Format::Png, cfg!(feature = "make-png")),
+            (
+                Format::Jpeg { quality: QUALITY },
+                cfg!(feature = "make-jpeg"),
+            ),
+            (Format::WebP, cfg!(feature = "make-webp")),
+            (Format::Svgz, cfg!(feature = "make-svgz")),
+            (Format::Bmp, true),
+            (Format::Ppm, true),
+            (Format::Svg, true),
+        ];
+        for &(format, enabled) in &formats {
+            let dest = dir.path().join(format!("x.{}.tmp", format));
+            let result = doc.save_as(dest.to_str().unwrap(), format);
+            if enabled {
+                assert!(result.is_ok(), "{}", format);
+                assert!(dest.is_file());
+            } else {
+                assert!(
+                    matches!(result, Err(Error::MissingFeature { .. })),
+                    "{}",
+                    format
+                );
+                assert!(!dest.exists(), "{}", format);
+            }
+        }
+    }
+}
@@ -1,6 +1,7 @@
 use crate::prelude::*;
+use serde_derive::{Deserialize, Serialize};
 
-#[derive(Clone, Copy)]
+#[derive(Clone, Copy, Serialize, Deserialize)]
 pub struct Frame {
     pub x: usize,
     pub y: usize,
@@ -19,13 +20,163 @@ impl Frame {
         Pos((self.x + self.w / 2) as f64, (self.y + self.h / 2) as f64)
     }
 
-    /// Check that point is within some distance of the frame (include points that are not far outside)
-    pub fn is_inside(&self, pos: Pos) -> bool {
-        let xerr = (self.w as f64) / 10.;
-        let yerr = (self.h as f64) / 10.;
-        (self.x as f64 - xerr) < pos.0
-            && pos.0 < (self.x + self.w) as f64 + xerr
-            && (self.y as f64 - yerr) < pos.1
-            && pos.1 < (self.y + self.h) as f64 + yerr
+    /// Part of a polygon that lies within the frame extended by `margin` on all sides
+    /// (Sutherland-Hodgman). Empty if the polygon is entirely outside.
+    pub fn clip(&self, poly: &[Pos], margin: f64) -> Vec<Pos> {
+        let (xmin, ymin) = (self.x as f64 - margin, self.y as f64 - margin);
+        let (xmax, ymax) = (
+            (self.x + self.w) as f64 + margin,
+            (self.y + self.h) as f64 + margin,
+        );
+        let inside = |p: &Pos| xmin <= p.0 && p.0 <= xmax && ymin <= p.1 && p.1 <= ymax;
+        // Most tiles do not touch the border
+        if poly.iter().all(inside) {
+            return poly.to_vec();
+        }
+        // Each border is given as (vertical, limit, keep values above the limit)
+        let borders = [
+            (true, xmin, true),
+            (true, xmax, false),
+            (false, ymin, true),
+            (false, ymax, false),
+        ];
+        let mut current = poly.to_vec();
+        for &(vertical, limit, above) in &borders {
+            let coord = |p: Pos| if vertical { p.0 } else { p.1 };
+            let keep = |p: Pos| {
+                if above {
+                    coord(p) >= limit
+                } else {
+                    coord(p) <= limit
+                }
+            };
+            let input = std::mem::take(&mut current);
+            for (i, &p) in input.iter().enumerate() {
+                let prev = input[(i + input.len() - 1) % input.len()];
+                if keep(p) != keep(prev) {
+                    let t = (limit - coord(prev)) / (coord(p) - coord(prev));
+                    current.push(prev + (p - prev) * t);
+                }
+                if keep(p) {
+                    current.push(p);
+                }
+            }
+            if current.is_empty() {
+                break;
+            }
+        }
+        current
+    }
+}
+
+/// Size in pixels of a rendered image, independent of the frame it shows
+#[derive(Clone, Copy, Debug)]
+pub struct Resolution {
+    /// Defaults to the width of the frame, or keeps its aspect ratio if only the height is set
+    pub width: Option<usize>,
+    pub height: Option<usize>,
+    /// Draw raster images this many times larger and average them down, which hides seams between tiles
+    pub supersample: usize,
+}
+
+impl Default for Resolution {
+    fn default() -> Self {
+        Self {
+            width: None,
+            height: None,
+            supersample: 1,
+        }
+    }
+}
+
+impl Resolution {
+    /// Output dimensions for a given frame
+    pub fn size(&self, frame: &Frame) -> (usize, usize) {
+        let ratio = |a: usize, b: usize, c: usize| ((a * b) as f64 / c as f64).round() as usize;
+        match (self.width, self.height) {
+            (Some(w), Some(h)) => (w, h),
+            (Some(w), None) => (w, ratio(w, frame.h, frame.w).max(1)),
+            (None, Some(h)) => (ratio(h, frame.w, frame.h).max(1), h),
+            (None, None) => (frame.w, frame.h),
+        }
+    }
+
+    /// Both dimensions of the frame multiplied by `scale`
+    pub fn scaled(frame: &Frame, scale: f64) -> Self {
+        Self {
+            width: Some(((frame.w as f64 * scale).round() as usize).max(1)),
+            height: Some(((frame.h as f64 * scale).round() as usize).max(1)),
+            ..Self::default()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const FRAME: Frame = Frame {
+        x: 10,
+        y: 20,
+        w: 100,
+        h: 50,
+    };
+
+    fn square(x: f64, y: f64, side: f64) -> Vec<Pos> {
+        vec![
+            Pos(x, y),
+            Pos(x + side, y),
+            Pos(x + side, y + side),
+            Pos(x, y + side),
+        ]
+    }
+
+    fn area(poly: &[Pos]) -> f64 {
+        let twice = (0..poly.len())
+            .map(|i| {
+                let (a, b) = (poly[i], poly[(i + 1) % poly.len()]);
+                a.0 * b.1 - b.0 * a.1
+            })
+            .sum::<f64>();
+        twice.abs() / 2.
+    }
+
+    #[test]
+    fn clip_inside_is_unchanged() {
+        let poly = square(20., 30., 10.);
+        let clipped = FRAME.clip(&poly, 0.);
+        assert_eq!(clipped.len(), 4);
+        for (a, b) in poly.iter().zip(&clipped) {
+            assert_eq!(a.into_tuple(), b.into_tuple());
+        }
+    }
+
+    #[test]
+    fn clip_outside_is_empty() {
+        assert!(FRAME.clip(&square(200., 30., 10.), 0.).is_empty());
+        assert!(FRAME.clip(&square(0., 0., 5.), 0.).is_empty());
+    }
+
+    #[test]
+    fn clip_straddling_border() {
+        // Half of the square is left of x = 10
+        let clipped = FRAME.clip(&square(5., 30., 10.), 0.);
+        assert!((area(&clipped) - 50.).abs() < 1e-9);
+        assert!(clipped.iter().all(|p| p.0 >= 10. - 1e-9));
+        // Over a corner, only a quarter remains
+        let clipped = FRAME.clip(&square(105., 65., 10.), 0.);
+        assert!((area(&clipped) - 25.).abs() < 1e-9);
+        // Larger than the whole frame
+        let clipped = FRAME.clip(&square(0., 0., 200.), 0.);
+        assert!((area(&clipped) - 100. * 50.).abs() < 1e-9);
+    }
+
+    #[test]
+    fn clip_with_margin() {
+        let poly = square(5., 30., 10.);
+        assert_eq!(FRAME.clip(&poly, 5.).len(), 4);
+        let clipped = FRAME.clip(&poly, 2.);
+        assert!((area(&clipped) - 70.).abs() < 1e-9);
+        assert_eq!(FRAME.clip(&square(0., 10., 5.), 15.).len(), 4);
     }
 }
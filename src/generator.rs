@@ -0,0 +1,262 @@
+use crate::cfg::SceneCfg;
+use crate::deserializer::MetaConfig;
+use crate::log::Logger;
+use crate::palette::Tint;
+use crate::prelude::*;
+use crate::scene::Scene;
+use crate::schedule::Moment;
+use crate::svg::Document;
+use crate::Result;
+use chrono::{Local, NaiveDateTime, Offset, TimeZone};
+use rand::{rngs::StdRng, Rng, SeedableRng};
+use rand_chacha::ChaCha20Rng;
+use rayon::prelude::*;
+use std::path::PathBuf;
+
+/// Where the configuration comes from
+enum Source {
+    Default,
+    Parsed(Box<MetaConfig>),
+    Text(String),
+    File(PathBuf),
+}
+
+/// Entry point for generating wallpapers from a configuration.
+///
+/// ```no_run
+/// let svg = wallrnd::Generator::new()
+///     .config_file("wallrnd.toml")
+///     .time(1730)
+///     .seed(42)
+///     .render_svg()
+///     .unwrap();
+/// ```
+pub struct Generator<'d> {
+    source: Source,
+    at: Option<NaiveDateTime>,
+    utc_offset: Option<i32>,
+    time: Option<usize>,
+    seed: Option<u64>,
+    width: Option<usize>,
+    height: Option<usize>,
+    diag: &'d dyn Diagnostics,
+}
+
+/// A chosen configuration and scene, ready to be drawn
+pub struct Wallpaper {
+    pub seed: u64,
+    pub cfg: SceneCfg,
+    pub scene: Scene,
+}
+
+impl Default for Generator<'_> {
+    fn default() -> Self {
+        Self {
+            source: Source::Default,
+            at: None,
+            utc_offset: None,
+            time: None,
+            seed: None,
+            width: None,
+            height: None,
+            diag: &SILENT,
+        }
+    }
+}
+
+const SILENT: Verbosity = Verbosity {
+    info: false,
+    warn: false,
+    prog: false,
+    details: false,
+};
+
+impl<'d> Generator<'d> {
+    /// Default settings, current time and a random seed
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Use an already parsed configuration
+    pub fn config(mut self, cfg: MetaConfig) -> Self {
+        self.source = Source::Parsed(Box::new(cfg));
+        self
+    }
+
+    /// Use the contents of a configuration file
+    pub fn config_str<S: Into<String>>(mut self, src: S) -> Self {
+        self.source = Source::Text(src.into());
+        self
+    }
+
+    /// Read the configuration from a file when generating, along with the files that it includes
+    pub fn config_file<P: Into<PathBuf>>(mut self, path: P) -> Self {
+        self.source = Source::File(path.into());
+        self
+    }
+
+    /// Generate as if it was `at` (local time), for entries that depend on the date
+    pub fn at(mut self, at: NaiveDateTime) -> Self {
+        self.at = Some(at);
+        self
+    }
+
+    /// Time zone of `at` in minutes east of UTC, which places `sun` spans in the day.
+    /// Defaults to the time zone of the computer.
+    pub fn utc_offset(mut self, minutes: i32) -> Self {
+        self.utc_offset = Some(minutes);
+        self
+    }
+
+    /// Generate as if the current time was `hhmm`, takes precedence over the time of `at`
+    pub fn time(mut self, hhmm: usize) -> Self {
+        self.time = Some(hhmm);
+        self
+    }
+
+    /// Same seed, configuration and time produce the same image
+    pub fn seed(mut self, seed: u64) -> Self {
+        self.seed = Some(seed);
+        self
+    }
+
+    /// Override global.width
+    pub fn width(mut self, width: usize) -> Self {
+        self.width = Some(width);
+        self
+    }
+
+    /// Override global.height
+    pub fn height(mut self, height: usize) -> Self {
+        self.height = Some(height);
+        self
+    }
+
+    /// Where to report warnings and progress, silent by default
+    pub fn diagnostics(mut self, diag: &'d dyn Diagnostics) -> Self {
+        self.diag = diag;
+        self
+    }
+
+    /// Make all random choices
+    pub fn build(self) -> Result<Wallpaper> {
+        let diag = self.diag;
+        let meta = match self.source {
+            Source::Default => MetaConfig::default(),
+            Source::Parsed(cfg) => *cfg,
+            Source::Text(src) => MetaConfig::parse(&src)?,
+            Source::File(path) => MetaConfig::load(&path)?,
+        };
+        let at = self.at.unwrap_or_else(|| {
+            let now = Local::now().naive_local();
+            diag.info(format_args!(
+                "Using current time: {}",
+                now.format("%Y-%m-%d %H%M")
+            ));
+            now
+        });
+        // Offset of the time zone of the computer at that time,
+        // the earliest one if clocks go back then
+        let utc_offset = self.utc_offset.unwrap_or_else(|| {
+            Local
+                .offset_from_local_datetime(&at)
+                .earliest()
+                .map(|zone| zone.fix().local_minus_utc() / 60)
+                .unwrap_or(0)
+        });
+        let mut now = Moment::new(at, utc_offset);
+        if let Some(hhmm) = self.time {
+            now.time = hhmm;
+        }
+        // All randomness is derived from the seed so that an image can be reproduced
+        let seed = self.seed.unwrap_or_else(|| rand::thread_rng().gen());
+        diag.info(format_args!("Using seed: {}", seed));
+        let mut rng = StdRng::seed_from_u64(seed);
+
+        diag.progress(format_args!(
+            "Choosing random settings according to configuration"
+        ));
+        let mut cfg = meta.pick_cfg(&mut rng, now, diag);
+        if let Some(w) = self.width {
+            cfg.frame.w = w;
+        }
+        if let Some(h) = self.height {
+            cfg.frame.h = h;
+        }
+        diag.progress(format_args!("Building scene"));
+        let scene = Scene::new(&cfg, &mut rng, diag);
+        Ok(Wallpaper { seed, cfg, scene })
+    }
+
+    /// Make all random choices and draw the image
+    pub fn generate(self) -> Result<Document> {
+        let diag = self.diag;
+        self.build()?.render(diag)
+    }
+
+    /// Make all random choices and draw the image as SVG text
+    pub fn render_svg(self) -> Result<String> {
+        Ok(self.generate()?.to_string())
+    }
+}
+
+impl Wallpaper {
+    /// Reconstruct a wallpaper from its scene file
+    pub fn replay(log: Logger) -> Self {
+        let (seed, cfg, scene) = log.restore();
+        Self { seed, cfg, scene }
+    }
+
+    /// Everything needed to replay this wallpaper
+    pub fn record(&self) -> Logger {
+        Logger::record(self.seed, &self.cfg, &self.scene)
+    }
+
+    /// Color tiling according to scene
+    pub fn render(&self, diag: &dyn Diagnostics) -> Result<Document> {
+        let stroke = self.cfg.line_color;
+        let stroke_width = self.cfg.line_width;
+        let stroke_like_fill = stroke_width < 0.0001;
+
+        diag.progress(format_args!("Creating tiling"));
+        let tiles = self.cfg.make_tiling()?;
+        let index = self.scene.index(&self.cfg.frame);
+        // Tiles dropped by clipping keep their index, so that clipping does not change colors
+        let tiles = tiles
+            .into_par_iter()
+            .enumerate()
+            .filter_map(|(i, (pos, elem))| {
+                let elem = self.cfg.clip_tile(elem)?;
+                Some((elem, index.color(pos, &mut tile_rng(self.seed, i))))
+            })
+            .collect::<Vec<_>>();
+        let mut document = Document::new(self.cfg.frame).with_resolution(self.cfg.resolution);
+        document.precision = self.cfg.precision;
+        document.compact = self.cfg.compact;
+        document.recolorable = self.cfg.recolorable;
+        if self.cfg.recolorable && self.cfg.compact {
+            diag.warn(format_args!(
+                "Recolorable output cannot be compact, ignoring 'compact'"
+            ));
+        }
+        for (elem, Tint { color: fill, slot }) in tiles {
+            document.add(
+                elem.with_fill_color(fill)
+                    .with_slot(slot)
+                    .with_stroke_color(if stroke_like_fill { fill } else { stroke })
+                    .with_stroke_width(stroke_width.max(0.1)),
+            );
+        }
+        Ok(document)
+    }
+}
+
+/// Independent generator for each tile, so that colors do not depend on
+/// the order in which tiles are processed nor on how many random draws
+/// went into building the scene.
+/// Stream 0 is the one that `StdRng::seed_from_u64(seed)` uses to build the scene.
+fn tile_rng(seed: u64, index: usize) -> ChaCha20Rng {
+    let mut rng = ChaCha20Rng::seed_from_u64(seed);
+    rng.set_stream(index as u64 + 1);
+    rng
+}
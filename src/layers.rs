@@ -0,0 +1,242 @@
+//! Configuration spread over several files: files included with `include = [...]`,
+//! and the system, user and project configurations that are found automatically.
+
+use crate::{Error, Result};
+use std::env;
+use std::path::{Path, PathBuf};
+use toml::{map::Map, Value};
+
+/// Name of the configuration file in each of the directories searched
+pub const CONFIG_NAME: &str = "wallrnd.toml";
+
+/// Subdirectory of the system and user configuration directories
+const APP_DIR: &str = "wallrnd";
+
+/// Merge `over` into `base`: tables are merged key by key, any other value
+/// (including arrays such as the list of entries) replaces the one of `base`
+pub fn merge(base: &mut Map<String, Value>, over: Map<String, Value>) {
+    for (key, value) in over {
+        match (base.get_mut(&key), value) {
+            (Some(Value::Table(base)), Value::Table(over)) => merge(base, over),
+            (_, value) => {
+                base.insert(key, value);
+            }
+        }
+    }
+}
+
+/// Read a configuration file along with the files that it includes
+pub fn load(path: &Path) -> Result<Map<String, Value>> {
+    load_nested(path, &mut Vec::new())
+}
+
+/// Parse a configuration along with the files that it includes,
+/// relative paths are taken from `dir`
+pub fn parse(src: &str, dir: &Path) -> Result<Map<String, Value>> {
+    parse_nested(src, dir, &mut Vec::new())
+}
+
+/// Merge of all files of `paths`, later ones take precedence
+pub fn load_all(paths: &[PathBuf]) -> Result<Map<String, Value>> {
+    let mut merged = Map::new();
+    for path in paths {
+        merge(&mut merged, load(path)?);
+    }
+    Ok(merged)
+}
+
+/// `stack` holds the files being read, to detect files that include themselves
+fn load_nested(path: &Path, stack: &mut Vec<PathBuf>) -> Result<Map<String, Value>> {
+    let context = |e: Error| Error::Include {
+        path: path.display().to_string(),
+        error: Box::new(e),
+    };
+    let canonical = path.canonicalize().map_err(|e| context(e.into()))?;
+    if stack.contains(&canonical) {
+        return Err(Error::IncludeCycle(path.display().to_string()));
+    }
+    let src = std::fs::read_to_string(path).map_err(|e| context(e.into()))?;
+    let dir = canonical.parent().unwrap_or_else(|| Path::new("."));
+    stack.push(canonical.clone());
+    let table = parse_nested(&src, dir, stack);
+    stack.pop();
+    table.map_err(|e| match e {
+        // Already names the file
+        Error::Include { .. } | Error::IncludeCycle(_) => e,
+        e => context(e),
+    })
+}
+
+fn parse_nested(src: &str, dir: &Path, stack: &mut Vec<PathBuf>) -> Result<Map<String, Value>> {
+    let mut table = match src.parse::<Value>()? {
+        Value::Table(t) => t,
+        _ => unreachable!("A TOML document is always a table"),
+    };
+    let includes = match table.remove("include") {
+        None => Vec::new(),
+        Some(Value::String(s)) => vec![s],
+        Some(Value::Array(a)) => a
+            .into_iter()
+            .map(|v| match v {
+                Value::String(s) => Ok(s),
+                v => Err(Error::InvalidInclude(v.to_string())),
+            })
+            .collect::<Result<_>>()?,
+        Some(v) => return Err(Error::InvalidInclude(v.to_string())),
+    };
+    // The including file has the last word
+    let mut merged = Map::new();
+    for name in includes {
+        merge(
+            &mut merged,
+            load_nested(&dir.join(expand_home(&name)), stack)?,
+        );
+    }
+    merge(&mut merged, table);
+    Ok(merged)
+}
+
+/// Replace a leading `~/` by the home directory
+fn expand_home(name: &str) -> PathBuf {
+    match (name.strip_prefix("~/"), env::var_os("HOME")) {
+        (Some(rest), Some(home)) => PathBuf::from(home).join(rest),
+        _ => PathBuf::from(name),
+    }
+}
+
+/// Value of an environment variable that holds a path, ignored if empty
+fn env_path(var: &str) -> Option<PathBuf> {
+    env::var_os(var)
+        .filter(|v| !v.is_empty())
+        .map(PathBuf::from)
+}
+
+/// Configuration files that are looked for when none is given, from lowest to highest precedence:
+/// system (`$XDG_CONFIG_DIRS/wallrnd/wallrnd.toml`, by default in `/etc/xdg`),
+/// user (`$XDG_CONFIG_HOME/wallrnd/wallrnd.toml`, by default in `~/.config`)
+/// and project (`wallrnd.toml` in the current directory)
+pub fn candidates() -> Vec<PathBuf> {
+    let mut paths = Vec::new();
+    let system = env::var_os("XDG_CONFIG_DIRS")
+        .filter(|v| !v.is_empty())
+        .unwrap_or_else(|| "/etc/xdg".into());
+    // The first directories of the list are the most important ones
+    let system = env::split_paths(&system).collect::<Vec<_>>();
+    for dir in system.iter().rev() {
+        paths.push(dir.join(APP_DIR).join(CONFIG_NAME));
+    }
+    let user = env_path("XDG_CONFIG_HOME").or_else(|| env_path("HOME").map(|h| h.join(".config")));
+    if let Some(dir) = user {
+        paths.push(dir.join(APP_DIR).join(CONFIG_NAME));
+    }
+    paths.push(PathBuf::from(CONFIG_NAME));
+    paths
+}
+
+/// Those of `candidates()` that exist
+pub fn discover() -> Vec<PathBuf> {
+    candidates().into_iter().filter(|p| p.is_file()).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+    use tempfile::TempDir;
+
+    /// Write `src` to the file `name` of `dir`, creating subdirectories as needed
+    fn write(dir: &TempDir, name: &str, src: &str) -> PathBuf {
+        let path = dir.path().join(name);
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(&path, src).unwrap();
+        path
+    }
+
+    fn table(src: &str) -> Map<String, Value> {
+        parse(src, Path::new(".")).unwrap()
+    }
+
+    #[test]
+    fn later_layers_take_precedence() {
+        let mut base = table("[global]\nsize = 10\nwidth = 100\n[[entry]]\nspan = \"0000-1200\"\n");
+        merge(
+            &mut base,
+            table("[global]\nsize = 20\n[[entry]]\nspan = \"1200-2400\"\n"),
+        );
+        let global = base["global"].as_table().unwrap();
+        assert_eq!(global["size"].as_integer(), Some(20));
+        // Tables are merged key by key
+        assert_eq!(global["width"].as_integer(), Some(100));
+        // Arrays are replaced as a whole
+        let entries = base["entry"].as_array().unwrap();
+        assert_eq!(entries.len(), 1);
+        assert_eq!(entries[0]["span"].as_str(), Some("1200-2400"));
+    }
+
+    #[test]
+    fn includes_are_relative_to_the_including_file() {
+        let dir = TempDir::new().unwrap();
+        write(
+            &dir,
+            "common/colors.toml",
+            "[colors]\nred = \"#FF0000\"\n[global]\nsize = 10\n",
+        );
+        write(
+            &dir,
+            "common/base.toml",
+            "include = \"colors.toml\"\n[global]\nsize = 15\nwidth = 100\n",
+        );
+        let top = write(
+            &dir,
+            "wallrnd.toml",
+            "include = [\"common/base.toml\"]\n[global]\nwidth = 200\n",
+        );
+        let merged = load(&top).unwrap();
+        assert!(!merged.contains_key("include"));
+        assert_eq!(merged["colors"]["red"].as_str(), Some("#FF0000"));
+        // The including file has the last word
+        assert_eq!(merged["global"]["size"].as_integer(), Some(15));
+        assert_eq!(merged["global"]["width"].as_integer(), Some(200));
+        // Later files of a list take precedence
+        write(&dir, "a.toml", "[global]\nsize = 1\n");
+        write(&dir, "b.toml", "[global]\nsize = 2\n");
+        let merged = parse("include = [\"a.toml\", \"b.toml\"]", dir.path()).unwrap();
+        assert_eq!(merged["global"]["size"].as_integer(), Some(2));
+    }
+
+    #[test]
+    fn missing_include_names_the_file() {
+        let dir = TempDir::new().unwrap();
+        let top = write(&dir, "wallrnd.toml", "include = \"nowhere.toml\"\n");
+        match load(&top) {
+            Err(Error::Include { path, .. }) => assert!(path.ends_with("nowhere.toml")),
+            e => panic!("Unexpected result {:?}", e),
+        }
+        assert!(matches!(
+            parse("include = 5", dir.path()),
+            Err(Error::InvalidInclude(_))
+        ));
+    }
+
+    #[test]
+    fn include_cycles_are_detected() {
+        let dir = TempDir::new().unwrap();
+        let top = write(&dir, "a.toml", "include = \"b.toml\"\n");
+        write(&dir, "b.toml", "include = \"sub/../a.toml\"\n");
+        fs::create_dir_all(dir.path().join("sub")).unwrap();
+        match load(&top) {
+            Err(Error::IncludeCycle(path)) => assert!(path.ends_with("a.toml")),
+            e => panic!("Unexpected result {:?}", e),
+        }
+        let itself = write(&dir, "self.toml", "include = \"self.toml\"\n");
+        assert!(matches!(load(&itself), Err(Error::IncludeCycle(_))));
+        // Including the same file twice is not a cycle
+        write(&dir, "leaf.toml", "[global]\nsize = 3\n");
+        let twice = write(
+            &dir,
+            "twice.toml",
+            "include = [\"leaf.toml\", \"leaf.toml\"]\n",
+        );
+        assert!(load(&twice).is_ok());
+    }
+}
@@ -1,35 +1,94 @@
 pub mod cfg;
 pub mod chooser;
 pub mod color;
+pub mod compact;
 pub mod deserializer;
+pub mod error;
+pub mod format;
 pub mod frame;
+pub mod generator;
+pub mod layers;
 pub mod log;
 pub mod paint;
+pub mod palette;
 pub mod pos;
+pub mod raster;
 pub mod salt;
 pub mod scene;
+pub mod schedule;
 pub mod shape;
+pub mod sun;
 pub mod svg;
 pub mod tesselate;
+pub mod validate;
 
 pub mod prelude {
-    pub use super::Verbosity;
     use super::*;
+    pub use super::{Diagnostics, Level, Verbosity};
     pub use cfg::{Pattern, Tiling};
     pub use chooser::Chooser;
     pub use color::Color;
-    pub use frame::Frame;
+    pub use frame::{Frame, Resolution};
     pub use pos::{radians, Pos};
     pub use salt::{Salt, SaltItem};
 
     use std::collections::HashMap;
     pub type ColorList = HashMap<String, Color>;
     pub type ThemeList = HashMap<String, Chooser<ThemeItem>>;
+    pub type ShapeList = HashMap<String, (Chooser<Pattern>, Chooser<Tiling>)>;
 
     #[derive(Clone, Debug)]
     pub struct ThemeItem(pub Color, pub Option<usize>, pub Option<usize>, pub Salt);
 }
 
+pub use error::{Error, Result};
+pub use generator::{Generator, Wallpaper};
+
+use std::fmt;
+
+/// Kind of message reported during generation
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Level {
+    /// Basic information (theme, shapes, ...)
+    Info,
+    /// Recoverable errors (invalid configuration, badly formatted color, ...)
+    Warn,
+    /// Current actions
+    Progress,
+    /// Full scene layout, list of colors, ...
+    Details,
+}
+
+/// Receiver of all messages emitted by the library
+pub trait Diagnostics {
+    fn emit(&self, level: Level, message: fmt::Arguments);
+}
+
+impl<F: Fn(Level, fmt::Arguments)> Diagnostics for F {
+    fn emit(&self, level: Level, message: fmt::Arguments) {
+        self(level, message)
+    }
+}
+
+impl dyn Diagnostics + '_ {
+    pub fn info(&self, message: fmt::Arguments) {
+        self.emit(Level::Info, message)
+    }
+
+    pub fn warn(&self, message: fmt::Arguments) {
+        self.emit(Level::Warn, message)
+    }
+
+    pub fn progress(&self, message: fmt::Arguments) {
+        self.emit(Level::Progress, message)
+    }
+
+    pub fn details(&self, message: fmt::Arguments) {
+        self.emit(Level::Details, message)
+    }
+}
+
+/// Print selected messages to stdout
 #[derive(Clone, Copy, Default)]
 pub struct Verbosity {
     pub info: bool,
@@ -38,8 +97,24 @@ pub struct Verbosity {
     pub details: bool,
 }
 
-impl Verbosity {
-    pub fn from(s: &str) -> Self {
+impl Diagnostics for Verbosity {
+    fn emit(&self, level: Level, message: fmt::Arguments) {
+        let enabled = match level {
+            Level::Info => self.info,
+            Level::Warn => self.warn,
+            Level::Progress => self.prog,
+            Level::Details => self.details,
+        };
+        if enabled {
+            println!("{}", message);
+        }
+    }
+}
+
+impl std::str::FromStr for Verbosity {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
         let mut v = Verbosity::default();
         for option in s.chars() {
             match option {
@@ -53,12 +128,9 @@ impl Verbosity {
                 'W' => v.warn = true,
                 'P' => v.prog = true,
                 'D' => v.details = true,
-                c => println!(
-                    "Unknown verbosity option '{}', use one or more of 'IWPDA'",
-                    c
-                ),
+                c => return Err(Error::InvalidVerbosity(c)),
             }
         }
-        v
+        Ok(v)
     }
 }
@@ -1,360 +1,417 @@
+use crate::cfg::SceneCfg;
 use crate::prelude::*;
 use crate::scene::*;
-use std::fmt;
+use crate::{Error, Result};
+use serde_derive::{Deserialize, Serialize};
 use std::fs::File;
 use std::io::{Read, Write};
-use std::rc::Rc;
+use std::sync::Arc;
 
+/// Version of the scene file format, increased on any incompatible change
+pub const VERSION: usize = 2;
+
+/// Everything that is needed to reproduce an image
 pub struct Logger {
+    pub seed: u64,
     pub frame: Frame,
+    pub tiling: Tiling,
+    pub rotation: isize,
+    pub size_tiling: f64,
+    pub sdiag_rhombus: f64,
+    pub nb_delaunay: usize,
+    pub seed_delaunay: u64,
+    pub line_width: f64,
+    pub line_color: Color,
+    pub resolution: Resolution,
+    pub clip: bool,
+    pub precision: Option<usize>,
+    pub compact: bool,
+    pub recolorable: bool,
+    pub sampled: Vec<(String, toml::Value)>,
     pub bg: ColorItem,
-    pub objects: Vec<Rc<dyn Contains>>,
-}
-
-impl fmt::Display for Frame {
-    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        write!(f, "{} {} {} {} #", self.x, self.y, self.w, self.h)
-    }
+    pub objects: Vec<Arc<dyn Contains>>,
 }
 
-impl fmt::Display for ColorItem {
-    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        write!(f, "{} {} {} ", self.shade.0, self.shade.1, self.shade.2)?;
-        write!(f, "{} {} {} ", self.theme.0, self.theme.1, self.theme.2)?;
-        write!(f, "{} ", self.salt)?;
-        write!(f, "{} {} #", self.deviation, self.distance)
-    }
-}
-
-impl fmt::Display for SaltItem {
-    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        write!(f, "{} {} {} ", self.color.0, self.color.1, self.color.2)?;
-        write!(f, "{} {} ", self.likeliness, self.variability)
-    }
+/// Serializable form of any scene item
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(tag = "kind")]
+pub enum Item {
+    Disc(Disc),
+    HalfPlane(HalfPlane),
+    Triangle(Triangle),
+    Spiral(Spiral),
+    Stripe(Stripe),
+    Wave(Wave),
+    Sawtooth(Sawtooth),
 }
 
-impl fmt::Display for Salt {
-    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        for item in self.0.iter() {
-            write!(f, "{} ", item)?;
+impl Item {
+    pub fn dynamic(self) -> Arc<dyn Contains> {
+        match self {
+            Item::Disc(x) => Arc::new(x),
+            Item::HalfPlane(x) => Arc::new(x),
+            Item::Triangle(x) => Arc::new(x),
+            Item::Spiral(x) => Arc::new(x),
+            Item::Stripe(x) => Arc::new(x),
+            Item::Wave(x) => Arc::new(x),
+            Item::Sawtooth(x) => Arc::new(x),
         }
-        write!(f, "#")
-    }
-}
-
-impl fmt::Display for Disc {
-    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        write!(
-            f,
-            "Disc {} {} {} ",
-            self.center.0, self.center.1, self.radius
-        )?;
-        write!(f, "{} #", self.color)
-    }
-}
-
-impl fmt::Display for HalfPlane {
-    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        write!(
-            f,
-            "HalfPlane {} {} {} {} ",
-            self.limit.0, self.limit.1, self.reference.0, self.reference.1
-        )?;
-        write!(f, "{} #", self.color)
-    }
-}
-
-impl fmt::Display for Triangle {
-    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        write!(f, "Triangle {} {} ", self.a.0, self.a.1)?;
-        write!(f, "{} {} ", self.b.0, self.b.1)?;
-        write!(f, "{} {} ", self.c.0, self.c.1)?;
-        write!(f, "{} #", self.color)
     }
 }
 
-impl fmt::Display for Spiral {
-    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        write!(
-            f,
-            "Spiral {} {} {} {} ",
-            self.center.0, self.center.1, self.width, self.tightness
-        )?;
-        write!(f, "{} #", self.color)
-    }
+/// On-disk layout of a scene file.
+/// Plain values come before tables, as required by the TOML serializer.
+#[derive(Serialize, Deserialize)]
+struct SceneFile {
+    version: usize,
+    #[serde(with = "seed_format")]
+    seed: u64,
+    frame: Frame,
+    tiling: TilingRecord,
+    lines: LinesRecord,
+    /// Absent from files written before it was added
+    #[serde(default)]
+    output: OutputRecord,
+    /// Values drawn for settings given as ranges or lists, for information only
+    #[serde(default, skip_serializing_if = "toml::map::Map::is_empty")]
+    sampled: toml::map::Map<String, toml::Value>,
+    background: ColorItem,
+    #[serde(default)]
+    items: Vec<Item>,
 }
 
-impl fmt::Display for Stripe {
-    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        write!(
-            f,
-            "Stripe {} {} {} {} ",
-            self.limit.0, self.limit.1, self.reference.0, self.reference.1
-        )?;
-        write!(f, "{} #", self.color)
-    }
+#[derive(Serialize, Deserialize)]
+struct TilingRecord {
+    #[serde(flatten)]
+    kind: Tiling,
+    rotation: isize,
+    size: f64,
+    sdiag_rhombus: f64,
+    nb_delaunay: usize,
+    #[serde(with = "seed_format")]
+    seed_delaunay: u64,
 }
 
-impl fmt::Display for Wave {
-    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        write!(
-            f,
-            "Wave {} {} {} {} ",
-            self.limit.0, self.limit.1, self.reference.0, self.reference.1
-        )?;
-        write!(f, "{} {} ", self.amplitude, self.frequency)?;
-        write!(f, "{} #", self.color)
-    }
+#[derive(Serialize, Deserialize)]
+struct LinesRecord {
+    width: f64,
+    color: Color,
 }
 
-impl fmt::Display for Sawtooth {
-    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        write!(
-            f,
-            "Sawtooth {} {} {} {} ",
-            self.limit.0, self.limit.1, self.reference.0, self.reference.1
-        )?;
-        write!(f, "{} {} ", self.amplitude, self.frequency)?;
-        write!(f, "{} #", self.color)
-    }
+/// How the image was written, does not affect what it shows
+#[derive(Serialize, Deserialize)]
+#[serde(default)]
+struct OutputRecord {
+    width: Option<usize>,
+    height: Option<usize>,
+    supersample: usize,
+    clip: bool,
+    precision: Option<usize>,
+    compact: bool,
+    recolorable: bool,
 }
 
-impl fmt::Display for Logger {
-    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        write!(f, "{} ", self.frame)?;
-        write!(f, "{} ", self.bg)?;
-        write!(f, "{} ", self.objects.len())?;
-        for o in &self.objects {
-            write!(f, "{} ", o)?;
+impl Default for OutputRecord {
+    fn default() -> Self {
+        Self {
+            width: None,
+            height: None,
+            supersample: 1,
+            clip: false,
+            precision: None,
+            compact: false,
+            recolorable: false,
         }
-        Ok(())
     }
 }
 
 impl Logger {
-    pub fn save(&self, dest: &str) -> std::io::Result<()> {
-        let mut buffer = File::create(dest)?;
-        buffer.write_all(&format!("{}", &self).into_bytes())
-    }
-
-    pub fn load(src: &str) -> Self {
-        let mut s = String::new();
-        let mut file = File::open(src).unwrap();
-        file.read_to_string(&mut s).unwrap();
-        let mut items = s.split(' ');
-        Logger::restore(&mut items)
+    /// Gather all information on a generated scene
+    pub fn record(seed: u64, cfg: &SceneCfg, scene: &Scene) -> Self {
+        Self {
+            seed,
+            frame: cfg.frame,
+            tiling: cfg.tiling,
+            rotation: cfg.rotation,
+            size_tiling: cfg.size_tiling,
+            sdiag_rhombus: cfg.sdiag_rhombus,
+            nb_delaunay: cfg.nb_delaunay,
+            seed_delaunay: cfg.seed_delaunay,
+            line_width: cfg.line_width,
+            line_color: cfg.line_color,
+            resolution: cfg.resolution,
+            clip: cfg.clip,
+            precision: cfg.precision,
+            compact: cfg.compact,
+            recolorable: cfg.recolorable,
+            sampled: cfg.sampled.clone(),
+            bg: scene.bg.clone(),
+            objects: scene.items.clone(),
+        }
     }
-}
-
-trait Restore {
-    fn restore<'a>(items: &mut impl Iterator<Item = &'a str>) -> Self;
-}
 
-impl Restore for usize {
-    fn restore<'a>(items: &mut impl Iterator<Item = &'a str>) -> Self {
-        items.next().unwrap().parse::<Self>().unwrap()
+    /// Configuration and scene that were recorded, along with the seed
+    /// that was used for the original image
+    pub fn restore(self) -> (u64, SceneCfg, Scene) {
+        let cfg = SceneCfg {
+            frame: self.frame,
+            resolution: self.resolution,
+            clip: self.clip,
+            precision: self.precision,
+            compact: self.compact,
+            recolorable: self.recolorable,
+            tiling: self.tiling,
+            rotation: self.rotation,
+            size_tiling: self.size_tiling,
+            sdiag_rhombus: self.sdiag_rhombus,
+            nb_delaunay: self.nb_delaunay,
+            seed_delaunay: self.seed_delaunay,
+            line_width: self.line_width,
+            line_color: self.line_color,
+            sampled: self.sampled,
+            // Only used to build a scene, which is already there
+            theme: Chooser::default(),
+            distance: 0,
+            deviation: 0,
+            pattern: Pattern::FreeCircles,
+            nb_pattern: 0,
+            var_stripes: 0,
+            width_pattern: 0.,
+            tightness_spiral: 0.,
+        };
+        let scene = Scene {
+            bg: self.bg,
+            items: self.objects,
+        };
+        (self.seed, cfg, scene)
     }
-}
 
-impl Restore for f64 {
-    fn restore<'a>(items: &mut impl Iterator<Item = &'a str>) -> Self {
-        items.next().unwrap().parse::<Self>().unwrap()
+    pub fn save(&self, dest: &str) -> Result<()> {
+        let contents = self.to_toml()?;
+        let mut buffer = File::create(dest)?;
+        Ok(buffer.write_all(contents.as_bytes())?)
     }
-}
 
-impl Restore for Pos {
-    fn restore<'a>(items: &mut impl Iterator<Item = &'a str>) -> Self {
-        Self(f64::restore(items), f64::restore(items))
+    pub fn load(src: &str) -> Result<Self> {
+        let mut s = String::new();
+        File::open(src)?.read_to_string(&mut s)?;
+        Self::from_toml(&s)
     }
-}
 
-impl Restore for Color {
-    fn restore<'a>(items: &mut impl Iterator<Item = &'a str>) -> Self {
-        Self(
-            usize::restore(items),
-            usize::restore(items),
-            usize::restore(items),
-        )
+    /// Contents of the scene file
+    fn to_toml(&self) -> Result<String> {
+        let file = SceneFile {
+            version: VERSION,
+            seed: self.seed,
+            frame: self.frame,
+            tiling: TilingRecord {
+                kind: self.tiling,
+                rotation: self.rotation,
+                size: self.size_tiling,
+                sdiag_rhombus: self.sdiag_rhombus,
+                nb_delaunay: self.nb_delaunay,
+                seed_delaunay: self.seed_delaunay,
+            },
+            lines: LinesRecord {
+                width: self.line_width,
+                color: self.line_color,
+            },
+            output: OutputRecord {
+                width: self.resolution.width,
+                height: self.resolution.height,
+                supersample: self.resolution.supersample,
+                clip: self.clip,
+                precision: self.precision,
+                compact: self.compact,
+                recolorable: self.recolorable,
+            },
+            sampled: self.sampled.iter().cloned().collect(),
+            background: self.bg.clone(),
+            items: self.objects.iter().map(|o| o.record()).collect(),
+        };
+        Ok(toml::to_string(&file)?)
     }
-}
 
-impl Restore for Logger {
-    fn restore<'a>(items: &mut impl Iterator<Item = &'a str>) -> Self {
-        let frame = Frame::restore(items);
-        let bg = ColorItem::restore(items);
-        let len = items.next().unwrap().parse::<usize>().unwrap();
-        let mut objects = Vec::new();
-        for _ in 0..len {
-            objects.push(match items.next().unwrap() {
-                "Disc" => Rc::new(Disc::restore(items)) as Rc<dyn Contains>,
-                "HalfPlane" => Rc::new(HalfPlane::restore(items)) as Rc<dyn Contains>,
-                "Stripe" => Rc::new(Stripe::restore(items)) as Rc<dyn Contains>,
-                "Triangle" => Rc::new(Triangle::restore(items)) as Rc<dyn Contains>,
-                "Spiral" => Rc::new(Spiral::restore(items)) as Rc<dyn Contains>,
-                "Wave" => Rc::new(Wave::restore(items)) as Rc<dyn Contains>,
-                "Sawtooth" => Rc::new(Sawtooth::restore(items)) as Rc<dyn Contains>,
-                _ => panic!("Unknown item"),
+    /// Read the contents of a scene file
+    fn from_toml(s: &str) -> Result<Self> {
+        // Check the version on its own first to give a better message than
+        // whatever missing field a format change would cause
+        let version = toml::from_str::<toml::Value>(s)?
+            .get("version")
+            .and_then(|v| v.as_integer());
+        if version != Some(VERSION as i64) {
+            return Err(Error::SceneVersion {
+                found: version,
+                expected: VERSION,
             });
         }
-        Self { frame, bg, objects }
+        let file = toml::from_str::<SceneFile>(s)?;
+        Ok(Self {
+            seed: file.seed,
+            frame: file.frame,
+            tiling: file.tiling.kind,
+            rotation: file.tiling.rotation,
+            size_tiling: file.tiling.size,
+            sdiag_rhombus: file.tiling.sdiag_rhombus,
+            nb_delaunay: file.tiling.nb_delaunay,
+            seed_delaunay: file.tiling.seed_delaunay,
+            line_width: file.lines.width,
+            line_color: file.lines.color,
+            resolution: Resolution {
+                width: file.output.width,
+                height: file.output.height,
+                supersample: file.output.supersample,
+            },
+            clip: file.output.clip,
+            precision: file.output.precision,
+            compact: file.output.compact,
+            recolorable: file.output.recolorable,
+            sampled: file.sampled.into_iter().collect(),
+            bg: file.background,
+            objects: file.items.into_iter().map(Item::dynamic).collect(),
+        })
     }
 }
 
-impl Restore for Frame {
-    fn restore<'a>(items: &mut impl Iterator<Item = &'a str>) -> Self {
-        let x = usize::restore(items);
-        let y = usize::restore(items);
-        let w = usize::restore(items);
-        let h = usize::restore(items);
-        assert_eq!(items.next().unwrap(), "#");
-        Self { x, y, w, h }
-    }
-}
+/// TOML integers are signed 64-bit, seeds that do not fit are written as strings
+mod seed_format {
+    use serde::{de, Deserialize, Deserializer, Serializer};
+    use serde_derive::Deserialize;
+    use std::convert::TryFrom;
 
-impl Restore for Salt {
-    fn restore<'a>(items: &mut impl Iterator<Item = &'a str>) -> Self {
-        let nb = usize::restore(items);
-        let mut salt = Salt::default();
-        for _ in 0..nb {
-            salt.0.push(SaltItem::restore(items));
+    pub fn serialize<S: Serializer>(seed: &u64, s: S) -> Result<S::Ok, S::Error> {
+        match i64::try_from(*seed) {
+            Ok(n) => s.serialize_i64(n),
+            Err(_) => s.serialize_str(&seed.to_string()),
         }
-        assert_eq!(items.next().unwrap(), "#");
-        salt
     }
-}
 
-impl Restore for SaltItem {
-    fn restore<'a>(items: &mut impl Iterator<Item = &'a str>) -> Self {
-        let color = Color::restore(items);
-        let likeliness = f64::restore(items);
-        let variability = usize::restore(items);
-        Self {
-            color,
-            likeliness,
-            variability,
-        }
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum Repr {
+        Int(u64),
+        Str(String),
     }
-}
 
-impl Restore for ColorItem {
-    fn restore<'a>(items: &mut impl Iterator<Item = &'a str>) -> Self {
-        let shade = Color::restore(items);
-        let theme = Color::restore(items);
-        let salt = Salt::restore(items);
-        let deviation = usize::restore(items);
-        let distance = usize::restore(items);
-        assert_eq!(items.next().unwrap(), "#");
-        Self {
-            shade,
-            theme,
-            deviation,
-            distance,
-            salt,
+    pub fn deserialize<'de, D: Deserializer<'de>>(d: D) -> Result<u64, D::Error> {
+        match Repr::deserialize(d)? {
+            Repr::Int(n) => Ok(n),
+            Repr::Str(s) => s.parse().map_err(de::Error::custom),
         }
     }
 }
 
-impl Restore for Disc {
-    fn restore<'a>(items: &mut impl Iterator<Item = &'a str>) -> Self {
-        let center = Pos::restore(items);
-        let radius = f64::restore(items);
-        let color = ColorItem::restore(items);
-        assert_eq!(items.next().unwrap(), "#");
-        Self {
-            center,
-            radius,
-            color,
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn logger(seed: u64, tiling: Tiling) -> Logger {
+        let color = ColorItem {
+            shade: Color(10, 20, 30),
+            deviation: 5,
+            theme: Color(100, 50, 0),
+            distance: 40,
+            slot: Some(1),
+            salt: Salt::none(),
+        };
+        Logger {
+            seed,
+            frame: Frame {
+                x: 0,
+                y: 0,
+                w: 400,
+                h: 300,
+            },
+            tiling,
+            rotation: 30,
+            size_tiling: 12.5,
+            sdiag_rhombus: 0.7,
+            nb_delaunay: 500,
+            seed_delaunay: u64::MAX - 1,
+            line_width: 0.1,
+            line_color: Color(0, 0, 0),
+            resolution: Resolution::default(),
+            clip: false,
+            precision: Some(2),
+            compact: false,
+            recolorable: true,
+            sampled: Vec::new(),
+            bg: color.clone(),
+            objects: vec![Arc::new(Disc {
+                center: Pos(1., 2.),
+                radius: 3.,
+                color,
+            })],
         }
     }
-}
 
-impl Restore for HalfPlane {
-    fn restore<'a>(items: &mut impl Iterator<Item = &'a str>) -> Self {
-        let limit = Pos::restore(items);
-        let reference = Pos::restore(items);
-        let color = ColorItem::restore(items);
-        assert_eq!(items.next().unwrap(), "#");
-        Self {
-            limit,
-            reference,
-            color,
-        }
+    fn round_trip(log: &Logger) -> Logger {
+        Logger::from_toml(&log.to_toml().unwrap()).unwrap()
     }
-}
-
-impl Restore for Stripe {
-    fn restore<'a>(items: &mut impl Iterator<Item = &'a str>) -> Self {
-        let limit = Pos::restore(items);
-        let reference = Pos::restore(items);
-        let color = ColorItem::restore(items);
-        assert_eq!(items.next().unwrap(), "#");
-        Self {
-            limit,
-            reference,
-            color,
-        }
-    }
-}
 
-impl Restore for Triangle {
-    fn restore<'a>(items: &mut impl Iterator<Item = &'a str>) -> Self {
-        let a = Pos::restore(items);
-        let b = Pos::restore(items);
-        let c = Pos::restore(items);
-        let color = ColorItem::restore(items);
-        assert_eq!(items.next().unwrap(), "#");
-        Self { a, b, c, color }
+    #[test]
+    fn large_seeds_are_strings() {
+        let log = logger(u64::MAX, Tiling::Hexagons);
+        let toml = log.to_toml().unwrap();
+        assert!(toml.contains(&format!("seed = \"{}\"", u64::MAX)));
+        let back = round_trip(&log);
+        assert_eq!(back.seed, u64::MAX);
+        assert_eq!(back.seed_delaunay, u64::MAX - 1);
+        // Smaller seeds stay integers
+        let toml = logger(42, Tiling::Hexagons).to_toml().unwrap();
+        assert!(toml.contains("seed = 42\n"));
+        assert_eq!(
+            round_trip(&logger(i64::MAX as u64, Tiling::Hexagons)).seed,
+            i64::MAX as u64
+        );
     }
-}
 
-impl Restore for Spiral {
-    fn restore<'a>(items: &mut impl Iterator<Item = &'a str>) -> Self {
-        let center = Pos::restore(items);
-        let width = f64::restore(items);
-        let tightness = f64::restore(items);
-        let color = ColorItem::restore(items);
-        assert_eq!(items.next().unwrap(), "#");
-        Self {
-            center,
-            width,
-            color,
-            tightness,
+    #[test]
+    fn version_mismatch() {
+        let toml = logger(1, Tiling::Hexagons).to_toml().unwrap();
+        let current = format!("version = {}\n", VERSION);
+        assert!(toml.starts_with(&current));
+        let older = toml.replacen(&current, "version = 0\n", 1);
+        match Logger::from_toml(&older) {
+            Err(e @ Error::SceneVersion { found: Some(0), .. }) => assert_eq!(
+                e.to_string(),
+                format!("Unsupported scene file version 0, expected {}", VERSION)
+            ),
+            _ => panic!("version 0 accepted"),
         }
+        let missing = toml.replacen(&current, "", 1);
+        assert!(matches!(
+            Logger::from_toml(&missing),
+            Err(Error::SceneVersion { found: None, .. })
+        ));
     }
-}
 
-impl Restore for Wave {
-    fn restore<'a>(items: &mut impl Iterator<Item = &'a str>) -> Self {
-        let limit = Pos::restore(items);
-        let reference = Pos::restore(items);
-        let amplitude = f64::restore(items);
-        let frequency = f64::restore(items);
-        let color = ColorItem::restore(items);
-        assert_eq!(items.next().unwrap(), "#");
-        Self {
-            limit,
-            reference,
-            amplitude,
-            frequency,
-            color,
-        }
-    }
-}
-
-impl Restore for Sawtooth {
-    fn restore<'a>(items: &mut impl Iterator<Item = &'a str>) -> Self {
-        let limit = Pos::restore(items);
-        let reference = Pos::restore(items);
-        let amplitude = f64::restore(items);
-        let frequency = f64::restore(items);
-        let color = ColorItem::restore(items);
-        assert_eq!(items.next().unwrap(), "#");
-        Self {
-            limit,
-            reference,
-            amplitude,
-            frequency,
-            color,
+    #[test]
+    fn tilings_round_trip() {
+        use Tiling::*;
+        let mut tilings = vec![
+            Hexagons,
+            Triangles,
+            HexagonsAndTriangles,
+            SquaresAndTriangles,
+            Rhombus,
+            Delaunay,
+        ];
+        tilings.extend((1..=6).map(Pentagons));
+        // Scene files are written after the choice among pentagons is made
+        let resolved = Pentagons(0).resolve(&mut rand::thread_rng());
+        assert!(!matches!(resolved, Pentagons(0)));
+        tilings.push(resolved);
+        for tiling in tilings {
+            let back = round_trip(&logger(7, tiling));
+            assert_eq!(format!("{:?}", back.tiling), format!("{:?}", tiling));
+            assert_eq!(back.rotation, 30);
+            assert_eq!(back.size_tiling, 12.5);
+            assert_eq!(back.sdiag_rhombus, 0.7);
+            assert_eq!(back.nb_delaunay, 500);
+            assert_eq!(back.precision, Some(2));
+            assert!(back.recolorable);
+            assert_eq!(back.objects.len(), 1);
         }
     }
 }
@@ -1,156 +1,373 @@
-use chrono::{Local, Timelike};
-use std::env;
-use std::fs::File;
+use chrono::{NaiveDate, NaiveDateTime};
+use clap::{Args, Parser, Subcommand, ValueEnum};
 use std::io::prelude::*;
+use std::path::{Path, PathBuf};
 use std::process::exit;
-use wallrnd::deserializer::MetaConfig;
+use wallrnd::deserializer::{MetaConfig, Shape, SHAPE_NAMES};
+use wallrnd::format::Format;
+use wallrnd::layers;
 use wallrnd::log::Logger;
 use wallrnd::prelude::*;
-use wallrnd::scene::Scene;
-use wallrnd::svg::*;
+use wallrnd::svg::MAX_PRECISION;
+use wallrnd::validate::Severity;
+use wallrnd::{Generator, Wallpaper};
 
-fn main() {
-    let args = read_command_line_arguments();
+const VERBOSITY_HELP: &str = "Display more debug information.
+By default, wallrnd only reports missing or invalid configuration files. Verbosity is a combination of one or more of
+  'I': Info        Display basic information (theme, shapes, ...)
+  'P': Progress    Inform on the current actions
+  'D': Details     Very verbose, prints full scene layout and list of colors
+  'W': Warnings    Alert recoverable errors (badly formatted color, setting out of range, ...)
+  'A': All         Alias for 'IPDW'";
+
+const EXAMPLES: &str = "Examples:
+  wallrnd --image /tmp/random-wallpaper.svg --config ~/.config/wallrnd.toml --set --nice
+  wallrnd generate --verbose IP --log save.toml --time 1000 --image test.svg
+  wallrnd generate --seed 1234 --time 1000 --config wallrnd.toml --image test.svg
+  wallrnd replay save.toml --image test.svg
+  wallrnd --at 2024-12-25T08:30 --config wallrnd.toml --image christmas.svg
+  wallrnd --image /tmp/lock-screen.jpg --quality 80 --config wallrnd.toml
+  wallrnd --image wallpaper.png --scale 0.5 --supersample 2 --config wallrnd.toml
+  wallrnd init default.toml
+  wallrnd validate wallrnd.toml
+  wallrnd --image wallpaper.svg --recolorable --config wallrnd.toml
+  wallrnd recolor wallpaper.svg --theme ocean --config wallrnd.toml
+  wallrnd list themes --config wallrnd.toml";
+
+/// wallrnd - A highly configurable generator of abstract random wallpapers
+///
+/// When no subcommand is given, 'generate' is assumed.
+#[derive(Parser)]
+#[command(
+    version,
+    args_conflicts_with_subcommands = true,
+    arg_required_else_help = true,
+    subcommand_negates_reqs = true,
+    after_help = EXAMPLES
+)]
+struct Cli {
+    #[command(subcommand)]
+    command: Option<Command>,
+    #[command(flatten)]
+    generate: Generate,
+    /// Display more debug information (combination of 'IPDWA')
+    #[arg(long, global = true, value_name = "V", value_parser = parse_verbosity, long_help = VERBOSITY_HELP)]
+    verbose: Option<Verbosity>,
+    /// Lower process priority to run in the background (recommended)
+    #[arg(long, global = true)]
+    nice: bool,
+}
 
-    if args.help {
-        print_help();
-        exit(0);
+#[derive(Subcommand)]
+enum Command {
+    /// Generate a new random image (default)
+    Generate(Generate),
+    /// Create a default configuration file and exit
+    Init {
+        /// Destination of the configuration
+        file: String,
+    },
+    /// Reproduce an image from a scene file saved with '--log'
+    Replay {
+        /// Scene file
+        log: String,
+        #[command(flatten)]
+        output: Output,
+    },
+    /// Check a configuration file and report all problems
+    Validate {
+        /// Configuration file
+        config: String,
+        /// Also fail on warnings
+        #[arg(long)]
+        strict: bool,
+    },
+    /// Apply another theme to an SVG generated with '--recolorable'
+    Recolor {
+        /// SVG image to recolor
+        svg: String,
+        /// Name of the theme
+        #[arg(long, value_name = "T")]
+        theme: String,
+        /// Location of the config file that defines the theme, found as for 'generate' if absent
+        #[arg(long, value_name = "C")]
+        config: Option<String>,
+        /// Destination of the recolored image, the original is replaced if absent
+        #[arg(long, value_name = "I")]
+        image: Option<String>,
+    },
+    /// List items available for configuration
+    List {
+        #[arg(value_enum)]
+        what: Listing,
+        /// Location of the config file, found as for 'generate' if absent
+        #[arg(long, value_name = "C")]
+        config: Option<String>,
+    },
+}
+
+#[derive(Args)]
+struct Generate {
+    /// Location of the config file. If absent, the system, user (~/.config/wallrnd/wallrnd.toml)
+    /// and project (./wallrnd.toml) files that exist are merged. If invalid, default parameters are used.
+    #[arg(long, value_name = "C")]
+    config: Option<String>,
+    /// Generate image as if the current time was T (format HHMM)
+    #[arg(long, value_name = "T")]
+    time: Option<usize>,
+    /// Generate image as if it was D (ISO-8601, e.g. 2024-12-25T08:30 or 2024-12-25). '--time' overrides the time of day.
+    /// A time zone (2024-12-25T08:30+01:00) places 'sun' spans in the day instead of the one of the computer.
+    #[arg(long, value_name = "D", value_parser = parse_at)]
+    at: Option<At>,
+    /// Seed the random number generator to reproduce an image (printed with '--verbose I')
+    #[arg(long, value_name = "N")]
+    seed: Option<u64>,
+    /// Save generation information for image replication in file F (TOML scene file)
+    #[arg(long, value_name = "F")]
+    log: Option<String>,
+    #[command(flatten)]
+    output: Output,
+}
+
+#[derive(Args)]
+struct Output {
+    /// Destination of the generated file. Not necessarily absolute path.
+    // Not a plain `String`: the top-level arguments are still built when a
+    // subcommand is used, clap only enforces the requirement when parsing
+    #[arg(long, value_name = "I", required = true)]
+    image: Option<String>,
+    /// Image format (svg, svgz, png, jpg, webp, bmp, ppm). Guessed from the extension of '--image' if absent.
+    #[arg(long, value_name = "FMT", value_parser = parse_format)]
+    format: Option<Format>,
+    /// Quality of lossy formats (jpg), from 1 to 100. WebP is always lossless.
+    #[arg(long, value_name = "Q", value_parser = clap::value_parser!(u8).range(1..=100))]
+    quality: Option<u8>,
+    /// Decimal digits of SVG coordinates, up to 15 (overrides global.precision)
+    #[arg(long, value_name = "D", value_parser = clap::value_parser!(u8).range(0..=MAX_PRECISION as i64))]
+    precision: Option<u8>,
+    /// Group SVG paths by color and merge neighboring tiles of the same color (overrides global.compact)
+    #[arg(long)]
+    compact: bool,
+    /// Record the theme item of each tile so that 'recolor' can change the theme (overrides global.recolorable)
+    #[arg(long)]
+    recolorable: bool,
+    /// Output size relative to the frame (overrides global.output_width and global.output_height)
+    #[arg(long, value_name = "S", value_parser = parse_scale)]
+    scale: Option<f64>,
+    /// Draw raster images N times larger and average them down to hide seams between tiles
+    #[arg(long, value_name = "N", value_parser = clap::value_parser!(u64).range(1..=16))]
+    supersample: Option<u64>,
+    /// Set as wallpaper. Absolute path recommended for '--image'.
+    #[arg(long)]
+    set: bool,
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum Listing {
+    Themes,
+    Shapes,
+    Colors,
+}
+
+fn parse_verbosity(s: &str) -> Result<Verbosity, String> {
+    if s.is_empty() {
+        return Err(String::from("expected at least one flag"));
     }
+    s.parse().map_err(|e: wallrnd::Error| e.to_string())
+}
 
-    let verbose = args.verbose;
+fn parse_format(s: &str) -> Result<Format, String> {
+    s.to_lowercase()
+        .parse()
+        .map_err(|e: wallrnd::Error| e.to_string())
+}
 
-    if args.nice {
+/// Value of '--at'
+#[derive(Clone, Copy, Debug)]
+struct At {
+    time: NaiveDateTime,
+    /// Minutes east of UTC, if a time zone was given
+    utc_offset: Option<i32>,
+}
+
+fn parse_at(s: &str) -> Result<At, String> {
+    let zoned = chrono::DateTime::parse_from_rfc3339(s)
+        .or_else(|_| chrono::DateTime::parse_from_str(s, "%Y-%m-%dT%H:%M%:z"));
+    if let Ok(at) = zoned {
+        return Ok(At {
+            time: at.naive_local(),
+            utc_offset: Some(at.offset().local_minus_utc() / 60),
+        });
+    }
+    let naive = |time| At {
+        time,
+        utc_offset: None,
+    };
+    for fmt in &["%Y-%m-%dT%H:%M:%S", "%Y-%m-%dT%H:%M", "%Y-%m-%d %H:%M"] {
+        if let Ok(at) = NaiveDateTime::parse_from_str(s, fmt) {
+            return Ok(naive(at));
+        }
+    }
+    NaiveDate::parse_from_str(s, "%Y-%m-%d")
+        .ok()
+        .and_then(|d| d.and_hms_opt(0, 0, 0))
+        .map(naive)
+        .ok_or_else(|| String::from("expected a date and time such as 2024-12-25T08:30"))
+}
+
+fn parse_scale(s: &str) -> Result<f64, String> {
+    match s.parse::<f64>() {
+        Ok(x) if x > 0.0 && x.is_finite() => Ok(x),
+        Ok(_) => Err(String::from("expected a positive number")),
+        Err(e) => Err(e.to_string()),
+    }
+}
+
+fn main() {
+    let cli = Cli::parse();
+    let verbose = cli.verbose.unwrap_or_default();
+
+    if cli.nice {
         #[cfg(feature = "nice")]
         reduce_priority(verbose);
         #[cfg(not(feature = "nice"))]
         {
-            if verbose.warn {
-                println!("Feature 'nice' is not enabled, you cannot control process priority");
-            }
+            eprintln!("Feature 'nice' is not enabled, you cannot control process priority");
             exit(1);
         }
     }
 
-    if args.init != "" {
-        if verbose.prog {
-            println!("Initializing configuration file");
+    match cli.command.unwrap_or(Command::Generate(cli.generate)) {
+        Command::Generate(args) => generate(args, verbose),
+        Command::Init { file } => {
+            if verbose.prog {
+                println!("Initializing configuration file");
+            }
+            make_config_file(&file);
         }
-        make_config_file(&args.init[..]);
-        exit(0);
+        Command::Replay { log, output } => replay(&log, output, verbose),
+        Command::Validate { config, strict } => validate(&config, strict),
+        Command::Recolor {
+            svg,
+            theme,
+            config,
+            image,
+        } => recolor(&svg, &theme, config.as_deref(), image.as_deref(), verbose),
+        Command::List { what, config } => list(what, config.as_deref(), verbose),
     }
-
-    // Get local time and convert to app-specific format: HHMM
     if verbose.prog {
-        println!("Reading time");
+        println!("Process exited successfully");
     }
-    let time = args.time.unwrap_or_else(|| {
-        let now = Local::now();
-        let h = now.hour();
-        let m = now.minute();
-        let current = (h * 100 + m) as usize;
-        if verbose.info {
-            println!("Using current time: {}", current);
-        }
-        current
-    });
-    let dest = args.image;
-    let fname = args.config;
+}
 
-    if verbose.prog {
-        println!("Creating random number generator");
-    }
-    let mut rng = rand::thread_rng();
-    if verbose.prog {
-        println!("Attempting to open configuration file");
-    }
-    let cfg_file = File::open(fname);
-    let mut cfg_contents = String::new();
-    if let Ok(mut f) = cfg_file {
-        if let Err(e) = f.read_to_string(&mut cfg_contents) {
-            if verbose.warn {
-                println!("{}; Switching to default settings.", e);
-            }
+fn generate(args: Generate, verbose: Verbosity) {
+    let mut generator = Generator::new()
+        .config(load_config(args.config.as_deref(), verbose))
+        .diagnostics(&verbose);
+    if let Some(at) = args.at {
+        generator = generator.at(at.time);
+        if let Some(minutes) = at.utc_offset {
+            generator = generator.utc_offset(minutes);
         }
-    } else if verbose.warn {
-        println!("Settings file not found");
-    }
-    if verbose.prog {
-        println!("Choosing random settings according to configuration");
     }
-    let mut cfg = MetaConfig::from_string(cfg_contents, verbose).pick_cfg(&mut rng, time, verbose);
-
-    if verbose.prog {
-        println!("Building scene");
+    if let Some(time) = args.time {
+        generator = generator.time(time);
     }
-    let mut scene = Scene::new(&cfg, &mut rng, verbose);
-    let stroke = cfg.line_color;
-    let stroke_width = cfg.line_width;
-    let stroke_like_fill = stroke_width < 0.0001;
-
-    if args.load != "" {
-        let loader = Logger::load(&args.load);
-        let Logger { bg, objects, frame } = loader;
-        scene.bg = bg;
-        scene.items = objects;
-        cfg.frame = frame;
+    if let Some(seed) = args.seed {
+        generator = generator.seed(seed);
     }
+    let mut wallpaper = generator.build().unwrap_or_else(|e| {
+        eprintln!("An error occurred: {}", e);
+        exit(1);
+    });
+    // The scene file records how the image is actually written
+    apply_output(&mut wallpaper, &args.output);
 
-    if args.log != "" {
-        let logger = Logger {
-            bg: scene.bg.clone(),
-            objects: scene.items.clone(),
-            frame: cfg.frame,
-        };
-        logger.save(&args.log).unwrap_or_else(|_| {
+    if let Some(log) = args.log {
+        wallpaper.record().save(&log).unwrap_or_else(|e| {
             if verbose.warn {
-                println!("No valid destination specified");
+                println!("Could not save scene: {}", e);
             }
         });
     }
 
-    // Generate document
+    render(wallpaper, args.output, verbose);
+}
+
+fn replay(log: &str, output: Output, verbose: Verbosity) {
     if verbose.prog {
-        println!("Creating tiling");
+        println!("Loading scene");
     }
-    let mut document = Document::new(cfg.frame);
-    for (pos, elem) in cfg.make_tiling(&mut rng) {
-        let fill = scene.color(pos, &mut rng);
-        document.add(
-            elem.with_fill_color(fill)
-                .with_stroke_color(if stroke_like_fill { fill } else { stroke })
-                .with_stroke_width(stroke_width.max(0.1)),
-        );
+    let loader = Logger::load(log).unwrap_or_else(|e| {
+        eprintln!("Could not load scene: {}", e);
+        exit(1);
+    });
+    let mut wallpaper = Wallpaper::replay(loader);
+    apply_output(&mut wallpaper, &output);
+    render(wallpaper, output, verbose);
+}
+
+/// Options of the command line that override how the image is written
+fn apply_output(wallpaper: &mut Wallpaper, output: &Output) {
+    let res = &mut wallpaper.cfg.resolution;
+    if let Some(scale) = output.scale {
+        *res = Resolution {
+            supersample: res.supersample,
+            ..Resolution::scaled(&wallpaper.cfg.frame, scale)
+        };
+    }
+    if let Some(n) = output.supersample {
+        res.supersample = n as usize;
+    }
+    if let Some(p) = output.precision {
+        wallpaper.cfg.precision = Some(p as usize);
+    }
+    if output.compact {
+        wallpaper.cfg.compact = true;
     }
+    if output.recolorable {
+        wallpaper.cfg.recolorable = true;
+    }
+}
 
-    if dest == "" {
-        if verbose.prog {
-            println!("No destination specified");
-        }
+/// Draw wallpaper and save to destination
+fn render(wallpaper: Wallpaper, output: Output, verbose: Verbosity) {
+    // Checked before drawing, which can take a while
+    let dest = output.image.expect("--image is required");
+    let quality = output.quality;
+    let format = output
+        .format
+        .map(Ok)
+        .unwrap_or_else(|| Format::from_path(&dest))
+        .and_then(|format| match quality {
+            Some(quality) => format.with_quality(quality),
+            None => Ok(format),
+        })
+        .unwrap_or_else(|e| {
+            eprintln!("An error occurred: {}", e);
+            exit(1);
+        });
+
+    let document = wallpaper.render(&verbose).unwrap_or_else(|e| {
+        eprintln!("An error occurred: {}", e);
         exit(1);
-    }
+    });
 
     if verbose.prog {
         println!("Writing image to file");
     }
-    document.save(&(dest.clone() + ".tmp")).unwrap_or_else(|e| {
-        if verbose.warn {
-            println!("An error occured: {:?}", e);
-        }
+    let tmp = format!("{}.tmp", &dest);
+    document.save_as(&tmp, format).unwrap_or_else(|e| {
+        // Do not leave a partial image behind
+        let _ = std::fs::remove_file(&tmp);
+        eprintln!("An error occurred: {}", e);
         exit(1);
     });
-    #[allow(clippy::redundant_clone)]
-    // Reason: clone is NOT redundant when certain feature flags are used...
-    std::process::Command::new("mv")
-        .arg(format!("{}.tmp", &dest))
-        .arg(dest.clone())
-        .status()
-        .unwrap_or_else(|e| {
-            if verbose.warn {
-                println!("An error occured: {}", e);
-            }
-            exit(1);
-        });
-    if args.set {
+    std::fs::rename(&tmp, &dest).unwrap_or_else(|e| {
+        eprintln!("An error occurred: {}", e);
+        exit(1);
+    });
+    if output.set {
         #[cfg(feature = "set-wallpaper")]
         {
             // The following code includes functionality from a crate licensed under GPL 3.0
@@ -160,25 +377,19 @@ fn main() {
             }
             use wallpaper_rs::{Desktop, DesktopEnvt};
             let envt = DesktopEnvt::new().unwrap_or_else(|_| {
-                if verbose.warn {
-                    println!("Unable to detect desktop environment");
-                }
+                eprintln!("Unable to detect desktop environment");
                 exit(1);
             });
             let imgdir = std::path::PathBuf::from(&dest);
             let canon = std::fs::canonicalize(&imgdir)
                 .unwrap_or_else(|_| {
-                    if verbose.warn {
-                        println!("Could not resolve path");
-                    }
+                    eprintln!("Could not resolve path");
                     exit(1);
                 })
                 .into_os_string()
                 .into_string()
                 .unwrap_or_else(|_| {
-                    if verbose.warn {
-                        println!("Invalid file name");
-                    }
+                    eprintln!("Invalid file name");
                     exit(1);
                 });
             if verbose.info {
@@ -193,117 +404,148 @@ fn main() {
         }
         #[cfg(not(feature = "set-wallpaper"))]
         {
-            if verbose.warn {
-                println!("You have not selected the set-wallpaper functionality");
-                println!("Make sure to include the feature 'set-wallpaper' to access this option");
-                println!("See 'https://doc.rust-lang.org/cargo/reference/features.html' to learn how to do it");
-            }
+            eprintln!("You have not selected the set-wallpaper functionality");
+            eprintln!("Make sure to include the feature 'set-wallpaper' to access this option");
+            eprintln!("See 'https://doc.rust-lang.org/cargo/reference/features.html' to learn how to do it");
             exit(1);
         }
     }
+}
+
+/// Read the configuration file and the files it includes, or if there is none
+/// the system, user and project configurations. Default settings if nothing can be read.
+fn load_config(fname: Option<&str>, verbose: Verbosity) -> MetaConfig {
     if verbose.prog {
-        println!("Process exited successfully");
+        println!("Attempting to open configuration file");
     }
+    let paths = match fname {
+        Some(f) => vec![PathBuf::from(f)],
+        None => {
+            let found = layers::discover();
+            if found.is_empty() {
+                eprintln!("No configuration file found, using default settings. Looked for:");
+                for path in layers::candidates() {
+                    eprintln!("  {}", path.display());
+                }
+                eprintln!("Create one with 'wallrnd init'");
+                return MetaConfig::default();
+            }
+            if verbose.info {
+                for path in &found {
+                    println!("Using configuration file {}", path.display());
+                }
+            }
+            found
+        }
+    };
+    MetaConfig::load_all(&paths).unwrap_or_else(|e| {
+        eprintln!("{}; Switching to default settings.", e);
+        MetaConfig::default()
+    })
 }
 
-#[derive(Default)]
-struct Args {
-    help: bool,
-    set: bool,
-    nice: bool,
-    verbose: Verbosity,
-    time: Option<usize>,
-    log: String,
-    load: String,
-    image: String,
-    config: String,
-    init: String,
+fn recolor(src: &str, name: &str, config: Option<&str>, dest: Option<&str>, verbose: Verbosity) {
+    let cfg = load_config(config, verbose);
+    let themes = cfg.theme_list(&cfg.color_list(&verbose), &verbose);
+    let theme = themes.get(name).unwrap_or_else(|| {
+        eprintln!("Theme '{}' does not exist, see 'wallrnd list themes'", name);
+        exit(1);
+    });
+    let recolored = std::fs::read_to_string(src)
+        .map_err(wallrnd::Error::from)
+        .and_then(|svg| wallrnd::palette::recolor(&svg, theme))
+        .unwrap_or_else(|e| {
+            eprintln!("An error occurred: {}", e);
+            exit(1);
+        });
+    let dest = dest.unwrap_or(src);
+    if verbose.prog {
+        println!("Writing image to file");
+    }
+    std::fs::write(dest, recolored).unwrap_or_else(|e| {
+        eprintln!("An error occurred: {}", e);
+        exit(1);
+    });
+}
+
+fn validate(fname: &str, strict: bool) {
+    let diagnostics = wallrnd::validate::validate_file(Path::new(fname)).unwrap_or_else(|e| {
+        eprintln!("{}: {}", fname, e);
+        exit(1);
+    });
+    for d in &diagnostics {
+        println!("{}: {}", fname, d);
+    }
+    let errors = diagnostics
+        .iter()
+        .filter(|d| d.severity == Severity::Error)
+        .count();
+    let warnings = diagnostics.len() - errors;
+    if errors == 0 && warnings == 0 {
+        println!("{}: valid configuration", fname);
+    } else {
+        println!("{}: {} error(s), {} warning(s)", fname, errors, warnings);
+    }
+    if errors > 0 || (strict && warnings > 0) {
+        exit(1);
+    }
 }
 
-fn read_command_line_arguments() -> Args {
-    let mut args = Args::default();
-    let args_split = env::args().collect::<Vec<_>>();
-    let mut it = args_split.iter().skip(1).flat_map(|s| s.split('='));
+fn sorted<V>(map: &std::collections::HashMap<String, V>) -> Vec<&String> {
+    let mut keys = map.keys().collect::<Vec<_>>();
+    keys.sort();
+    keys
+}
 
-    loop {
-        match it.next().as_deref() {
-            None => return args,
-            Some("--help") => args.help = true,
-            Some("--log") => {
-                args.log = it
-                    .next()
-                    .unwrap_or_else(|| {
-                        panic!("Option --log should be followed by a destination file")
-                    })
-                    .to_string()
-            }
-            Some("--load") => {
-                args.load = it
-                    .next()
-                    .unwrap_or_else(|| {
-                        panic!("Option --load should be followed by a source file")
-                    })
-                    .to_string()
+fn list(what: Listing, config: Option<&str>, verbose: Verbosity) {
+    let cfg = load_config(config, verbose);
+    match what {
+        Listing::Colors => {
+            let colors = cfg.color_list(&verbose);
+            for name in sorted(&colors) {
+                println!("{} = {}", name, colors[name]);
             }
-            Some("--verbose") => args.verbose = Verbosity::from(&it.next().unwrap_or_else(|| panic!("Option --verbose should be followed by a verbosity descriptor: '^[PDIWA]*$',
-P: Progress
-D: Details
-I: Info
-W: Warnings
-A: All"))[..]),
-            Some("--init") => {
-                args.init = it
-                    .next()
-                    .unwrap_or_else(|| panic!("Option --init should be followed by a source file"))
-                    .to_string()
+        }
+        Listing::Themes => {
+            let themes = cfg.theme_list(&cfg.color_list(&verbose), &verbose);
+            for name in sorted(&themes) {
+                println!("{}", name);
             }
-            Some("--time") => {
-                args.time = Some(
-                    it.next()
-                        .unwrap_or_else(|| {
-                            panic!("Option --time should be followed by a timestamp.")
-                        })
-                        .parse()
-                        .unwrap_or_else(|e| panic!("Failed to parse time: {}", e)),
-                )
+        }
+        Listing::Shapes => {
+            println!("Tilings:");
+            for (names, shape) in SHAPE_NAMES {
+                if let Shape::Tiling(_) = shape {
+                    println!("  {}", names.join(" / "));
+                }
             }
-            Some("--image") => {
-                args.image = it
-                    .next()
-                    .unwrap_or_else(|| {
-                        panic!("Option --image should be followed by a destination file")
-                    })
-                    .to_string()
+            println!("Patterns:");
+            for (names, shape) in SHAPE_NAMES {
+                if let Shape::Pattern(_) = shape {
+                    println!("  {}", names.join(" / "));
+                }
             }
-            Some("--config") => {
-                args.config = it
-                    .next()
-                    .unwrap_or_else(|| {
-                        panic!("Option --config should be followed by a source file")
-                    })
-                    .to_string()
+            let shapes = cfg.shape_list(&verbose);
+            if !shapes.is_empty() {
+                println!("Combinations:");
+                for name in sorted(&shapes) {
+                    println!("  {}", name);
+                }
             }
-            Some("--set") => args.set = true,
-            Some("--nice") => args.nice = true,
-            Some(o) => panic!("Unknown option {}", o),
         }
     }
 }
 
-fn print_help() {
-    print!(include_str!("../assets/man"));
-}
-
 fn make_config_file(fname: &str) {
     let mut buffer = std::fs::File::create(fname).unwrap_or_else(|e| {
-        println!("Error creating configuration: {}", e);
+        eprintln!("Error creating configuration: {}", e);
         exit(1);
     });
     let sample_cfg = include_str!("../assets/default.toml");
     buffer
         .write_all(&sample_cfg.to_string().into_bytes())
         .unwrap_or_else(|e| {
-            println!("Error writing configuration: {}", e);
+            eprintln!("Error writing configuration: {}", e);
             exit(1);
         });
 }
@@ -316,17 +558,13 @@ fn reduce_priority(verbose: Verbosity) {
         println!("Current priority: {:?}", base);
     }
     let background_priority = base.lower().next().unwrap_or_else(|| {
-        if verbose.warn {
-            println!("No lower priority available");
-        }
+        eprintln!("No lower priority available");
         exit(1);
     });
     Process::current()
         .set_priority(background_priority)
         .unwrap_or_else(|_| {
-            if verbose.warn {
-                println!("Failed to lower priority");
-            }
+            eprintln!("Failed to lower priority");
             exit(1);
         });
     if verbose.info {
@@ -1,9 +1,13 @@
 use crate::cfg::SceneCfg;
 use crate::prelude::*;
 use crate::scene::*;
-use rand::{rngs::ThreadRng, Rng};
+use rand::Rng;
 
-pub fn create_free_circles(rng: &mut ThreadRng, cfg: &SceneCfg, verbose: Verbosity) -> Vec<Disc> {
+pub fn create_free_circles<R: Rng>(
+    rng: &mut R,
+    cfg: &SceneCfg,
+    diag: &dyn Diagnostics,
+) -> Vec<Disc> {
     let mut items = Vec::new();
     for i in 1..=cfg.nb_pattern {
         let c = cfg.choose_color(rng);
@@ -14,17 +18,15 @@ pub fn create_free_circles(rng: &mut ThreadRng, cfg: &SceneCfg, verbose: Verbosi
             i as f64 / cfg.nb_pattern as f64 * 0.5,
         ));
     }
-    items.sort_by(|a, b| a.radius.partial_cmp(&b.radius).unwrap());
-    if verbose.details {
-        println!("{:#?}", items);
-    }
+    items.sort_by(|a, b| a.radius.total_cmp(&b.radius));
+    diag.details(format_args!("{:#?}", items));
     items
 }
 
-pub fn create_free_triangles(
-    rng: &mut ThreadRng,
+pub fn create_free_triangles<R: Rng>(
+    rng: &mut R,
     cfg: &SceneCfg,
-    verbose: Verbosity,
+    diag: &dyn Diagnostics,
 ) -> Vec<Triangle> {
     let mut items = Vec::new();
     for i in 1..=cfg.nb_pattern {
@@ -36,48 +38,50 @@ pub fn create_free_triangles(
             i as f64 / cfg.nb_pattern as f64 * 0.7,
         ));
     }
-    items.sort_by(|a, b| a.radius.partial_cmp(&b.radius).unwrap());
+    items.sort_by(|a, b| a.radius.total_cmp(&b.radius));
     let items = items
         .into_iter()
         .map(|d| Triangle::random(rng, d))
         .collect::<Vec<_>>();
-    if verbose.details {
-        println!("{:#?}", items);
-    }
+    diag.details(format_args!("{:#?}", items));
     items
 }
 
-pub fn create_free_stripes(rng: &mut ThreadRng, cfg: &SceneCfg, verbose: Verbosity) -> Vec<Stripe> {
+pub fn create_free_stripes<R: Rng>(
+    rng: &mut R,
+    cfg: &SceneCfg,
+    diag: &dyn Diagnostics,
+) -> Vec<Stripe> {
     let mut items = Vec::new();
     for _ in 0..cfg.nb_pattern {
         let c = cfg.choose_color(rng);
         let w = cfg.width_pattern * cfg.frame.h as f64 * (rng.gen::<f64>() + 0.5);
         items.push(Stripe::random(rng, &cfg.frame, c, w));
     }
-    if verbose.details {
-        println!("{:#?}", items);
-    }
+    diag.details(format_args!("{:#?}", items));
     items
 }
 
-pub fn create_free_spirals(rng: &mut ThreadRng, cfg: &SceneCfg, verbose: Verbosity) -> Vec<Spiral> {
+pub fn create_free_spirals<R: Rng>(
+    rng: &mut R,
+    cfg: &SceneCfg,
+    diag: &dyn Diagnostics,
+) -> Vec<Spiral> {
     let mut items = Vec::new();
     for _ in 0..cfg.nb_pattern {
         let c = cfg.choose_color(rng);
         let w = cfg.width_pattern * cfg.frame.h as f64 * (rng.gen::<f64>() + 0.5);
         items.push(Spiral::random(rng, &cfg.frame, c, w, cfg.tightness_spiral));
     }
-    items.sort_by(|a, b| a.width.partial_cmp(&b.width).unwrap());
-    if verbose.details {
-        println!("{:#?}", items);
-    }
+    items.sort_by(|a, b| a.width.total_cmp(&b.width));
+    diag.details(format_args!("{:#?}", items));
     items
 }
 
-pub fn create_concentric_circles(
-    rng: &mut ThreadRng,
+pub fn create_concentric_circles<R: Rng>(
+    rng: &mut R,
     cfg: &SceneCfg,
-    verbose: Verbosity,
+    diag: &dyn Diagnostics,
 ) -> Vec<Disc> {
     let mut items = Vec::new();
     let center = Pos::random(&cfg.frame, rng);
@@ -93,17 +97,15 @@ pub fn create_concentric_circles(
             color: cfg.choose_color(rng),
         })
     }
-    items.sort_by(|a, b| a.radius.partial_cmp(&b.radius).unwrap());
-    if verbose.details {
-        println!("{:#?}", items);
-    }
+    items.sort_by(|a, b| a.radius.total_cmp(&b.radius));
+    diag.details(format_args!("{:#?}", items));
     items
 }
 
-pub fn create_parallel_stripes(
-    rng: &mut ThreadRng,
+pub fn create_parallel_stripes<R: Rng>(
+    rng: &mut R,
     cfg: &SceneCfg,
-    verbose: Verbosity,
+    diag: &dyn Diagnostics,
 ) -> Vec<HalfPlane> {
     let mut items = Vec::new();
     let (a, b, dir) = {
@@ -124,16 +126,14 @@ pub fn create_parallel_stripes(
             c,
         ));
     }
-    if verbose.details {
-        println!("{:#?}", items);
-    }
+    diag.details(format_args!("{:#?}", items));
     items
 }
 
-pub fn create_crossed_stripes(
-    rng: &mut ThreadRng,
+pub fn create_crossed_stripes<R: Rng>(
+    rng: &mut R,
     cfg: &SceneCfg,
-    verbose: Verbosity,
+    diag: &dyn Diagnostics,
 ) -> Vec<HalfPlane> {
     let mut items = Vec::new();
     let (a, b, a_orth, b_orth, dir) = {
@@ -163,13 +163,11 @@ pub fn create_crossed_stripes(
             c,
         ));
     }
-    if verbose.details {
-        println!("{:#?}", items);
-    }
+    diag.details(format_args!("{:#?}", items));
     items
 }
 
-pub fn create_waves(rng: &mut ThreadRng, cfg: &SceneCfg, verbose: Verbosity) -> Vec<Wave> {
+pub fn create_waves<R: Rng>(rng: &mut R, cfg: &SceneCfg, diag: &dyn Diagnostics) -> Vec<Wave> {
     let mut items = Vec::new();
     let (a, b, dir) = {
         let c = cfg.frame.center();
@@ -191,13 +189,15 @@ pub fn create_waves(rng: &mut ThreadRng, cfg: &SceneCfg, verbose: Verbosity) ->
             c,
         ));
     }
-    if verbose.details {
-        println!("{:#?}", items);
-    }
+    diag.details(format_args!("{:#?}", items));
     items
 }
 
-pub fn create_sawteeth(rng: &mut ThreadRng, cfg: &SceneCfg, verbose: Verbosity) -> Vec<Sawtooth> {
+pub fn create_sawteeth<R: Rng>(
+    rng: &mut R,
+    cfg: &SceneCfg,
+    diag: &dyn Diagnostics,
+) -> Vec<Sawtooth> {
     let mut items = Vec::new();
     let (a, b, dir) = {
         let c = cfg.frame.center();
@@ -219,8 +219,6 @@ pub fn create_sawteeth(rng: &mut ThreadRng, cfg: &SceneCfg, verbose: Verbosity)
             c,
         ));
     }
-    if verbose.details {
-        println!("{:#?}", items);
-    }
+    diag.details(format_args!("{:#?}", items));
     items
 }
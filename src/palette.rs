@@ -0,0 +1,311 @@
+//! Recolorable SVG output: each tile records the theme item it came from
+//! and how far its color is from it, so that another theme can be applied.
+
+use crate::prelude::*;
+use crate::svg::Document;
+use crate::{Error, Result};
+use std::fmt;
+
+/// Color of a tile along with where it came from
+#[derive(Clone, Copy, Debug)]
+pub struct Tint {
+    pub color: Color,
+    pub slot: Option<Slot>,
+}
+
+/// Theme item (or salt of a theme item) that a color was derived from
+#[derive(Clone, Copy, Debug)]
+pub struct Slot {
+    /// Position of the item in the theme of the scene
+    pub theme: usize,
+    /// Position in the salt of the item, if the color was salted
+    pub salt: Option<usize>,
+    /// Color of the item or of the salt
+    pub base: Color,
+    /// Share of `base` in the color, between 0 and 1
+    pub weight: f64,
+}
+
+impl Slot {
+    /// Name of the theme item (and salt), as stored in the image
+    fn name(&self) -> String {
+        match self.salt {
+            Some(s) => format!("t{}s{}", self.theme, s),
+            None => format!("t{}", self.theme),
+        }
+    }
+
+    /// What is left of `color` once the share of `base` is removed
+    fn offset(&self, color: Color) -> (f64, f64, f64) {
+        let (c, b, w) = (color.validate(), self.base.validate(), self.weight);
+        // Two decimals are enough for rounding to give back `color`
+        let d = |c: usize, b: usize| ((c as f64 - w * b as f64) * 100.).round() / 100.;
+        (d(c.0, b.0), d(c.1, b.1), d(c.2, b.2))
+    }
+}
+
+/// Color of a tile made from `base` with the share `weight` and offset `(r, g, b)`
+fn mix(base: Color, weight: f64, (r, g, b): (f64, f64, f64)) -> Color {
+    let base = base.validate();
+    let c = |b: usize, d: f64| (weight * b as f64 + d).round().clamp(0., 255.) as usize;
+    Color(c(base.0, r), c(base.1, g), c(base.2, b))
+}
+
+impl Document {
+    /// SVG where tiles also store the theme item they come from,
+    /// used instead of `Display` when `recolorable` is set
+    pub(crate) fn fmt_recolorable(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for p in &self.items {
+            let slot = match p.slot {
+                Some(slot) => slot,
+                // Not derived from the theme, the color stays as is
+                None => {
+                    match self.precision {
+                        Some(n) => writeln!(f, "{:.*}", n, p)?,
+                        None => writeln!(f, "{}", p)?,
+                    }
+                    continue;
+                }
+            };
+            // Colors are written in full so that any viewer can draw the image,
+            // `recolor` computes them again from the attributes
+            let (r, g, b) = slot.offset(p.fill_color);
+            write!(
+                f,
+                "<path {}=\"{}\" {}=\"{} {} {} {}\" d=\"",
+                SLOT,
+                slot.name(),
+                TINT,
+                slot.weight,
+                r,
+                g,
+                b
+            )?;
+            match self.precision {
+                Some(n) => write!(f, "{:.*}", n, p.data)?,
+                None => write!(f, "{}", p.data)?,
+            }
+            writeln!(
+                f,
+                "\" fill=\"{}\" stroke=\"{}\" stroke-width=\"{}\" />",
+                p.fill_color, p.stroke_color, p.stroke_width
+            )?;
+        }
+        Ok(())
+    }
+}
+
+/// Attribute of recolorable tiles that names their theme item
+const SLOT: &str = "data-slot";
+/// Attribute of recolorable tiles with the share of the theme item and the offset
+const TINT: &str = "data-tint";
+
+/// Value of the attribute `name` in an SVG tag
+fn attribute<'s>(tag: &'s str, name: &str) -> Option<&'s str> {
+    let start = tag.find(&format!(" {}=\"", name))? + name.len() + 3;
+    let len = tag[start..].find('"')?;
+    Some(&tag[start..start + len])
+}
+
+/// Apply the colors of `theme` to an SVG written with `recolorable` set.
+/// Theme items are matched by position and wrap around if `theme` is shorter
+/// than the original one. Tiles from salts that `theme` does not have keep their color.
+pub fn recolor(svg: &str, theme: &Chooser<ThemeItem>) -> Result<String> {
+    let items = theme.extract();
+    let mut found = false;
+    let mut out = String::with_capacity(svg.len());
+    for line in svg.split_inclusive('\n') {
+        let name = match attribute(line, SLOT) {
+            Some(name) => name,
+            None => {
+                out.push_str(line);
+                continue;
+            }
+        };
+        found = true;
+        let (item, salt) = parse_name(name).ok_or(Error::NoPalette)?;
+        let tint = attribute(line, TINT)
+            .and_then(parse_tint)
+            .ok_or(Error::NoPalette)?;
+        let base = match items.get(item % items.len().max(1)) {
+            Some((ThemeItem(c, _, _, s), _)) => match salt {
+                None => Some(*c),
+                Some(j) => s.0.get(j).map(|s| s.color),
+            },
+            None => None,
+        };
+        let (fill, stroke) = (attribute(line, "fill"), attribute(line, "stroke"));
+        match (base, fill) {
+            (Some(base), Some(fill)) => {
+                let (weight, offset) = tint;
+                let new = mix(base, weight, offset).to_string();
+                let mut line = line.replacen(
+                    &format!(" fill=\"{}\"", fill),
+                    &format!(" fill=\"{}\"", new),
+                    1,
+                );
+                // Lines the color of the tile follow it
+                if stroke == Some(fill) {
+                    line = line.replacen(
+                        &format!(" stroke=\"{}\"", fill),
+                        &format!(" stroke=\"{}\"", new),
+                        1,
+                    );
+                }
+                out.push_str(&line);
+            }
+            _ => out.push_str(line),
+        }
+    }
+    if found {
+        Ok(out)
+    } else {
+        Err(Error::NoPalette)
+    }
+}
+
+/// Weight and offset from the value of `data-tint`
+fn parse_tint(value: &str) -> Option<(f64, (f64, f64, f64))> {
+    let v = value
+        .split(' ')
+        .map(|x| x.parse().ok())
+        .collect::<Option<Vec<f64>>>()?;
+    match v[..] {
+        [w, r, g, b] => Some((w, (r, g, b))),
+        _ => None,
+    }
+}
+
+/// Theme item and salt of a slot from its name `t<I>` or `t<I>s<J>`
+fn parse_name(name: &str) -> Option<(usize, Option<usize>)> {
+    let rest = name.strip_prefix('t')?;
+    match rest.split_once('s') {
+        Some((i, j)) => Some((i.parse().ok()?, Some(j.parse().ok()?))),
+        None => Some((rest.parse().ok()?, None)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::svg::{Data, Path};
+
+    const RED: Color = Color(200, 30, 40);
+    const BLUE: Color = Color(20, 40, 220);
+    const SALT: Color = Color(250, 250, 0);
+
+    fn slot(theme: usize, salt: Option<usize>, base: Color, weight: f64) -> Slot {
+        Slot {
+            theme,
+            salt,
+            base,
+            weight,
+        }
+    }
+
+    fn theme(items: &[(Color, &[Color])]) -> Chooser<ThemeItem> {
+        Chooser::new(
+            items
+                .iter()
+                .map(|(c, salt)| {
+                    let salt = salt
+                        .iter()
+                        .map(|&color| SaltItem {
+                            color,
+                            likeliness: 0.1,
+                            variability: 0,
+                        })
+                        .collect();
+                    (ThemeItem(*c, None, None, Salt(salt)), 1)
+                })
+                .collect(),
+        )
+    }
+
+    fn document(recolorable: bool) -> Document {
+        let mut doc = Document::new(Frame {
+            x: 0,
+            y: 0,
+            w: 10,
+            h: 10,
+        });
+        doc.recolorable = recolorable;
+        let tiles = [
+            (Color(150, 60, 70), Some(slot(0, None, RED, 0.6))),
+            (Color(40, 52, 190), Some(slot(1, None, BLUE, 0.8))),
+            (Color(245, 255, 3), Some(slot(0, Some(0), SALT, 1.))),
+            (Color(1, 2, 3), None),
+        ];
+        for (i, &(color, slot)) in tiles.iter().enumerate() {
+            let x = i as f64;
+            doc.add(
+                Path::new(
+                    Data::new(Pos(x, 0.))
+                        .with_line_to(Pos(x + 1., 0.))
+                        .with_line_to(Pos(x, 1.)),
+                )
+                .with_fill_color(color)
+                // The last tile has lines of another color
+                .with_stroke_color(if slot.is_some() { color } else { RED })
+                .with_stroke_width(0.1)
+                .with_slot(slot),
+            );
+        }
+        doc
+    }
+
+    fn fills(svg: &str) -> Vec<&str> {
+        svg.lines().filter_map(|l| attribute(l, "fill")).collect()
+    }
+
+    #[test]
+    fn offsets_give_back_colors() {
+        for weight in &[0., 0.33, 0.5, 0.87, 1.] {
+            for c in (0..=255).step_by(5) {
+                let color = Color(c, 255 - c, (c * 7) % 256);
+                let slot = slot(0, None, Color(123, 7, 250), *weight);
+                assert_eq!(mix(slot.base, *weight, slot.offset(color)), color);
+            }
+        }
+    }
+
+    #[test]
+    fn recolorable_colors_are_plain() {
+        let (plain, recolorable) = (document(false).to_string(), document(true).to_string());
+        assert_eq!(fills(&plain), fills(&recolorable));
+        assert!(!recolorable.contains("var("));
+        assert!(recolorable.contains(" data-slot=\"t0s0\" data-tint=\"1 -5 5 3\" "));
+        // Recoloring with the original theme changes nothing
+        let original = theme(&[(RED, &[SALT]), (BLUE, &[])]);
+        assert_eq!(recolor(&recolorable, &original).unwrap(), recolorable);
+    }
+
+    #[test]
+    fn recolor_with_another_theme() {
+        let svg = document(true).to_string();
+        let green = Color(0, 200, 0);
+        let recolored = recolor(&svg, &theme(&[(green, &[])])).unwrap();
+        let fills = fills(&recolored);
+        // Offsets are kept, theme items wrap around, salts that are missing are kept
+        assert_eq!(fills[0], Color(30, 162, 46).to_string());
+        assert_eq!(fills[1], Color(24, 180, 14).to_string());
+        assert_eq!(fills[2], Color(245, 255, 3).to_string());
+        assert_eq!(fills[3], Color(1, 2, 3).to_string());
+        // Lines the color of the tile follow it, others do not
+        let strokes = recolored
+            .lines()
+            .filter_map(|l| attribute(l, "stroke"))
+            .collect::<Vec<_>>();
+        assert_eq!(strokes[..2], fills[..2]);
+        assert_eq!(strokes[3], RED.to_string());
+    }
+
+    #[test]
+    fn plain_images_are_rejected() {
+        let svg = document(false).to_string();
+        assert!(matches!(
+            recolor(&svg, &theme(&[(RED, &[])])),
+            Err(Error::NoPalette)
+        ));
+    }
+}
@@ -1,11 +1,10 @@
 use crate::prelude::*;
-use rand::{rngs::ThreadRng, Rng};
-use std::cmp::{Eq, PartialEq};
+use rand::Rng;
+use serde_derive::{Deserialize, Serialize};
 use std::f64::consts::PI;
-use std::hash::{Hash, Hasher};
 use std::ops::{Add, Mul, Neg, Sub};
 
-#[derive(Clone, Copy, Debug)]
+#[derive(Clone, Copy, Debug, Serialize, Deserialize)]
 pub struct Pos(pub f64, pub f64);
 
 impl Pos {
@@ -13,14 +12,6 @@ impl Pos {
         (self.0, self.1)
     }
 
-    // Nearest 100'th of unit
-    pub fn round(self) -> (i32, i32) {
-        (
-            (self.0 * 100.).round() as i32,
-            (self.1 * 100.).round() as i32,
-        )
-    }
-
     pub fn norm(self) -> f64 {
         self.dot_self().sqrt()
     }
@@ -37,7 +28,7 @@ impl Pos {
         self.0 * other.0 + self.1 * other.1
     }
 
-    pub fn random(f: &Frame, rng: &mut ThreadRng) -> Self {
+    pub fn random<R: Rng>(f: &Frame, rng: &mut R) -> Self {
         let errx = f.w as f64 / 10.;
         let erry = f.h as f64 / 10.;
         let x = f.x as f64 - errx + rng.gen::<f64>() * f.w as f64 * 1.2;
@@ -59,7 +50,9 @@ impl Pos {
         Pos(r * theta.cos(), r * theta.sin())
     }
 
-    pub fn intersect((pos1, rot1): (Self, isize), (pos2, rot2): (Self, isize)) -> Self {
+    /// Where the line through `pos1` at angle `rot1` meets the one through `pos2` at angle `rot2`,
+    /// None if they are (almost) parallel
+    pub fn intersect((pos1, rot1): (Self, isize), (pos2, rot2): (Self, isize)) -> Option<Self> {
         let pos1b = pos1 + Pos::polar(rot1, 1.);
         let pos2b = pos2 + Pos::polar(rot2, 1.);
 
@@ -71,7 +64,7 @@ impl Pos {
         let inv = {
             let div = det(dx, dy);
             if div.abs() < 0.01 {
-                panic!("Malformed intersection");
+                return None;
             }
             1. / div
         };
@@ -79,7 +72,7 @@ impl Pos {
         let d = Pos(det(pos1, pos1b), det(pos2, pos2b));
         let x = det(d, dx) * inv;
         let y = det(d, dy) * inv;
-        Pos(x, y)
+        Some(Pos(x, y))
     }
 
     pub fn zero() -> Self {
@@ -126,7 +119,7 @@ impl Mul<isize> for Pos {
 impl Mul<f64> for Pos {
     type Output = Self;
     fn mul(self, x: f64) -> Self::Output {
-        Pos(self.0 * x as f64, self.1 * x as f64)
+        Pos(self.0 * x, self.1 * x)
     }
 }
 
@@ -136,17 +129,3 @@ impl Neg for Pos {
         Self(-self.0, -self.1)
     }
 }
-
-impl PartialEq for Pos {
-    fn eq(&self, other: &Self) -> bool {
-        self.round() == other.round()
-    }
-}
-
-impl Eq for Pos {}
-
-impl Hash for Pos {
-    fn hash<H: Hasher>(&self, state: &mut H) {
-        self.round().hash(state)
-    }
-}
@@ -0,0 +1,582 @@
+use crate::format::Format;
+use crate::prelude::*;
+use crate::svg::{Document, Path};
+use crate::{Error, Result};
+use std::fs::File;
+use std::io::{BufWriter, Write};
+
+/// RGBA image, channels are premultiplied by alpha and range from 0 to 1
+pub struct Canvas {
+    pub width: usize,
+    pub height: usize,
+    pixels: Vec<[f32; 4]>,
+    /// Vertical position of the first row, when only a band of a larger image is drawn
+    top: f32,
+    /// Reused between polygons to accumulate coverage
+    scratch: Vec<f32>,
+}
+
+impl Canvas {
+    /// Fully transparent canvas
+    pub fn new(width: usize, height: usize) -> Self {
+        Self {
+            width,
+            height,
+            pixels: vec![[0.0; 4]; width * height],
+            top: 0.0,
+            scratch: Vec::new(),
+        }
+    }
+
+    /// Paint the union of several polygons (nonzero rule) with a single color.
+    /// Edges are anti-aliased according to the exact area of each pixel that is covered.
+    pub fn fill(&mut self, polygons: &[Vec<(f32, f32)>], color: Color) {
+        let (mut xmin, mut ymin) = (f32::INFINITY, f32::INFINITY);
+        let (mut xmax, mut ymax) = (f32::NEG_INFINITY, f32::NEG_INFINITY);
+        let top = self.top;
+        for &(x, y) in polygons.iter().flatten() {
+            let y = y - top;
+            xmin = xmin.min(x);
+            xmax = xmax.max(x);
+            ymin = ymin.min(y);
+            ymax = ymax.max(y);
+        }
+        // Empty, degenerate or entirely out of the canvas
+        if !(xmin < xmax && ymin < ymax)
+            || xmax <= 0.0
+            || ymax <= 0.0
+            || xmin >= self.width as f32
+            || ymin >= self.height as f32
+        {
+            return;
+        }
+        // Coverage is accumulated in a buffer that spans the bounding box,
+        // only the rows are clipped: columns to the left of the canvas
+        // still contribute to the running sum of each row
+        let (ox, oy) = (xmin.floor(), ymin.max(0.0).floor());
+        let w = (xmax.ceil() - ox) as usize;
+        let h = (ymax.min(self.height as f32).ceil() - oy) as usize;
+        let stride = w + 2;
+        self.scratch.clear();
+        self.scratch.resize(stride * h, 0.0);
+        for poly in polygons {
+            for (i, &(x0, y0)) in poly.iter().enumerate() {
+                let (x1, y1) = poly[(i + 1) % poly.len()];
+                accumulate_line(
+                    &mut self.scratch,
+                    stride,
+                    h,
+                    (x0 - ox, y0 - top - oy),
+                    (x1 - ox, y1 - top - oy),
+                );
+            }
+        }
+        let Color(r, g, b) = color;
+        let rgb = [r as f32 / 255., g as f32 / 255., b as f32 / 255.];
+        let (ox, oy) = (ox as isize, oy as usize);
+        for row in 0..h {
+            let y = oy + row;
+            let mut sum = 0.0;
+            for col in 0..stride {
+                sum += self.scratch[row * stride + col];
+                let x = ox + col as isize;
+                if x < 0 || x as usize >= self.width {
+                    continue;
+                }
+                let cov = sum.abs().min(1.0);
+                if cov > 0.0 {
+                    let px = &mut self.pixels[y * self.width + x as usize];
+                    for c in 0..3 {
+                        px[c] = rgb[c] * cov + px[c] * (1.0 - cov);
+                    }
+                    px[3] = cov + px[3] * (1.0 - cov);
+                }
+            }
+        }
+    }
+
+    /// Draw the outline of a polygon
+    pub fn stroke(&mut self, poly: &[(f32, f32)], width: f32, color: Color) {
+        // Each edge becomes a rectangle, extended by half the width at both ends
+        // so that corners are filled. All rectangles have the same orientation
+        // hence overlaps do not cancel out.
+        let half = width / 2.0;
+        let mut quads = Vec::with_capacity(poly.len());
+        for (i, &(x0, y0)) in poly.iter().enumerate() {
+            let (x1, y1) = poly[(i + 1) % poly.len()];
+            let len = ((x1 - x0).powi(2) + (y1 - y0).powi(2)).sqrt();
+            if len == 0.0 {
+                continue;
+            }
+            let (dx, dy) = ((x1 - x0) / len * half, (y1 - y0) / len * half);
+            let (nx, ny) = (-dy, dx);
+            let (ax, ay) = (x0 - dx, y0 - dy);
+            let (bx, by) = (x1 + dx, y1 + dy);
+            quads.push(vec![
+                (ax + nx, ay + ny),
+                (bx + nx, by + ny),
+                (bx - nx, by - ny),
+                (ax - nx, ay - ny),
+            ]);
+        }
+        self.fill(&quads, color);
+    }
+
+    /// Average blocks of `k` by `k` pixels into the rows of `dest` starting at `row`
+    fn downsample_into(&self, k: usize, dest: &mut Canvas, row: usize) {
+        let norm = 1.0 / (k * k) as f32;
+        for y in 0..self.height / k {
+            for x in 0..dest.width {
+                let mut acc = [0.0; 4];
+                for sy in 0..k {
+                    let line = (y * k + sy) * self.width + x * k;
+                    for px in &self.pixels[line..line + k] {
+                        for (a, p) in acc.iter_mut().zip(px) {
+                            *a += p;
+                        }
+                    }
+                }
+                dest.pixels[(row + y) * dest.width + x] = acc.map(|a| a * norm);
+            }
+        }
+    }
+
+    /// Straight (not premultiplied) RGBA bytes, row by row
+    pub fn to_rgba8(&self) -> Vec<u8> {
+        let byte = |v: f32| (v * 255.0).round().clamp(0.0, 255.0) as u8;
+        let mut bytes = Vec::with_capacity(self.pixels.len() * 4);
+        for &[r, g, b, a] in &self.pixels {
+            if a > 0.0 {
+                bytes.extend_from_slice(&[byte(r / a), byte(g / a), byte(b / a), byte(a)]);
+            } else {
+                bytes.extend_from_slice(&[0; 4]);
+            }
+        }
+        bytes
+    }
+
+    /// Opaque RGB bytes, row by row, as if drawn over black
+    pub fn to_rgb8(&self) -> Vec<u8> {
+        let byte = |v: f32| (v * 255.0).round().clamp(0.0, 255.0) as u8;
+        let mut bytes = Vec::with_capacity(self.pixels.len() * 3);
+        for &[r, g, b, _] in &self.pixels {
+            // Premultiplied colors are exactly the result of blending over black
+            bytes.extend_from_slice(&[byte(r), byte(g), byte(b)]);
+        }
+        bytes
+    }
+
+    /// Encode in any raster format
+    pub fn save(&self, dest: &str, format: Format) -> Result<()> {
+        // Files are only created once the format is known to be available
+        let create = || File::create(dest).map(BufWriter::new);
+        match format {
+            Format::Svg | Format::Svgz => Err(Error::UnsupportedFormat(dest.to_string())),
+            Format::Png => self.write_png(create),
+            Format::Jpeg { quality } => self.write_jpeg(create, quality),
+            Format::WebP => self.write_webp(create),
+            Format::Bmp => Ok(self.write_bmp(create()?)?),
+            Format::Ppm => Ok(self.write_ppm(create()?)?),
+        }
+    }
+
+    #[cfg(feature = "make-png")]
+    fn write_png<W: Write>(&self, create: impl FnOnce() -> std::io::Result<W>) -> Result<()> {
+        let w = create()?;
+        let mut encoder = png::Encoder::new(w, self.width as u32, self.height as u32);
+        encoder.set_color(png::ColorType::Rgba);
+        encoder.set_depth(png::BitDepth::Eight);
+        encoder
+            .write_header()
+            .and_then(|mut writer| writer.write_image_data(&self.to_rgba8()))
+            .map_err(|e| Error::Render(format!("Could not save image: {}", e)))
+    }
+
+    #[cfg(not(feature = "make-png"))]
+    fn write_png<W: Write>(&self, _: impl FnOnce() -> std::io::Result<W>) -> Result<()> {
+        Err(Error::MissingFeature {
+            format: "PNG",
+            feature: "make-png",
+        })
+    }
+
+    #[cfg(feature = "make-jpeg")]
+    fn write_jpeg<W: Write>(
+        &self,
+        create: impl FnOnce() -> std::io::Result<W>,
+        quality: u8,
+    ) -> Result<()> {
+        use std::convert::TryFrom;
+        let too_large = || Error::Render(String::from("JPEG images are limited to 65535x65535"));
+        let width = u16::try_from(self.width).map_err(|_| too_large())?;
+        let height = u16::try_from(self.height).map_err(|_| too_large())?;
+        let w = create()?;
+        jpeg_encoder::Encoder::new(w, quality)
+            .encode(&self.to_rgb8(), width, height, jpeg_encoder::ColorType::Rgb)
+            .map_err(|e| Error::Render(format!("Could not save image: {}", e)))
+    }
+
+    #[cfg(not(feature = "make-jpeg"))]
+    fn write_jpeg<W: Write>(&self, _: impl FnOnce() -> std::io::Result<W>, _: u8) -> Result<()> {
+        Err(Error::MissingFeature {
+            format: "JPEG",
+            feature: "make-jpeg",
+        })
+    }
+
+    #[cfg(feature = "make-webp")]
+    fn write_webp<W: Write>(&self, create: impl FnOnce() -> std::io::Result<W>) -> Result<()> {
+        let w = create()?;
+        image_webp::WebPEncoder::new(w)
+            .encode(
+                &self.to_rgba8(),
+                self.width as u32,
+                self.height as u32,
+                image_webp::ColorType::Rgba8,
+            )
+            .map_err(|e| Error::Render(format!("Could not save image: {}", e)))
+    }
+
+    #[cfg(not(feature = "make-webp"))]
+    fn write_webp<W: Write>(&self, _: impl FnOnce() -> std::io::Result<W>) -> Result<()> {
+        Err(Error::MissingFeature {
+            format: "WebP",
+            feature: "make-webp",
+        })
+    }
+
+    /// Bottom-up BGR rows, each padded to a multiple of 4 bytes
+    fn write_bmp<W: Write>(&self, mut w: W) -> std::io::Result<()> {
+        let row = (self.width * 3).div_ceil(4) * 4;
+        let data_size = (row * self.height) as u32;
+        let header_size = 14 + 40;
+        // File header
+        w.write_all(b"BM")?;
+        w.write_all(&(header_size + data_size).to_le_bytes())?;
+        w.write_all(&[0; 4])?;
+        w.write_all(&header_size.to_le_bytes())?;
+        // Info header
+        w.write_all(&40u32.to_le_bytes())?;
+        w.write_all(&(self.width as i32).to_le_bytes())?;
+        w.write_all(&(self.height as i32).to_le_bytes())?;
+        w.write_all(&1u16.to_le_bytes())?; // planes
+        w.write_all(&24u16.to_le_bytes())?; // bits per pixel
+        w.write_all(&0u32.to_le_bytes())?; // no compression
+        w.write_all(&data_size.to_le_bytes())?;
+        w.write_all(&2835i32.to_le_bytes())?; // 72 DPI
+        w.write_all(&2835i32.to_le_bytes())?;
+        w.write_all(&[0; 8])?; // no palette
+        let rgb = self.to_rgb8();
+        let mut line = vec![0; row];
+        for y in (0..self.height).rev() {
+            let src = &rgb[y * self.width * 3..(y + 1) * self.width * 3];
+            for (dst, px) in line.chunks_exact_mut(3).zip(src.chunks_exact(3)) {
+                dst.copy_from_slice(&[px[2], px[1], px[0]]);
+            }
+            w.write_all(&line)?;
+        }
+        w.flush()
+    }
+
+    fn write_ppm<W: Write>(&self, mut w: W) -> std::io::Result<()> {
+        write!(w, "P6\n{} {}\n255\n", self.width, self.height)?;
+        w.write_all(&self.to_rgb8())?;
+        w.flush()
+    }
+}
+
+/// Add the signed area covered by a line to the pixels on its left
+/// (the running sum of a row gives the winding number of each pixel).
+/// Coordinates are relative to the buffer and must satisfy 0 <= x <= stride - 2.
+fn accumulate_line(acc: &mut [f32], stride: usize, h: usize, p0: (f32, f32), p1: (f32, f32)) {
+    if p0.1 == p1.1 {
+        return;
+    }
+    let (dir, p0, p1) = if p0.1 < p1.1 {
+        (1.0, p0, p1)
+    } else {
+        (-1.0, p1, p0)
+    };
+    let dxdy = (p1.0 - p0.0) / (p1.1 - p0.1);
+    let mut x = p0.0;
+    if p0.1 < 0.0 {
+        x -= p0.1 * dxdy;
+    }
+    let ystart = p0.1.max(0.0) as usize;
+    let yend = (p1.1.ceil().max(0.0) as usize).min(h);
+    for y in ystart..yend {
+        let line = y * stride;
+        let dy = (y as f32 + 1.0).min(p1.1) - (y as f32).max(p0.1);
+        let xnext = x + dxdy * dy;
+        let d = dy * dir;
+        let (x0, x1) = if x < xnext { (x, xnext) } else { (xnext, x) };
+        let x0floor = x0.floor();
+        let x0i = x0floor as usize;
+        let x1ceil = x1.ceil();
+        let x1i = x1ceil as usize;
+        if x1i <= x0i + 1 {
+            // The line stays within a single pixel on this row
+            let xmf = 0.5 * (x + xnext) - x0floor;
+            acc[line + x0i] += d - d * xmf;
+            acc[line + x0i + 1] += d * xmf;
+        } else {
+            let s = (x1 - x0).recip();
+            let x0f = x0 - x0floor;
+            let a0 = 0.5 * s * (1.0 - x0f) * (1.0 - x0f);
+            let x1f = x1 - x1ceil + 1.0;
+            let am = 0.5 * s * x1f * x1f;
+            acc[line + x0i] += d * a0;
+            if x1i == x0i + 2 {
+                acc[line + x0i + 1] += d * (1.0 - a0 - am);
+            } else {
+                let a1 = s * (1.5 - x0f);
+                acc[line + x0i + 1] += d * (a1 - a0);
+                for xi in x0i + 2..x1i - 1 {
+                    acc[line + xi] += d * s;
+                }
+                let a2 = a1 + (x1i - x0i - 3) as f32 * s;
+                acc[line + x1i - 1] += d * (1.0 - a2 - am);
+            }
+            acc[line + x1i] += d * am;
+        }
+        x = xnext;
+    }
+}
+
+/// Rows of output drawn at once when supersampling,
+/// so that memory usage does not grow with the square of the factor
+const BAND_ROWS: usize = 64;
+
+/// A path in canvas coordinates
+struct Shape<'d> {
+    poly: Vec<(f32, f32)>,
+    /// Vertical extent including the stroke
+    ymin: f32,
+    ymax: f32,
+    stroke_width: f32,
+    path: &'d Path,
+}
+
+impl Document {
+    /// Draw all paths, in order, with the frame stretched to the output size
+    pub fn rasterize(&self) -> Canvas {
+        let k = self.supersample.max(1);
+        let (width, height) = self.size;
+        let (x, y) = (self.frame.x as f64, self.frame.y as f64);
+        let sx = (width * k) as f64 / self.frame.w as f64;
+        let sy = (height * k) as f64 / self.frame.h as f64;
+        let shapes = self
+            .items
+            .iter()
+            .map(|path| {
+                let poly = path
+                    .data
+                    .0
+                    .iter()
+                    .map(|&Pos(px, py)| (((px - x) * sx) as f32, ((py - y) * sy) as f32))
+                    .collect::<Vec<_>>();
+                let stroke_width = (path.stroke_width * (sx + sy) / 2.0) as f32;
+                let (ymin, ymax) = poly
+                    .iter()
+                    .fold((f32::INFINITY, f32::NEG_INFINITY), |(lo, hi), p| {
+                        (lo.min(p.1), hi.max(p.1))
+                    });
+                Shape {
+                    poly,
+                    ymin: ymin - stroke_width,
+                    ymax: ymax + stroke_width,
+                    stroke_width,
+                    path,
+                }
+            })
+            .collect::<Vec<_>>();
+        if k == 1 {
+            let mut canvas = Canvas::new(width, height);
+            draw(&mut canvas, &shapes);
+            return canvas;
+        }
+        let mut canvas = Canvas::new(width, height);
+        for row in (0..height).step_by(BAND_ROWS) {
+            let rows = BAND_ROWS.min(height - row);
+            let mut band = Canvas::new(width * k, rows * k);
+            band.top = (row * k) as f32;
+            draw(&mut band, &shapes);
+            band.downsample_into(k, &mut canvas, row);
+        }
+        canvas
+    }
+
+    /// Render through an SVG string and resvg.
+    /// Slower than `rasterize`, only kept to compare the two.
+    #[cfg(feature = "bench-resvg")]
+    pub fn rasterize_with_resvg(&self) -> Result<Vec<u8>> {
+        // The following code uses functionality from two crates licensed under MPL 2.0
+        //   usvg: https://crates.io/crates/usvg
+        //   resvg: https://crates.io/crates/resvg
+        let svg_data = format!("{}", &self);
+        let tree = usvg::Tree::from_str(&svg_data, &usvg::Options::default())
+            .map_err(|e| Error::Render(format!("Failed to parse svg: {}", e)))?;
+        let converted = resvg::render(&tree, usvg::FitTo::Original, None)
+            .ok_or_else(|| Error::Render(String::from("Failed to convert to png")))?;
+        Ok(converted.data().to_vec())
+    }
+}
+
+/// Paint the shapes that overlap the canvas
+fn draw(canvas: &mut Canvas, shapes: &[Shape]) {
+    let (top, bottom) = (canvas.top, canvas.top + canvas.height as f32);
+    for shape in shapes {
+        if shape.ymax <= top || shape.ymin >= bottom {
+            continue;
+        }
+        canvas.fill(std::slice::from_ref(&shape.poly), shape.path.fill_color);
+        if shape.stroke_width > 0.0 {
+            canvas.stroke(&shape.poly, shape.stroke_width, shape.path.stroke_color);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const WHITE: Color = Color(255, 255, 255);
+
+    fn rect(x0: f32, y0: f32, x1: f32, y1: f32) -> Vec<(f32, f32)> {
+        vec![(x0, y0), (x1, y0), (x1, y1), (x0, y1)]
+    }
+
+    fn alpha(canvas: &Canvas, x: usize, y: usize) -> f32 {
+        canvas.pixels[y * canvas.width + x][3]
+    }
+
+    fn assert_alphas(canvas: &Canvas, expected: &[&[f32]]) {
+        for (y, row) in expected.iter().enumerate() {
+            for (x, &a) in row.iter().enumerate() {
+                let found = alpha(canvas, x, y);
+                assert!(
+                    (found - a).abs() < 1e-5,
+                    "({}, {}): {} instead of {}",
+                    x,
+                    y,
+                    found,
+                    a
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn unit_square() {
+        let mut canvas = Canvas::new(3, 3);
+        canvas.fill(&[rect(1., 1., 2., 2.)], WHITE);
+        assert_alphas(&canvas, &[&[0., 0., 0.], &[0., 1., 0.], &[0., 0., 0.]]);
+    }
+
+    #[test]
+    fn half_pixel_offset() {
+        let mut canvas = Canvas::new(3, 3);
+        canvas.fill(&[rect(0.5, 1., 1.5, 2.)], WHITE);
+        assert_alphas(&canvas, &[&[0., 0., 0.], &[0.5, 0.5, 0.], &[0., 0., 0.]]);
+        let mut canvas = Canvas::new(4, 4);
+        canvas.fill(&[rect(0.5, 0.5, 2.5, 2.5)], WHITE);
+        assert_alphas(
+            &canvas,
+            &[
+                &[0.25, 0.5, 0.25, 0.],
+                &[0.5, 1., 0.5, 0.],
+                &[0.25, 0.5, 0.25, 0.],
+                &[0., 0., 0., 0.],
+            ],
+        );
+        // The color is not affected by partial coverage
+        assert_eq!(&canvas.to_rgba8()[..4], &[255, 255, 255, 64]);
+    }
+
+    #[test]
+    fn polygons_outside_of_the_canvas() {
+        let mut canvas = Canvas::new(2, 2);
+        canvas.fill(&[rect(-1.5, 0., 0.5, 1.)], WHITE);
+        assert_alphas(&canvas, &[&[0.5, 0.], &[0., 0.]]);
+        canvas.fill(&[rect(5., 5., 6., 6.)], WHITE);
+        assert_alphas(&canvas, &[&[0.5, 0.], &[0., 0.]]);
+    }
+
+    #[test]
+    fn stroke_width() {
+        let mut canvas = Canvas::new(10, 10);
+        canvas.stroke(&rect(1., 1., 9., 9.), 2., WHITE);
+        let column = (0..10).map(|y| alpha(&canvas, 5, y)).collect::<Vec<_>>();
+        assert_eq!(column, [1., 1., 0., 0., 0., 0., 0., 0., 1., 1.]);
+        // Corners are filled
+        assert_eq!(alpha(&canvas, 0, 0), 1.);
+        let mut canvas = Canvas::new(10, 10);
+        canvas.stroke(&rect(1., 1., 9., 9.), 1., WHITE);
+        let column = (0..10).map(|y| alpha(&canvas, 5, y)).collect::<Vec<_>>();
+        assert_eq!(column, [0.5, 0.5, 0., 0., 0., 0., 0., 0., 0.5, 0.5]);
+    }
+
+    #[test]
+    fn downsampling() {
+        for k in 2..=3 {
+            let mut big = Canvas::new(2 * k, k);
+            // Left block is fully covered, right block has a single opaque pixel
+            for y in 0..k {
+                for x in 0..k {
+                    big.pixels[y * big.width + x] = [1., 0.5, 0., 1.];
+                }
+            }
+            big.pixels[2 * k - 1] = [0., 0., 1., 1.];
+            let mut small = Canvas::new(2, 2);
+            big.downsample_into(k, &mut small, 1);
+            let norm = 1. / (k * k) as f32;
+            assert_eq!(small.pixels[0], [0.; 4]);
+            assert_eq!(small.pixels[1], [0.; 4]);
+            assert_eq!(small.pixels[2], [1., 0.5, 0., 1.]);
+            assert_eq!(small.pixels[3], [0., 0., norm, norm]);
+        }
+    }
+
+    /// 3x2 canvas with a different color for each pixel
+    fn pattern() -> Canvas {
+        let mut canvas = Canvas::new(3, 2);
+        for (i, px) in canvas.pixels.iter_mut().enumerate() {
+            let v = i as f32 / 10.;
+            *px = [v, 0.5, 1. - v, 1.];
+        }
+        canvas
+    }
+
+    #[test]
+    fn bmp_layout() {
+        let canvas = pattern();
+        let mut bmp = Vec::new();
+        canvas.write_bmp(&mut bmp).unwrap();
+        // Rows of 9 bytes are padded to 12
+        assert_eq!(bmp.len(), 54 + 2 * 12);
+        assert_eq!(&bmp[..2], b"BM");
+        assert_eq!(&bmp[2..6], &78u32.to_le_bytes());
+        assert_eq!(&bmp[10..14], &54u32.to_le_bytes());
+        assert_eq!(&bmp[18..22], &3i32.to_le_bytes());
+        assert_eq!(&bmp[22..26], &2i32.to_le_bytes());
+        assert_eq!(&bmp[28..30], &24u16.to_le_bytes());
+        assert_eq!(&bmp[34..38], &24u32.to_le_bytes());
+        let rgb = canvas.to_rgb8();
+        // The bottom row comes first, in BGR order
+        let (first, second) = (&bmp[54..66], &bmp[66..78]);
+        assert_eq!(&first[..3], &[rgb[11], rgb[10], rgb[9]]);
+        assert_eq!(&second[..3], &[rgb[2], rgb[1], rgb[0]]);
+        assert_eq!(&first[9..], &[0; 3]);
+        assert_eq!(&second[9..], &[0; 3]);
+    }
+
+    #[test]
+    fn ppm_layout() {
+        let canvas = pattern();
+        let mut ppm = Vec::new();
+        canvas.write_ppm(&mut ppm).unwrap();
+        let header = b"P6\n3 2\n255\n";
+        assert_eq!(&ppm[..header.len()], header);
+        assert_eq!(&ppm[header.len()..], canvas.to_rgb8().as_slice());
+    }
+}
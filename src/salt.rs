@@ -1,18 +1,19 @@
 use crate::prelude::*;
-use rand::{rngs::ThreadRng, Rng};
+use rand::Rng;
+use serde_derive::{Deserialize, Serialize};
 
-#[derive(Clone, Copy, Debug)]
+#[derive(Clone, Copy, Debug, Serialize, Deserialize)]
 pub struct SaltItem {
     pub color: Color,
     pub likeliness: f64,
     pub variability: usize,
 }
 
-#[derive(Clone, Debug, Default)]
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
 pub struct Salt(pub Vec<SaltItem>);
 
 impl SaltItem {
-    fn sample(&self, rng: &mut ThreadRng) -> Option<Color> {
+    fn sample<R: Rng + ?Sized>(&self, rng: &mut R) -> Option<Color> {
         if rng.gen::<f64>() < self.likeliness {
             Some(self.color.variate(rng, self.variability))
         } else {
@@ -22,10 +23,11 @@ impl SaltItem {
 }
 
 impl Salt {
-    pub fn sample(&self, rng: &mut ThreadRng) -> Option<Color> {
-        for item in self.0.iter() {
+    /// Color of the first item that applies, along with the index of that item
+    pub fn sample<R: Rng + ?Sized>(&self, rng: &mut R) -> Option<(usize, Color)> {
+        for (i, item) in self.0.iter().enumerate() {
             if let Some(c) = item.sample(rng) {
-                return Some(c);
+                return Some((i, c));
             }
         }
         None
@@ -1,58 +1,195 @@
 use crate::cfg::SceneCfg;
+use crate::log::Item;
+use crate::palette::{Slot, Tint};
 use crate::pos::crossprod_sign;
 use crate::prelude::*;
-use rand::{rngs::ThreadRng, Rng};
-use std::rc::Rc;
+use rand::{Rng, RngCore};
+use serde_derive::{Deserialize, Serialize};
+use std::sync::Arc;
 
 pub struct Scene {
     pub bg: ColorItem,
-    pub items: Vec<Rc<dyn Contains>>,
+    pub items: Vec<Arc<dyn Contains>>,
 }
 
 impl Scene {
-    pub fn new(cfg: &SceneCfg, rng: &mut ThreadRng, verbose: Verbosity) -> Self {
+    pub fn new<R: Rng>(cfg: &SceneCfg, rng: &mut R, diag: &dyn Diagnostics) -> Self {
         Self {
             bg: cfg.choose_color(rng),
-            items: cfg.create_items(rng, verbose),
+            items: cfg.create_items(rng, diag),
         }
     }
 
     /// Get color of a position depending on objects that were hit
-    pub fn color(&self, p: Pos, rng: &mut ThreadRng) -> Color {
-        for i in &self.items {
+    pub fn color<R: Rng>(&self, p: Pos, rng: &mut R) -> Tint {
+        self.first_hit(self.items.iter(), p, rng)
+    }
+
+    /// Color of the first item (in order of priority) that contains `p`
+    fn first_hit<'a, R: Rng>(
+        &self,
+        candidates: impl Iterator<Item = &'a Arc<dyn Contains>>,
+        p: Pos,
+        rng: &mut R,
+    ) -> Tint {
+        for i in candidates {
+            if !i.bbox().contains(p) {
+                continue;
+            }
             if let Some(c) = i.contains(p, rng) {
                 return c;
             }
         }
         self.bg.sample(rng)
     }
+
+    /// Sort items by the region of `frame` that they cover, for faster lookups.
+    /// The index does not follow later changes to the scene.
+    pub fn index(&self, frame: &Frame) -> Index<'_> {
+        // Tiles slightly outside of the frame are common, those further away
+        // are checked against every item
+        let (w, h) = (frame.w as f64, frame.h as f64);
+        let origin = Pos(frame.x as f64 - w / 10., frame.y as f64 - h / 10.);
+        let cell = Pos(w * 1.2 / GRID as f64, h * 1.2 / GRID as f64);
+        let mut cells = vec![Vec::new(); GRID * GRID];
+        for (n, item) in self.items.iter().enumerate() {
+            let BBox { min, max } = item.bbox();
+            // Clamping makes infinite items span the whole grid
+            let range = |lo: f64, hi: f64, start: f64, size: f64| {
+                let first = ((lo - start) / size).floor().max(0.).min(GRID as f64) as usize;
+                let last = ((hi - start) / size).floor().max(-1.).min(GRID as f64 - 1.) + 1.;
+                first..(last as usize)
+            };
+            for j in range(min.1, max.1, origin.1, cell.1) {
+                for i in range(min.0, max.0, origin.0, cell.0) {
+                    cells[j * GRID + i].push(n);
+                }
+            }
+        }
+        Index {
+            scene: self,
+            origin,
+            cell,
+            cells,
+        }
+    }
+}
+
+/// Number of cells of an index along each axis
+const GRID: usize = 32;
+
+/// Uniform grid over a frame, each cell knows which items overlap it
+pub struct Index<'s> {
+    scene: &'s Scene,
+    origin: Pos,
+    cell: Pos,
+    /// Indices into `scene.items`, in increasing order to preserve priorities
+    cells: Vec<Vec<usize>>,
+}
+
+impl Index<'_> {
+    /// Same as `Scene::color`, skipping items that cannot contain `p`
+    pub fn color<R: Rng>(&self, p: Pos, rng: &mut R) -> Tint {
+        let i = ((p.0 - self.origin.0) / self.cell.0).floor();
+        let j = ((p.1 - self.origin.1) / self.cell.1).floor();
+        if i >= 0. && j >= 0. && i < GRID as f64 && j < GRID as f64 {
+            let items = &self.scene.items;
+            let cell = &self.cells[j as usize * GRID + i as usize];
+            self.scene
+                .first_hit(cell.iter().map(|&n| &items[n]), p, rng)
+        } else {
+            self.scene.color(p, rng)
+        }
+    }
+}
+
+/// Axis-aligned rectangle, an item never contains points outside of its box
+#[derive(Clone, Copy, Debug)]
+pub struct BBox {
+    pub min: Pos,
+    pub max: Pos,
+}
+
+impl BBox {
+    /// For items that extend indefinitely
+    pub const INFINITE: Self = Self {
+        min: Pos(f64::NEG_INFINITY, f64::NEG_INFINITY),
+        max: Pos(f64::INFINITY, f64::INFINITY),
+    };
+
+    /// Smallest box around some points
+    pub fn around(points: &[Pos]) -> Self {
+        points.iter().fold(
+            Self {
+                min: Pos(f64::INFINITY, f64::INFINITY),
+                max: Pos(f64::NEG_INFINITY, f64::NEG_INFINITY),
+            },
+            |b, p| Self {
+                min: Pos(b.min.0.min(p.0), b.min.1.min(p.1)),
+                max: Pos(b.max.0.max(p.0), b.max.1.max(p.1)),
+            },
+        )
+    }
+
+    /// Borders are included
+    pub fn contains(&self, p: Pos) -> bool {
+        self.min.0 <= p.0 && p.0 <= self.max.0 && self.min.1 <= p.1 && p.1 <= self.max.1
+    }
 }
 
-/// Trait for anything that can contain a 2D point
-pub trait Contains: std::fmt::Display {
-    fn contains(&self, p: Pos, rng: &mut ThreadRng) -> Option<Color>;
+/// Trait for anything that can contain a 2D point.
+/// Items are shared between the threads that color tiles.
+pub trait Contains: Send + Sync {
+    fn contains(&self, p: Pos, rng: &mut dyn RngCore) -> Option<Tint>;
+    /// Region outside of which `contains` always fails
+    fn bbox(&self) -> BBox;
+    /// Serializable copy of the item, for the scene log
+    fn record(&self) -> Item;
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ColorItem {
     pub shade: Color,
     pub deviation: usize,
     pub theme: Color,
     pub distance: usize,
+    /// Position of `theme` in the theme of the scene, for recolorable output
+    #[serde(default)]
+    pub slot: Option<usize>,
     pub salt: Salt,
 }
 
 impl ColorItem {
-    pub fn sample(&self, rng: &mut ThreadRng) -> Color {
-        self.salt.sample(rng).unwrap_or_else(|| {
-            self.shade
-                .meanpoint(self.theme, self.distance)
-                .variate(rng, self.deviation)
-        })
+    pub fn sample<R: Rng + ?Sized>(&self, rng: &mut R) -> Tint {
+        // Scene files can be edited by hand
+        let distance = self.distance.min(100);
+        match self.salt.sample(rng) {
+            Some((i, color)) => Tint {
+                color,
+                slot: self.slot.map(|theme| Slot {
+                    theme,
+                    salt: Some(i),
+                    base: self.salt.0[i].color,
+                    weight: 1.,
+                }),
+            },
+            None => Tint {
+                color: self
+                    .shade
+                    .meanpoint(self.theme, distance)
+                    .variate(rng, self.deviation),
+                slot: self.slot.map(|theme| Slot {
+                    theme,
+                    salt: None,
+                    base: self.theme,
+                    weight: (100 - distance) as f64 / 100.,
+                }),
+            },
+        }
     }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Disc {
     pub center: Pos,
     pub radius: f64,
@@ -60,7 +197,7 @@ pub struct Disc {
 }
 
 impl Disc {
-    pub fn random(rng: &mut ThreadRng, f: &Frame, color: ColorItem, size_hint: f64) -> Self {
+    pub fn random<R: Rng>(rng: &mut R, f: &Frame, color: ColorItem, size_hint: f64) -> Self {
         let center = Pos::random(f, rng);
         let radius = (rng.gen::<f64>() * size_hint + 0.1) * (f.h.min(f.w) as f64);
         Self {
@@ -72,16 +209,28 @@ impl Disc {
 }
 
 impl Contains for Disc {
-    fn contains(&self, p: Pos, rng: &mut ThreadRng) -> Option<Color> {
+    fn contains(&self, p: Pos, rng: &mut dyn RngCore) -> Option<Tint> {
         if (self.center - p).dot_self() < self.radius.powi(2) {
             Some(self.color.sample(rng))
         } else {
             None
         }
     }
+
+    fn bbox(&self) -> BBox {
+        let r = Pos(self.radius, self.radius);
+        BBox {
+            min: self.center - r,
+            max: self.center + r,
+        }
+    }
+
+    fn record(&self) -> Item {
+        Item::Disc(self.clone())
+    }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct HalfPlane {
     pub limit: Pos,
     pub reference: Pos,
@@ -89,8 +238,8 @@ pub struct HalfPlane {
 }
 
 impl HalfPlane {
-    pub fn random(
-        rng: &mut ThreadRng,
+    pub fn random<R: Rng>(
+        rng: &mut R,
         limit: Pos,
         indic: isize,
         var: usize,
@@ -109,7 +258,7 @@ impl HalfPlane {
 }
 
 impl Contains for HalfPlane {
-    fn contains(&self, p: Pos, rng: &mut ThreadRng) -> Option<Color> {
+    fn contains(&self, p: Pos, rng: &mut dyn RngCore) -> Option<Tint> {
         let dotprod = (p - self.limit).dot(self.reference - self.limit);
         if dotprod < 0. {
             Some(self.color.sample(rng))
@@ -117,9 +266,17 @@ impl Contains for HalfPlane {
             None
         }
     }
+
+    fn bbox(&self) -> BBox {
+        BBox::INFINITE
+    }
+
+    fn record(&self) -> Item {
+        Item::HalfPlane(self.clone())
+    }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Triangle {
     pub a: Pos,
     pub b: Pos,
@@ -128,7 +285,7 @@ pub struct Triangle {
 }
 
 impl Triangle {
-    pub fn random(rng: &mut ThreadRng, circ: Disc) -> Self {
+    pub fn random<R: Rng>(rng: &mut R, circ: Disc) -> Self {
         let theta0 = rng.gen_range(0, 360);
         let theta1 = rng.gen_range(80, 150);
         let theta2 = rng.gen_range(80, 150);
@@ -142,7 +299,7 @@ impl Triangle {
 }
 
 impl Contains for Triangle {
-    fn contains(&self, p: Pos, rng: &mut ThreadRng) -> Option<Color> {
+    fn contains(&self, p: Pos, rng: &mut dyn RngCore) -> Option<Tint> {
         let d1 = crossprod_sign(p, self.a, self.b);
         let d2 = crossprod_sign(p, self.b, self.c);
         let d3 = crossprod_sign(p, self.c, self.a);
@@ -154,9 +311,17 @@ impl Contains for Triangle {
             None
         }
     }
+
+    fn bbox(&self) -> BBox {
+        BBox::around(&[self.a, self.b, self.c])
+    }
+
+    fn record(&self) -> Item {
+        Item::Triangle(self.clone())
+    }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Spiral {
     pub center: Pos,
     pub width: f64,
@@ -165,8 +330,8 @@ pub struct Spiral {
 }
 
 impl Spiral {
-    pub fn random(
-        rng: &mut ThreadRng,
+    pub fn random<R: Rng>(
+        rng: &mut R,
         f: &Frame,
         color: ColorItem,
         width: f64,
@@ -192,7 +357,7 @@ impl FracPart for f64 {
 }
 
 impl Contains for Spiral {
-    fn contains(&self, p: Pos, rng: &mut ThreadRng) -> Option<Color> {
+    fn contains(&self, p: Pos, rng: &mut dyn RngCore) -> Option<Tint> {
         let Pos(di, dj) = self.center - p;
         let theta = di.atan2(dj);
         let radius = (di.powi(2) + dj.powi(2)).sqrt() + theta / std::f64::consts::PI * self.width;
@@ -202,9 +367,17 @@ impl Contains for Spiral {
             None
         }
     }
+
+    fn bbox(&self) -> BBox {
+        BBox::INFINITE
+    }
+
+    fn record(&self) -> Item {
+        Item::Spiral(self.clone())
+    }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Stripe {
     pub limit: Pos,
     pub reference: Pos,
@@ -212,7 +385,7 @@ pub struct Stripe {
 }
 
 impl Stripe {
-    pub fn random(rng: &mut ThreadRng, f: &Frame, color: ColorItem, width: f64) -> Self {
+    pub fn random<R: Rng>(rng: &mut R, f: &Frame, color: ColorItem, width: f64) -> Self {
         let limit = Pos::random(f, rng);
         let reference = limit + Pos::polar(rng.gen_range(0, 360), width);
         Self {
@@ -224,7 +397,7 @@ impl Stripe {
 }
 
 impl Contains for Stripe {
-    fn contains(&self, p: Pos, rng: &mut ThreadRng) -> Option<Color> {
+    fn contains(&self, p: Pos, rng: &mut dyn RngCore) -> Option<Tint> {
         let dotprod1 = (p - self.limit).dot(self.reference - self.limit);
         let dotprod2 = (p - self.reference).dot(self.limit - self.reference);
         if dotprod1 > 0. && dotprod2 > 0. {
@@ -233,9 +406,18 @@ impl Contains for Stripe {
             None
         }
     }
+
+    fn bbox(&self) -> BBox {
+        // Unbounded along its direction
+        BBox::INFINITE
+    }
+
+    fn record(&self) -> Item {
+        Item::Stripe(self.clone())
+    }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Wave {
     pub limit: Pos,
     pub reference: Pos,
@@ -245,8 +427,8 @@ pub struct Wave {
 }
 
 impl Wave {
-    pub fn random(
-        _rng: &mut ThreadRng,
+    pub fn random<R: Rng>(
+        _rng: &mut R,
         limit: Pos,
         indic: isize,
         width: f64,
@@ -264,7 +446,7 @@ impl Wave {
 }
 
 impl Contains for Wave {
-    fn contains(&self, p: Pos, rng: &mut ThreadRng) -> Option<Color> {
+    fn contains(&self, p: Pos, rng: &mut dyn RngCore) -> Option<Tint> {
         let proj = (p - self.limit).project(self.reference - self.limit);
         let nearpt = p - proj;
         let phase = (self.limit - nearpt).norm() * self.frequency;
@@ -275,9 +457,17 @@ impl Contains for Wave {
             None
         }
     }
+
+    fn bbox(&self) -> BBox {
+        BBox::INFINITE
+    }
+
+    fn record(&self) -> Item {
+        Item::Wave(self.clone())
+    }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Sawtooth {
     pub limit: Pos,
     pub reference: Pos,
@@ -287,8 +477,8 @@ pub struct Sawtooth {
 }
 
 impl Sawtooth {
-    pub fn random(
-        _rng: &mut ThreadRng,
+    pub fn random<R: Rng>(
+        _rng: &mut R,
         limit: Pos,
         indic: isize,
         width: f64,
@@ -306,7 +496,7 @@ impl Sawtooth {
 }
 
 impl Contains for Sawtooth {
-    fn contains(&self, p: Pos, rng: &mut ThreadRng) -> Option<Color> {
+    fn contains(&self, p: Pos, rng: &mut dyn RngCore) -> Option<Tint> {
         let sawtooth = |f: f64| {
             let int = f.floor();
             let frac = f - int;
@@ -329,4 +519,93 @@ impl Contains for Sawtooth {
             None
         }
     }
+
+    fn bbox(&self) -> BBox {
+        BBox::INFINITE
+    }
+
+    fn record(&self) -> Item {
+        Item::Sawtooth(self.clone())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::{rngs::StdRng, SeedableRng};
+
+    /// Each item has its own color, to know which one was hit
+    fn plain(n: usize) -> ColorItem {
+        ColorItem {
+            shade: Color(n, n, n),
+            deviation: 0,
+            theme: Color(n, n, n),
+            distance: 0,
+            slot: None,
+            salt: Salt::none(),
+        }
+    }
+
+    #[test]
+    fn index_matches_linear_search() {
+        let mut rng = StdRng::seed_from_u64(0);
+        let frame = Frame {
+            x: 10,
+            y: 20,
+            w: 300,
+            h: 200,
+        };
+        let mut items: Vec<Arc<dyn Contains>> = Vec::new();
+        // Bounded items with a few narrow stripes first, then items that cover
+        // whole regions of the plane and hide everything that comes after them
+        for n in 0..60 {
+            let color = plain(n);
+            let pos = Pos::random(&frame, &mut rng);
+            items.push(match (n, n % 5) {
+                (0..=49, 0) => Arc::new(Stripe::random(&mut rng, &frame, color, 5.)),
+                (0..=49, 1) => {
+                    let circ = Disc::random(&mut rng, &frame, color, 0.3);
+                    Arc::new(Triangle::random(&mut rng, circ))
+                }
+                (0..=49, _) => Arc::new(Disc::random(&mut rng, &frame, color, 0.2)),
+                (_, 0) => Arc::new(Wave::random(&mut rng, pos, 30 * n as isize, 5., 10., color)),
+                _ => Arc::new(HalfPlane::random(&mut rng, pos, 45 * n as isize, 30, color)),
+            });
+        }
+        let scene = Scene {
+            bg: plain(255),
+            items,
+        };
+        let index = scene.index(&frame);
+        // Inside of the frame, around it and far away from it
+        let mut points = (0..5000)
+            .map(|_| Pos(rng.gen_range(-400., 700.), rng.gen_range(-300., 500.)))
+            .collect::<Vec<_>>();
+        points.extend((0..=GRID).map(|i| index.origin + Pos(index.cell.0 * i as f64, 0.)));
+        points.extend((0..=GRID).map(|j| index.origin + Pos(0., index.cell.1 * j as f64)));
+        let mut hits = std::collections::HashSet::new();
+        for p in points {
+            let linear = scene.color(p, &mut StdRng::seed_from_u64(1)).color;
+            let indexed = index.color(p, &mut StdRng::seed_from_u64(1)).color;
+            assert_eq!(linear, indexed, "{:?}", p);
+            hits.insert(linear.0);
+        }
+        // Most items are visible somewhere
+        assert!(hits.len() > 30);
+    }
+
+    #[test]
+    fn distance_is_capped() {
+        let item = ColorItem {
+            shade: Color(200, 100, 0),
+            deviation: 0,
+            theme: Color(0, 100, 200),
+            distance: 150,
+            slot: Some(0),
+            salt: Salt::none(),
+        };
+        let tint = item.sample(&mut StdRng::seed_from_u64(0));
+        assert_eq!(tint.color, Color(200, 100, 0));
+        assert_eq!(tint.slot.unwrap().weight, 0.);
+    }
 }
@@ -0,0 +1,336 @@
+//! When entries apply: times of the day, weekdays, months, days of the year and seasons
+
+use chrono::{Datelike, Duration, NaiveDate, NaiveDateTime, Timelike};
+
+/// When a wallpaper is generated
+#[derive(Clone, Copy, Debug)]
+pub struct Moment {
+    pub date: NaiveDate,
+    /// Time of the day as HHMM
+    pub time: usize,
+    /// Time zone of `date` and `time`, in minutes east of UTC
+    pub utc_offset: i32,
+}
+
+impl Moment {
+    pub fn new(at: NaiveDateTime, utc_offset: i32) -> Self {
+        Self {
+            date: at.date(),
+            time: (at.hour() * 100 + at.minute()) as usize,
+            utc_offset,
+        }
+    }
+
+    /// Same moment `n` minutes earlier, possibly on a previous day
+    pub fn minutes_before(self, n: usize) -> Self {
+        let minutes = (self.time / 100 * 60 + self.time % 100) as i64 - n as i64;
+        let days = minutes.div_euclid(MINUTES_PER_DAY as i64);
+        let minutes = minutes.rem_euclid(MINUTES_PER_DAY as i64) as usize;
+        Self {
+            date: self.date + Duration::days(days),
+            time: minutes / 60 * 100 + minutes % 60,
+            utc_offset: self.utc_offset,
+        }
+    }
+}
+
+const MINUTES_PER_DAY: usize = 24 * 60;
+
+/// Minutes from `start` to `end` (both HHMM), going past midnight if needed
+pub fn minutes_between(start: usize, end: usize) -> usize {
+    let minutes = |t: usize| t / 100 * 60 + t % 100;
+    (minutes(end) + MINUTES_PER_DAY - minutes(start)) % MINUTES_PER_DAY
+}
+
+/// Kinds of date selectors of an entry
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Selector {
+    /// "mon", "sat-sun", "fri-mon"
+    Weekdays,
+    /// "dec", "jun-aug"
+    Months,
+    /// Days of the year as "MMDD" or "MMDD-MMDD"
+    Dates,
+    /// "winter", "spring-summer": meteorological seasons of the northern hemisphere
+    Seasons,
+}
+
+pub const SELECTORS: [Selector; 4] = [
+    Selector::Weekdays,
+    Selector::Months,
+    Selector::Dates,
+    Selector::Seasons,
+];
+
+const WEEKDAYS: [&str; 7] = [
+    "monday",
+    "tuesday",
+    "wednesday",
+    "thursday",
+    "friday",
+    "saturday",
+    "sunday",
+];
+
+const MONTHS: [&str; 12] = [
+    "january",
+    "february",
+    "march",
+    "april",
+    "may",
+    "june",
+    "july",
+    "august",
+    "september",
+    "october",
+    "november",
+    "december",
+];
+
+/// Names and months covered
+const SEASONS: [(&str, u32, u32); 5] = [
+    ("winter", 12, 2),
+    ("spring", 3, 5),
+    ("summer", 6, 8),
+    ("autumn", 9, 11),
+    ("fall", 9, 11),
+];
+
+/// Inclusive range that wraps around when `start > end`
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Cyclic {
+    pub start: u32,
+    pub end: u32,
+}
+
+impl Cyclic {
+    pub fn contains(&self, x: u32) -> bool {
+        if self.start <= self.end {
+            self.start <= x && x <= self.end
+        } else {
+            x >= self.start || x <= self.end
+        }
+    }
+}
+
+/// Read a span of the day "HHMM-HHMM", which wraps around midnight if it ends before it starts
+pub fn parse_span(s: &str) -> Result<Cyclic, String> {
+    let invalid = || format!("{:?} is not a valid span, expected \"HHMM-HHMM\"", s);
+    let read = |t: &str| match t.parse::<u32>() {
+        Ok(n) if t.len() == 4 && n % 100 < 60 && n <= 2400 => Ok(n),
+        _ => Err(invalid()),
+    };
+    match s.split('-').collect::<Vec<_>>()[..] {
+        [start, end] => Ok(Cyclic {
+            start: read(start)?,
+            end: read(end)?,
+        }),
+        _ => Err(invalid()),
+    }
+}
+
+/// Index of `name` in `list`, also accepts abbreviations of at least 3 letters
+/// that match a single name
+fn find_name(list: &[&str], name: &str) -> Option<u32> {
+    let name = name.to_lowercase();
+    if name.len() < 3 {
+        return None;
+    }
+    let mut matches = list
+        .iter()
+        .enumerate()
+        .filter(|(_, full)| full.starts_with(&name));
+    match (matches.next(), matches.next()) {
+        (Some((i, _)), None) => Some(i as u32),
+        _ => None,
+    }
+}
+
+impl Selector {
+    /// Key in an `[[entry]]`
+    pub fn key(self) -> &'static str {
+        match self {
+            Selector::Weekdays => "weekdays",
+            Selector::Months => "months",
+            Selector::Dates => "dates",
+            Selector::Seasons => "seasons",
+        }
+    }
+
+    fn expected(self) -> &'static str {
+        match self {
+            Selector::Weekdays => "a weekday (\"mon\") or a range (\"mon-fri\")",
+            Selector::Months => "a month (\"dec\") or a range (\"jun-aug\")",
+            Selector::Dates => "a date (\"MMDD\") or a range (\"MMDD-MMDD\")",
+            Selector::Seasons => "a season (\"winter\") or a range (\"spring-summer\")",
+        }
+    }
+
+    /// First and last values that a single bound covers
+    fn bound(self, s: &str) -> Option<(u32, u32)> {
+        match self {
+            Selector::Weekdays => find_name(&WEEKDAYS, s).map(|d| (d, d)),
+            Selector::Months => find_name(&MONTHS, s).map(|m| (m + 1, m + 1)),
+            Selector::Dates => {
+                let n = s.parse::<u32>().ok().filter(|_| s.len() == 4)?;
+                // Any leap year accepts all valid days of the year
+                NaiveDate::from_ymd_opt(2000, n / 100, n % 100).map(|_| (n, n))
+            }
+            Selector::Seasons => SEASONS
+                .iter()
+                .find(|(name, _, _)| name.eq_ignore_ascii_case(s))
+                .map(|&(_, start, end)| (start, end)),
+        }
+    }
+
+    /// Read a single item of the selector, such as "mon-fri"
+    pub fn parse(self, s: &str) -> Result<Cyclic, String> {
+        let invalid = || format!("{:?} is not valid, expected {}", s, self.expected());
+        let bounds = s
+            .split('-')
+            .map(|b| self.bound(b.trim()).ok_or_else(invalid))
+            .collect::<Result<Vec<_>, _>>()?;
+        match bounds[..] {
+            [(start, end)] => Ok(Cyclic { start, end }),
+            [(start, _), (_, end)] => Ok(Cyclic { start, end }),
+            _ => Err(invalid()),
+        }
+    }
+
+    /// Value of `date` that items of the selector are compared with
+    pub fn value(self, date: NaiveDate) -> u32 {
+        match self {
+            Selector::Weekdays => date.weekday().num_days_from_monday(),
+            Selector::Months | Selector::Seasons => date.month(),
+            Selector::Dates => date.month() * 100 + date.day(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn covered(sel: Selector, item: &str) -> Vec<u32> {
+        let range = sel.parse(item).unwrap();
+        let all = match sel {
+            Selector::Weekdays => 0..7,
+            Selector::Months | Selector::Seasons => 1..13,
+            Selector::Dates => 101..1232,
+        };
+        all.filter(|&x| range.contains(x)).collect()
+    }
+
+    #[test]
+    fn spans() {
+        let night = parse_span("2100-0559").unwrap();
+        assert!(night.contains(2300) && night.contains(300) && night.contains(2100));
+        assert!(night.contains(559) && !night.contains(600) && !night.contains(1200));
+        let day = parse_span("0600-2059").unwrap();
+        assert!(day.contains(1200) && !day.contains(2300) && !day.contains(300));
+        assert!(parse_span("0000-2400").unwrap().contains(1234));
+        for span in &[
+            "0575-1200",
+            "1200-2401",
+            "600-1200",
+            "0600",
+            "0600-1200-1800",
+            "06:00-12:00",
+        ] {
+            assert!(parse_span(span).is_err(), "{}", span);
+        }
+    }
+
+    #[test]
+    fn minutes() {
+        assert_eq!(minutes_between(2100, 2300), 120);
+        assert_eq!(minutes_between(2100, 300), 360);
+        assert_eq!(minutes_between(1230, 1230), 0);
+        let now = Moment {
+            date: NaiveDate::from_ymd_opt(2024, 3, 1).unwrap(),
+            time: 15,
+            utc_offset: 0,
+        };
+        let before = now.minutes_before(30);
+        assert_eq!(before.time, 2345);
+        assert_eq!(before.date, NaiveDate::from_ymd_opt(2024, 2, 29).unwrap());
+    }
+
+    #[test]
+    fn weekdays() {
+        assert_eq!(covered(Selector::Weekdays, "mon"), [0]);
+        assert_eq!(covered(Selector::Weekdays, "sat-sun"), [5, 6]);
+        // Wraps around the end of the week
+        assert_eq!(covered(Selector::Weekdays, "fri-mon"), [0, 4, 5, 6]);
+        assert_eq!(covered(Selector::Weekdays, "Thurs-FRIDAY"), [3, 4]);
+    }
+
+    #[test]
+    fn months_and_seasons() {
+        assert_eq!(covered(Selector::Months, "dec"), [12]);
+        assert_eq!(covered(Selector::Months, "nov-feb"), [1, 2, 11, 12]);
+        assert_eq!(covered(Selector::Months, "sept-October"), [9, 10]);
+        assert_eq!(covered(Selector::Seasons, "winter"), [1, 2, 12]);
+        assert_eq!(
+            covered(Selector::Seasons, "spring-summer"),
+            [3, 4, 5, 6, 7, 8]
+        );
+        assert_eq!(
+            covered(Selector::Seasons, "fall"),
+            covered(Selector::Seasons, "autumn")
+        );
+        assert_eq!(
+            covered(Selector::Seasons, "autumn-winter"),
+            [1, 2, 9, 10, 11, 12]
+        );
+    }
+
+    #[test]
+    fn dates() {
+        assert_eq!(covered(Selector::Dates, "0229"), [229]);
+        let holidays = covered(Selector::Dates, "1220-0105");
+        assert_eq!(holidays.len(), 12 + 5);
+        assert!(holidays.contains(&1231) && holidays.contains(&101));
+        assert!(!holidays.contains(&1219) && !holidays.contains(&106));
+        let date = NaiveDate::from_ymd_opt(2023, 1, 3).unwrap();
+        assert_eq!(Selector::Dates.value(date), 103);
+        assert_eq!(Selector::Weekdays.value(date), 1);
+        assert_eq!(Selector::Seasons.value(date), 1);
+    }
+
+    #[test]
+    fn abbreviations() {
+        assert_eq!(find_name(&WEEKDAYS, "wed"), Some(2));
+        assert_eq!(find_name(&MONTHS, "JUL"), Some(6));
+        // Too short, even if there is a single match
+        assert_eq!(find_name(&MONTHS, "de"), None);
+        assert_eq!(find_name(&WEEKDAYS, "su"), None);
+        // Not a prefix
+        assert_eq!(find_name(&MONTHS, "uly"), None);
+        assert_eq!(find_name(&MONTHS, "julys"), None);
+        // Ambiguous
+        assert_eq!(find_name(&["march", "marathon"], "mar"), None);
+        assert_eq!(find_name(&["march", "marathon"], "marc"), Some(0));
+    }
+
+    #[test]
+    fn invalid_items() {
+        let invalid = [
+            (Selector::Weekdays, "mo"),
+            (Selector::Weekdays, "mon-"),
+            (Selector::Weekdays, "mon-wed-fri"),
+            (Selector::Months, "feb-30"),
+            (Selector::Months, "13"),
+            (Selector::Dates, "0231"),
+            (Selector::Dates, "1301"),
+            (Selector::Dates, "101"),
+            (Selector::Dates, "0101-0132"),
+            (Selector::Seasons, "wint"),
+            (Selector::Seasons, ""),
+        ];
+        for (sel, item) in &invalid {
+            let err = sel.parse(item).unwrap_err();
+            assert!(err.contains(sel.expected()), "{}", err);
+        }
+    }
+}
@@ -0,0 +1,309 @@
+//! Times of the day that depend on the position of the sun, computed offline
+//! from the latitude and longitude in `[location]`
+
+use crate::schedule::Cyclic;
+use chrono::NaiveDate;
+
+/// Where the wallpaper is seen from, in degrees (north and east are positive)
+#[derive(Clone, Copy, Debug)]
+pub struct Location {
+    pub latitude: f64,
+    pub longitude: f64,
+}
+
+/// Moments of the day defined by the altitude of the sun
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Event {
+    AstronomicalDawn,
+    NauticalDawn,
+    CivilDawn,
+    Sunrise,
+    Noon,
+    Sunset,
+    CivilDusk,
+    NauticalDusk,
+    AstronomicalDusk,
+}
+
+pub const EVENT_NAMES: &[(&str, Event)] = &[
+    ("astronomical-dawn", Event::AstronomicalDawn),
+    ("nautical-dawn", Event::NauticalDawn),
+    ("civil-dawn", Event::CivilDawn),
+    ("sunrise", Event::Sunrise),
+    ("noon", Event::Noon),
+    ("sunset", Event::Sunset),
+    ("civil-dusk", Event::CivilDusk),
+    ("nautical-dusk", Event::NauticalDusk),
+    ("astronomical-dusk", Event::AstronomicalDusk),
+];
+
+impl Event {
+    /// Altitude of the center of the sun in degrees, None for noon
+    fn altitude(self) -> Option<f64> {
+        match self {
+            Event::AstronomicalDawn | Event::AstronomicalDusk => Some(-18.),
+            Event::NauticalDawn | Event::NauticalDusk => Some(-12.),
+            Event::CivilDawn | Event::CivilDusk => Some(-6.),
+            // Accounts for refraction and the radius of the sun
+            Event::Sunrise | Event::Sunset => Some(-0.833),
+            Event::Noon => None,
+        }
+    }
+
+    fn is_morning(self) -> bool {
+        matches!(
+            self,
+            Event::AstronomicalDawn | Event::NauticalDawn | Event::CivilDawn | Event::Sunrise
+        )
+    }
+}
+
+const MINUTES_PER_DAY: f64 = 24. * 60.;
+
+impl Location {
+    /// Minutes after midnight UTC at which `event` happens on `date`,
+    /// None if the sun does not reach that altitude on that day.
+    /// Uses the sunrise equation, accurate to a couple of minutes.
+    fn utc_minutes(&self, event: Event, date: NaiveDate) -> Option<f64> {
+        let epoch = NaiveDate::from_ymd_opt(2000, 1, 1).unwrap();
+        let days = date.signed_duration_since(epoch).num_days() as f64;
+        // Days since noon UTC on 2000-01-01, at mean solar noon
+        let mean_noon = days + 0.0008 - self.longitude / 360.;
+        let anomaly = (357.5291 + 0.985_600_28 * mean_noon).rem_euclid(360.);
+        let m = anomaly.to_radians();
+        let center = 1.9148 * m.sin() + 0.02 * (2. * m).sin() + 0.0003 * (3. * m).sin();
+        let ecliptic = (anomaly + center + 180. + 102.9372)
+            .rem_euclid(360.)
+            .to_radians();
+        let transit = mean_noon + 0.0053 * m.sin() - 0.0069 * (2. * ecliptic).sin();
+        let declination = (ecliptic.sin() * 23.4397_f64.to_radians().sin()).asin();
+        let hour_angle = match event.altitude() {
+            None => 0.,
+            Some(altitude) => {
+                let lat = self.latitude.to_radians();
+                let cos = (altitude.to_radians().sin() - lat.sin() * declination.sin())
+                    / (lat.cos() * declination.cos());
+                if !(-1. ..=1.).contains(&cos) {
+                    return None;
+                }
+                cos.acos().to_degrees()
+            }
+        };
+        let side = if event.is_morning() { -1. } else { 1. };
+        let time = transit + side * hour_angle / 360.;
+        Some((time - days) * MINUTES_PER_DAY + MINUTES_PER_DAY / 2.)
+    }
+}
+
+/// An event shifted by a number of minutes, such as "sunrise+1h"
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct SunTime {
+    pub event: Event,
+    pub offset: i64,
+}
+
+impl SunTime {
+    /// Time of the day as HHMM in the time zone `utc_offset` minutes east of UTC
+    fn on(self, location: &Location, date: NaiveDate, utc_offset: i32) -> Option<u32> {
+        let utc = location.utc_minutes(self.event, date)?;
+        let minutes = (utc + utc_offset as f64).round() as i64 + self.offset;
+        let minutes = minutes.rem_euclid(MINUTES_PER_DAY as i64) as u32;
+        Some(minutes / 60 * 100 + minutes % 60)
+    }
+}
+
+/// Time frame between two events, such as "civil-dawn..sunrise+1h"
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct SunSpan {
+    pub start: SunTime,
+    pub end: SunTime,
+}
+
+impl SunSpan {
+    /// Span of the day that this covers on `date` in the time zone `utc_offset` minutes
+    /// east of UTC, None if either event does not happen
+    pub fn on(&self, location: &Location, date: NaiveDate, utc_offset: i32) -> Option<Cyclic> {
+        Some(Cyclic {
+            start: self.start.on(location, date, utc_offset)?,
+            end: self.end.on(location, date, utc_offset)?,
+        })
+    }
+}
+
+/// Read a sun span "EVENT..EVENT", where each event may be followed by an offset
+/// such as "+1h", "-30m" or "+1h30m"
+pub fn parse_sun_span(s: &str) -> Result<SunSpan, String> {
+    let invalid = || {
+        format!(
+            "{:?} is not a valid sun span, expected \"EVENT..EVENT\" such as \"civil-dawn..sunrise+1h\"",
+            s
+        )
+    };
+    match s.split("..").collect::<Vec<_>>()[..] {
+        [start, end] => Ok(SunSpan {
+            start: parse_sun_time(start.trim()).ok_or_else(invalid)?,
+            end: parse_sun_time(end.trim()).ok_or_else(invalid)?,
+        }),
+        _ => Err(invalid()),
+    }
+}
+
+fn parse_sun_time(s: &str) -> Option<SunTime> {
+    // Event names contain dashes too, the offset starts at the first sign followed by a digit
+    let split = s
+        .char_indices()
+        .find(|&(i, c)| {
+            (c == '+' || c == '-') && s[i + 1..].starts_with(|d: char| d.is_ascii_digit())
+        })
+        .map(|(i, _)| i)
+        .unwrap_or_else(|| s.len());
+    let (name, offset) = s.split_at(split);
+    let event = EVENT_NAMES
+        .iter()
+        .find(|(n, _)| n.eq_ignore_ascii_case(name))
+        .map(|&(_, e)| e)?;
+    let offset = match offset.chars().next() {
+        None => 0,
+        Some('-') => -parse_duration(&offset[1..])?,
+        Some(_) => parse_duration(&offset[1..])?,
+    };
+    Some(SunTime { event, offset })
+}
+
+/// Minutes in "1h", "30m" or "1h30m"
+fn parse_duration(s: &str) -> Option<i64> {
+    let mut total = 0;
+    let mut rest = s;
+    for (unit, minutes) in &[('h', 60), ('m', 1)] {
+        if let Some((n, tail)) = rest.split_once(*unit) {
+            total += i64::from(n.parse::<u32>().ok()?) * minutes;
+            rest = tail;
+        }
+    }
+    if rest.is_empty() && total <= MINUTES_PER_DAY as i64 && !s.is_empty() {
+        Some(total)
+    } else {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const LONDON: Location = Location {
+        latitude: 51.5074,
+        longitude: -0.1278,
+    };
+    const TROMSO: Location = Location {
+        latitude: 69.6492,
+        longitude: 18.9553,
+    };
+
+    fn date(y: i32, m: u32, d: u32) -> NaiveDate {
+        NaiveDate::from_ymd_opt(y, m, d).unwrap()
+    }
+
+    /// Within 5 minutes of `hhmm`
+    fn near(minutes: Option<f64>, hhmm: u32) -> bool {
+        let expected = (hhmm / 100 * 60 + hhmm % 100) as f64;
+        minutes.is_some_and(|m| (m - expected).abs() <= 5.)
+    }
+
+    #[test]
+    fn known_times() {
+        // Almanac times in UTC
+        let winter = date(2024, 12, 21);
+        assert!(near(LONDON.utc_minutes(Event::Sunrise, winter), 804));
+        assert!(near(LONDON.utc_minutes(Event::Noon, winter), 1159));
+        assert!(near(LONDON.utc_minutes(Event::Sunset, winter), 1553));
+        let paris = Location {
+            latitude: 48.8566,
+            longitude: 2.3522,
+        };
+        let summer = date(2024, 6, 21);
+        assert!(near(paris.utc_minutes(Event::Sunrise, summer), 347));
+        assert!(near(paris.utc_minutes(Event::Sunset, summer), 1958));
+        let dawn = paris.utc_minutes(Event::CivilDawn, summer).unwrap();
+        let rise = paris.utc_minutes(Event::Sunrise, summer).unwrap();
+        assert!(dawn < rise);
+    }
+
+    #[test]
+    fn polar_day_and_night() {
+        for &day in &[date(2024, 6, 21), date(2024, 12, 21)] {
+            assert_eq!(TROMSO.utc_minutes(Event::Sunrise, day), None);
+            assert_eq!(TROMSO.utc_minutes(Event::Sunset, day), None);
+            assert!(TROMSO.utc_minutes(Event::Noon, day).is_some());
+        }
+        // The sun never goes far below the horizon in summer nor far above it in winter
+        assert_eq!(
+            TROMSO.utc_minutes(Event::CivilDusk, date(2024, 6, 21)),
+            None
+        );
+        assert!(TROMSO
+            .utc_minutes(Event::CivilDawn, date(2024, 12, 21))
+            .is_some());
+        let span = parse_sun_span("sunrise..sunset").unwrap();
+        assert_eq!(span.on(&TROMSO, date(2024, 12, 21), 60), None);
+    }
+
+    #[test]
+    fn time_zones() {
+        let winter = date(2024, 12, 21);
+        let noon = SunTime {
+            event: Event::Noon,
+            offset: 0,
+        };
+        assert_eq!(noon.on(&LONDON, winter, 0), Some(1200));
+        assert_eq!(noon.on(&LONDON, winter, 120), Some(1400));
+        // Wraps around midnight
+        assert_eq!(noon.on(&LONDON, winter, -13 * 60), Some(2300));
+        let span = parse_sun_span("noon-30m..noon+1h").unwrap();
+        let local = span.on(&LONDON, winter, 60).unwrap();
+        assert_eq!((local.start, local.end), (1230, 1400));
+    }
+
+    #[test]
+    fn sun_spans() {
+        assert_eq!(
+            parse_sun_span("civil-dawn..sunrise+1h"),
+            Ok(SunSpan {
+                start: SunTime {
+                    event: Event::CivilDawn,
+                    offset: 0,
+                },
+                end: SunTime {
+                    event: Event::Sunrise,
+                    offset: 60,
+                },
+            })
+        );
+        let span = parse_sun_span(" Noon-30m .. sunset+1h30m ").unwrap();
+        assert_eq!((span.start.event, span.start.offset), (Event::Noon, -30));
+        assert_eq!((span.end.event, span.end.offset), (Event::Sunset, 90));
+        for s in &[
+            "",
+            "sunrise",
+            "sunrise..",
+            "dawn..sunset",
+            "sunrise..noon..sunset",
+            "sunrise+..sunset",
+            "sunrise+1x..sunset",
+            "sunrise*1h..sunset",
+        ] {
+            assert!(parse_sun_span(s).is_err(), "{}", s);
+        }
+    }
+
+    #[test]
+    fn durations() {
+        assert_eq!(parse_duration("1h"), Some(60));
+        assert_eq!(parse_duration("30m"), Some(30));
+        assert_eq!(parse_duration("1h30m"), Some(90));
+        assert_eq!(parse_duration("24h"), Some(24 * 60));
+        for s in &["", "h", "1", "1x", "30m1h", "1h30", "25h", "-1h"] {
+            assert_eq!(parse_duration(s), None, "{}", s);
+        }
+    }
+}
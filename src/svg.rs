@@ -1,11 +1,17 @@
+use crate::format::Format;
+use crate::palette::Slot;
 use crate::prelude::*;
+use crate::Result;
 use std::fmt;
-use std::io::{self, Write};
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
 
 pub struct Path {
     pub stroke_width: f64,
     pub stroke_color: Color,
     pub fill_color: Color,
+    /// Where `fill_color` comes from, for recolorable output
+    pub slot: Option<Slot>,
     pub data: Data,
 }
 
@@ -13,6 +19,16 @@ pub struct Data(pub Vec<Pos>);
 
 pub struct Document {
     pub frame: Frame,
+    /// Output dimensions in pixels
+    pub size: (usize, usize),
+    /// Raster formats are drawn this many times larger, then averaged down
+    pub supersample: usize,
+    /// Decimal digits kept in SVG coordinates, all of them if None
+    pub precision: Option<usize>,
+    /// Group paths by style and merge neighbors of the same color
+    pub compact: bool,
+    /// Theme colors in a `<style>` block that `palette::recolor` can replace
+    pub recolorable: bool,
     pub items: Vec<Path>,
 }
 
@@ -37,6 +53,7 @@ impl Path {
             stroke_width: 0.0,
             stroke_color: Color(0, 0, 0),
             fill_color: Color(255, 255, 255),
+            slot: None,
             data: d,
         }
     }
@@ -46,6 +63,11 @@ impl Path {
         self
     }
 
+    pub fn with_slot(mut self, slot: Option<Slot>) -> Self {
+        self.slot = slot;
+        self
+    }
+
     pub fn with_stroke_color(mut self, c: Color) -> Self {
         self.stroke_color = c;
         self
@@ -61,89 +83,107 @@ impl Document {
     pub fn new(frame: Frame) -> Self {
         Self {
             frame,
+            size: (frame.w, frame.h),
+            supersample: 1,
+            precision: None,
+            compact: false,
+            recolorable: false,
             items: Vec::new(),
         }
     }
 
+    pub fn with_resolution(mut self, res: Resolution) -> Self {
+        self.size = res.size(&self.frame);
+        self.supersample = res.supersample.max(1);
+        self
+    }
+
     pub fn add(&mut self, path: Path) {
         self.items.push(path);
     }
 
-    pub fn save(&self, dest: &str) -> io::Result<()> {
-        if dest.ends_with(".svg") || dest.ends_with(".svg.tmp") {
-            let mut buffer = std::fs::File::create(dest)?;
-            buffer.write_all(&format!("{}", &self).into_bytes())
-        } else if dest.ends_with(".png") || dest.ends_with(".png.tmp") {
-            #[cfg(feature = "make-png")]
-            {
-                // The following code uses functionality from two crates licensed under MPL 2.0
-                //   usvg: https://crates.io/crates/usvg
-                //   resvg: https://crates.io/crates/resvg
-                let svg_data = format!("{}", &self);
-                let tree = match usvg::Tree::from_str(&svg_data, &usvg::Options::default()) {
-                    Ok(tree) => tree,
-                    Err(_) => {
-                        return Err(io::Error::new(
-                            io::ErrorKind::InvalidData,
-                            "Failed to parse svg",
-                        ))
-                    }
-                };
-                let fit_to = usvg::FitTo::Original;
-                let bg = None;
-                let converted = match resvg::render(&tree, fit_to, bg) {
-                    Some(img) => img,
-                    None => {
-                        return Err(io::Error::new(
-                            io::ErrorKind::InvalidData,
-                            "Failed to convert to png",
-                        ))
-                    }
-                };
-                match converted.save_png(dest) {
-                    Ok(_) => Ok(()),
-                    Err(_) => {
-                        return Err(io::Error::new(
-                            io::ErrorKind::AddrNotAvailable,
-                            "Could not save image",
-                        ))
-                    }
-                }
-            }
-            #[cfg(not(feature = "make-png"))]
-            {
-                Err(io::Error::new(
-                    io::ErrorKind::InvalidData,
-                    "PNG is not supported with the current feature flags --  Make sure to include the feature 'make-png' to access this option -- See 'https://doc.rust-lang.org/cargo/reference/features.html' to learn how to do it",
-                ))
-            }
-        } else {
-            Err(io::Error::new(
-                io::ErrorKind::InvalidData,
-                "Can only support .svg and .png extensions",
-            ))
+    /// Write as SVG text, one path at a time
+    pub fn write_svg<W: Write>(&self, mut w: W) -> io::Result<()> {
+        // Formatting into an `io::Write` does not build the whole text in memory
+        write!(w, "{}", self)?;
+        w.flush()
+    }
+
+    /// Gzip-compressed SVG
+    #[cfg(feature = "make-svgz")]
+    fn write_svgz(&self, dest: &str) -> Result<()> {
+        use flate2::write::GzEncoder;
+        use flate2::Compression;
+        let gz = GzEncoder::new(BufWriter::new(File::create(dest)?), Compression::best());
+        let mut gz = BufWriter::new(gz);
+        write!(gz, "{}", self)?;
+        gz.into_inner()
+            .map_err(|e| e.into_error())?
+            .finish()?
+            .flush()?;
+        Ok(())
+    }
+
+    #[cfg(not(feature = "make-svgz"))]
+    fn write_svgz(&self, _dest: &str) -> Result<()> {
+        Err(crate::Error::MissingFeature {
+            format: "svgz",
+            feature: "make-svgz",
+        })
+    }
+
+    /// Write to `dest`, the format is guessed from the extension
+    pub fn save(&self, dest: &str) -> Result<()> {
+        self.save_as(dest, Format::from_path(dest)?)
+    }
+
+    /// Write to `dest` regardless of its extension
+    pub fn save_as(&self, dest: &str, format: Format) -> Result<()> {
+        match format {
+            Format::Svg => Ok(self.write_svg(BufWriter::new(File::create(dest)?))?),
+            Format::Svgz => self.write_svgz(dest),
+            _ => self.rasterize().save(dest, format),
         }
     }
 }
 
+/// Coordinates with more decimal digits than this are not rounded any further,
+/// a 64-bit float does not hold more of them
+pub const MAX_PRECISION: usize = 15;
+
+/// Round to `precision` decimal digits, or do nothing if it is None
+pub(crate) fn rounding(precision: Option<usize>) -> impl Fn(f64) -> f64 {
+    let scale = precision.map(|n| 10_f64.powi(n.min(MAX_PRECISION) as i32));
+    move |x| match scale {
+        Some(scale) => (x * scale).round() / scale,
+        None => x,
+    }
+}
+
+/// The precision of the formatter (as in `{:.2}`) is the number of decimal digits kept in coordinates
 impl fmt::Display for Path {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "<path d=\"")?;
+        match f.precision() {
+            Some(n) => write!(f, "{:.*}", n, self.data)?,
+            None => write!(f, "{}", self.data)?,
+        }
         write!(
             f,
-            "<path d=\"{}\" fill=\"{}\" stroke=\"{}\" stroke-width=\"{}\" />",
-            self.data, self.fill_color, self.stroke_color, self.stroke_width
+            "\" fill=\"{}\" stroke=\"{}\" stroke-width=\"{}\" />",
+            self.fill_color, self.stroke_color, self.stroke_width
         )
     }
 }
 
+/// The precision of the formatter (as in `{:.2}`) is the number of decimal digits kept
 impl fmt::Display for Data {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        if !self.0.is_empty() {
-            let Pos(x, y) = self.0[0];
-            write!(f, "M{},{} ", x, y)?;
-        }
-        for Pos(x, y) in self.0.iter().skip(1) {
-            write!(f, "L{},{} ", x, y)?;
+        // Rounding then printing the shortest representation drops trailing zeros
+        let round = rounding(f.precision());
+        for (i, &Pos(x, y)) in self.0.iter().enumerate() {
+            let cmd = if i == 0 { 'M' } else { 'L' };
+            write!(f, "{}{},{} ", cmd, round(x), round(y))?;
         }
         write!(f, "z")
     }
@@ -153,13 +193,29 @@ impl fmt::Display for Document {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         let (x1, y1, x2, y2) = self.frame.into_tuple();
         let src = String::from("http://www.w3.org/2000/svg");
-        writeln!(
-            f,
-            "<svg viewBox=\"{} {} {} {}\" xmlns=\"{}\">",
-            x1, y1, x2, y2, src
-        )?;
+        write!(f, "<svg viewBox=\"{} {} {} {}\" ", x1, y1, x2, y2)?;
+        if self.size != (self.frame.w, self.frame.h) {
+            // Stretch like the raster formats do instead of letterboxing
+            write!(
+                f,
+                "width=\"{}\" height=\"{}\" preserveAspectRatio=\"none\" ",
+                self.size.0, self.size.1
+            )?;
+        }
+        writeln!(f, "xmlns=\"{}\">", src)?;
+        if self.recolorable {
+            self.fmt_recolorable(f)?;
+            return write!(f, "</svg>");
+        }
+        if self.compact {
+            self.fmt_compact(f)?;
+            return write!(f, "</svg>");
+        }
         for p in self.items.iter() {
-            writeln!(f, "{}", p)?;
+            match self.precision {
+                Some(n) => writeln!(f, "{:.*}", n, p)?,
+                None => writeln!(f, "{}", p)?,
+            }
         }
         write!(f, "</svg>")
     }
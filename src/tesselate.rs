@@ -1,41 +1,46 @@
 use crate::prelude::*;
 use crate::shape::*;
 use crate::svg::*;
+use crate::{Error, Result};
 use delaunator as del;
-use rand::rngs::ThreadRng;
-use std::collections::HashSet;
-
-macro_rules! set {
-    { $( $elem:expr ),* } => {
-        {
-            let mut set = HashSet::new();
-            $( set.insert($elem); )*
-            set
-        }
-    }
-}
+use rand::Rng;
 
 /// Tile the plane with a pattern that can be mapped to a 2D grid.
 /// This criterion applies to all tilings used here except Delaunay triangulation.
+/// Cell (i, j) is anchored at `center + idir * i + jdir * j`, computed directly
+/// rather than step by step so that rounding errors do not accumulate.
 fn periodic_grid_tiling<F>(f: &Frame, gen: F, idir: Pos, jdir: Pos) -> Vec<(Pos, Path)>
 where
     F: Fn(Pos) -> Vec<(Pos, Path)>,
 {
-    let mut items = Vec::new();
     let center = f.center();
-    let mut set = set![center];
-    let mut stk = vec![center];
-    while let Some(pos) = stk.pop() {
-        if f.is_inside(pos) {
-            for item in gen(pos) {
-                items.push(item);
-            }
-            for &(i, j) in &[(0, 1), (0, -1), (1, 0), (-1, 0)] {
-                let p = pos + idir * i + jdir * j;
-                if !set.contains(&p) {
-                    set.insert(p);
-                    stk.push(p);
-                }
+    // A cell can overlap the frame even if its anchor is outside,
+    // but not if its anchor is further away than its furthest vertex
+    let margin = gen(Pos::zero())
+        .iter()
+        .flat_map(|(_, path)| path.data.0.iter())
+        .map(|v| v.norm())
+        .fold(0., f64::max);
+    let min = Pos(f.x as f64 - margin, f.y as f64 - margin);
+    let max = Pos((f.x + f.w) as f64 + margin, (f.y + f.h) as f64 + margin);
+    // Solve d = idir * i + jdir * j for each corner to bound the indices
+    let det = idir.0 * jdir.1 - idir.1 * jdir.0;
+    let (mut imin, mut imax, mut jmin, mut jmax) = (0, 0, 0, 0);
+    for &corner in &[min, Pos(max.0, min.1), Pos(min.0, max.1), max] {
+        let Pos(dx, dy) = corner - center;
+        let i = (dx * jdir.1 - dy * jdir.0) / det;
+        let j = (idir.0 * dy - idir.1 * dx) / det;
+        imin = imin.min(i.floor() as isize);
+        imax = imax.max(i.ceil() as isize);
+        jmin = jmin.min(j.floor() as isize);
+        jmax = jmax.max(j.ceil() as isize);
+    }
+    let mut items = Vec::new();
+    for j in jmin..=jmax {
+        for i in imin..=imax {
+            let pos = center + idir * i + jdir * j;
+            if min.0 <= pos.0 && pos.0 <= max.0 && min.1 <= pos.1 && pos.1 <= max.1 {
+                items.extend(gen(pos));
             }
         }
     }
@@ -138,13 +143,13 @@ pub fn tile_rhombus(f: &Frame, ldiag: f64, sdiag: f64, rot: isize) -> Vec<(Pos,
 
 /// External crate does the heavy lifting and is an order of magnitude faster than the previously implemented Boyer-Watson algorithm.
 /// Only downside is that it requires conversions between position types.
-fn fast_triangulate(pts: &[Pos]) -> Vec<(Pos, Pos, Pos)> {
+fn fast_triangulate(pts: &[Pos]) -> Result<Vec<(Pos, Pos, Pos)>> {
     let points = pts
         .iter()
         .map(|&Pos(x, y)| del::Point { x, y })
         .collect::<Vec<_>>();
     let result = del::triangulate(&points)
-        .unwrap()
+        .ok_or(Error::Triangulation { points: pts.len() })?
         .triangles
         .iter()
         .map(|&i| pts[i])
@@ -153,16 +158,16 @@ fn fast_triangulate(pts: &[Pos]) -> Vec<(Pos, Pos, Pos)> {
     for i in 0..result.len() / 3 {
         v.push((result[i * 3], result[i * 3 + 1], result[i * 3 + 2]));
     }
-    v
+    Ok(v)
 }
 
-pub fn random_delaunay(f: &Frame, rng: &mut ThreadRng, n: usize) -> Vec<(Pos, Path)> {
+pub fn random_delaunay<R: Rng>(f: &Frame, rng: &mut R, n: usize) -> Result<Vec<(Pos, Path)>> {
     let mut pts = Vec::new();
     for _ in 0..n {
         pts.push(Pos::random(f, rng));
     }
-    let triangulation = fast_triangulate(&pts);
-    triangulation
+    let triangulation = fast_triangulate(&pts)?;
+    Ok(triangulation
         .into_iter()
         .map(|(a, b, c)| {
             (
@@ -170,10 +175,10 @@ pub fn random_delaunay(f: &Frame, rng: &mut ThreadRng, n: usize) -> Vec<(Pos, Pa
                 Path::new(Data::new(a).with_line_to(b).with_line_to(c)),
             )
         })
-        .collect::<Vec<_>>()
+        .collect::<Vec<_>>())
 }
 
-pub fn pentagons_type1(f: &Frame, size: f64, rot: isize) -> Vec<(Pos, Path)> {
+pub fn pentagons_type1(f: &Frame, size: f64, rot: isize) -> Result<Vec<(Pos, Path)>> {
     let beta = 80;
     let gamma = 180 - beta;
     let alpha = 110;
@@ -183,12 +188,12 @@ pub fn pentagons_type1(f: &Frame, size: f64, rot: isize) -> Vec<(Pos, Path)> {
     let angles = [alpha, beta, gamma, delta, epsilon];
     #[rustfmt::skip]
     let mv = [
-        Pentagon { sizes, rot, angles }.to_movable(),
-        Pentagon { sizes, rot: rot + 180, angles }.to_movable(),
+        Pentagon { sizes, rot, angles }.to_movable()?,
+        Pentagon { sizes, rot: rot + 180, angles }.to_movable()?,
     ];
     let idir = mv[0].vertex(3) - mv[0].vertex(0);
     let jdir = mv[0].vertex(0) - mv[1].vertex(4) + mv[1].vertex(2) - mv[0].vertex(1);
-    periodic_grid_tiling(
+    Ok(periodic_grid_tiling(
         f,
         |pos| {
             vec![
@@ -198,10 +203,10 @@ pub fn pentagons_type1(f: &Frame, size: f64, rot: isize) -> Vec<(Pos, Path)> {
         },
         idir,
         jdir,
-    )
+    ))
 }
 
-pub fn pentagons_type2(f: &Frame, size: f64, rot: isize) -> Vec<(Pos, Path)> {
+pub fn pentagons_type2(f: &Frame, size: f64, rot: isize) -> Result<Vec<(Pos, Path)>> {
     let alpha = 110;
     let beta = 110;
     let gamma = 100;
@@ -212,14 +217,14 @@ pub fn pentagons_type2(f: &Frame, size: f64, rot: isize) -> Vec<(Pos, Path)> {
     let rangles = [beta, gamma, delta, epsilon, alpha];
     #[rustfmt::skip]
     let mv = [
-        Pentagon { sizes, rot, angles }.to_movable(),
-        Pentagon { sizes, rot: rot + 180, angles }.to_movable(),
-        Pentagon { sizes, rot: rot + 180, angles: rangles }.to_movable(),
-        Pentagon { sizes, rot, angles: rangles }.to_movable(),
+        Pentagon { sizes, rot, angles }.to_movable()?,
+        Pentagon { sizes, rot: rot + 180, angles }.to_movable()?,
+        Pentagon { sizes, rot: rot + 180, angles: rangles }.to_movable()?,
+        Pentagon { sizes, rot, angles: rangles }.to_movable()?,
     ];
     let idir = mv[0].vertex(0) - mv[2].vertex(1) + mv[2].vertex(2) - mv[0].vertex(3);
     let jdir = mv[0].vertex(4) - mv[3].vertex(1) + mv[3].vertex(4) - mv[0].vertex(2);
-    periodic_grid_tiling(
+    Ok(periodic_grid_tiling(
         f,
         |pos| {
             vec![
@@ -231,10 +236,10 @@ pub fn pentagons_type2(f: &Frame, size: f64, rot: isize) -> Vec<(Pos, Path)> {
         },
         idir,
         jdir,
-    )
+    ))
 }
 
-pub fn pentagons_type3(f: &Frame, size: f64, rot: isize) -> Vec<(Pos, Path)> {
+pub fn pentagons_type3(f: &Frame, size: f64, rot: isize) -> Result<Vec<(Pos, Path)>> {
     let alpha = 120;
     let gamma = alpha;
     let delta = alpha;
@@ -245,13 +250,13 @@ pub fn pentagons_type3(f: &Frame, size: f64, rot: isize) -> Vec<(Pos, Path)> {
     let angles = [epsilon, delta, gamma, beta, alpha];
     #[rustfmt::skip]
     let mv = [
-        Pentagon { sizes, rot, angles }.to_movable(),
-        Pentagon { sizes, rot: rot + 120, angles }.to_movable(),
-        Pentagon { sizes, rot: rot - 120, angles }.to_movable(),
+        Pentagon { sizes, rot, angles }.to_movable()?,
+        Pentagon { sizes, rot: rot + 120, angles }.to_movable()?,
+        Pentagon { sizes, rot: rot - 120, angles }.to_movable()?,
     ];
     let idir = -mv[0].vertex(4) + mv[0].vertex(2) - mv[2].vertex(2) + mv[2].vertex(4);
     let jdir = -mv[0].vertex(4) + mv[0].vertex(2) - mv[1].vertex(2) + mv[1].vertex(4);
-    periodic_grid_tiling(
+    Ok(periodic_grid_tiling(
         f,
         |pos| {
             vec![
@@ -262,10 +267,10 @@ pub fn pentagons_type3(f: &Frame, size: f64, rot: isize) -> Vec<(Pos, Path)> {
         },
         idir,
         jdir,
-    )
+    ))
 }
 
-pub fn pentagons_type4(f: &Frame, size: f64, rot: isize) -> Vec<(Pos, Path)> {
+pub fn pentagons_type4(f: &Frame, size: f64, rot: isize) -> Result<Vec<(Pos, Path)>> {
     let alpha = 100;
     let beta = 90;
     let gamma = 130;
@@ -275,14 +280,14 @@ pub fn pentagons_type4(f: &Frame, size: f64, rot: isize) -> Vec<(Pos, Path)> {
     let angles = [alpha, beta, gamma, delta, epsilon];
     #[rustfmt::skip]
     let mv = [
-        Pentagon { sizes, rot, angles }.to_movable(),
-        Pentagon { sizes, rot: rot + 90, angles }.to_movable(),
-        Pentagon { sizes, rot: rot + 180, angles }.to_movable(),
-        Pentagon { sizes, rot: rot - 90, angles }.to_movable(),
+        Pentagon { sizes, rot, angles }.to_movable()?,
+        Pentagon { sizes, rot: rot + 90, angles }.to_movable()?,
+        Pentagon { sizes, rot: rot + 180, angles }.to_movable()?,
+        Pentagon { sizes, rot: rot - 90, angles }.to_movable()?,
     ];
     let idir = -mv[0].vertex(1) + mv[0].vertex(4) - mv[2].vertex(0) + mv[2].vertex(1);
     let jdir = -mv[1].vertex(1) + mv[1].vertex(4) - mv[3].vertex(0) + mv[3].vertex(1);
-    periodic_grid_tiling(
+    Ok(periodic_grid_tiling(
         f,
         |pos| {
             vec![
@@ -294,10 +299,10 @@ pub fn pentagons_type4(f: &Frame, size: f64, rot: isize) -> Vec<(Pos, Path)> {
         },
         idir,
         jdir,
-    )
+    ))
 }
 
-pub fn pentagons_type5(f: &Frame, size: f64, rot: isize) -> Vec<(Pos, Path)> {
+pub fn pentagons_type5(f: &Frame, size: f64, rot: isize) -> Result<Vec<(Pos, Path)>> {
     let alpha = 150;
     let beta = 60;
     let gamma = 120;
@@ -307,12 +312,12 @@ pub fn pentagons_type5(f: &Frame, size: f64, rot: isize) -> Vec<(Pos, Path)> {
     let angles = [delta, gamma, beta, alpha, epsilon];
     #[rustfmt::skip]
     let mv = [
-        Pentagon { sizes, rot, angles }.to_movable(),
-        Pentagon { sizes, rot: rot + 60, angles }.to_movable(),
-        Pentagon { sizes, rot: rot + 120, angles }.to_movable(),
-        Pentagon { sizes, rot: rot + 180, angles }.to_movable(),
-        Pentagon { sizes, rot: rot - 120, angles }.to_movable(),
-        Pentagon { sizes, rot: rot - 60, angles }.to_movable(),
+        Pentagon { sizes, rot, angles }.to_movable()?,
+        Pentagon { sizes, rot: rot + 60, angles }.to_movable()?,
+        Pentagon { sizes, rot: rot + 120, angles }.to_movable()?,
+        Pentagon { sizes, rot: rot + 180, angles }.to_movable()?,
+        Pentagon { sizes, rot: rot - 120, angles }.to_movable()?,
+        Pentagon { sizes, rot: rot - 60, angles }.to_movable()?,
     ];
     let idir = -mv[1].vertex(2) + mv[1].vertex(4) - mv[5].vertex(4) + mv[5].vertex(1)
         - mv[1].vertex(1)
@@ -324,7 +329,7 @@ pub fn pentagons_type5(f: &Frame, size: f64, rot: isize) -> Vec<(Pos, Path)> {
         + mv[3].vertex(4)
         - mv[5].vertex(4)
         + mv[5].vertex(2);
-    periodic_grid_tiling(
+    Ok(periodic_grid_tiling(
         f,
         |pos| {
             vec![
@@ -350,10 +355,10 @@ pub fn pentagons_type5(f: &Frame, size: f64, rot: isize) -> Vec<(Pos, Path)> {
         },
         idir,
         jdir,
-    )
+    ))
 }
 
-pub fn pentagons_type6(f: &Frame, size: f64, rot: isize) -> Vec<(Pos, Path)> {
+pub fn pentagons_type6(f: &Frame, size: f64, _rot: isize) -> Result<Vec<(Pos, Path)>> {
     let rot = 60;
     let alpha = 150;
     let beta = 44;
@@ -364,14 +369,14 @@ pub fn pentagons_type6(f: &Frame, size: f64, rot: isize) -> Vec<(Pos, Path)> {
     let angles = [gamma, delta, epsilon, alpha, beta];
     #[rustfmt::skip]
     let mv = [
-        Pentagon { sizes, rot, angles }.to_movable(),
-        Pentagon { sizes, rot: rot + 180, angles }.to_movable(),
-        Pentagon { sizes, rot: rot - beta as isize, angles }.to_movable(),
-        Pentagon { sizes, rot: rot - beta as isize + 180, angles }.to_movable(),
+        Pentagon { sizes, rot, angles }.to_movable()?,
+        Pentagon { sizes, rot: rot + 180, angles }.to_movable()?,
+        Pentagon { sizes, rot: rot - beta as isize, angles }.to_movable()?,
+        Pentagon { sizes, rot: rot - beta as isize + 180, angles }.to_movable()?,
     ];
     let idir = -mv[0].vertex(3) + mv[0].vertex(2) - mv[1].vertex(1) + mv[1].vertex(4);
     let jdir = -mv[3].vertex(0) + mv[3].vertex(3) - mv[1].vertex(0) + mv[1].vertex(3);
-    periodic_grid_tiling(
+    Ok(periodic_grid_tiling(
         f,
         |pos| {
             vec![
@@ -383,7 +388,7 @@ pub fn pentagons_type6(f: &Frame, size: f64, rot: isize) -> Vec<(Pos, Path)> {
         },
         idir,
         jdir,
-    )
+    ))
 }
 
 struct Pentagon {
@@ -393,14 +398,12 @@ struct Pentagon {
 }
 
 impl Pentagon {
-    fn to_movable(&self) -> Movable {
-        assert_eq!(0, {
-            let mut s = 0;
-            for i in 0..5 {
-                s += 180 - self.angles[i];
-            }
-            s.rem_euclid(360)
-        });
+    fn to_movable(&self) -> Result<Movable> {
+        let malformed = || Error::MalformedShape(format!("pentagon with angles {:?}", self.angles));
+        let turns = self.angles.iter().map(|a| 180 - *a as isize).sum::<isize>();
+        if turns.rem_euclid(360) != 0 {
+            return Err(malformed());
+        }
         let mut pts = Vec::new();
         pts.push(Pos::zero());
         let mut running_angle = self.rot;
@@ -410,11 +413,153 @@ impl Pentagon {
             running_angle += 180 - self.angles[i + 1] as isize;
         }
         let latest = pts[3];
-        pts.push(Pos::intersect(
-            (Pos::zero(), self.rot + self.angles[0] as isize),
-            (latest, running_angle),
-        ));
+        pts.push(
+            Pos::intersect(
+                (Pos::zero(), self.rot + self.angles[0] as isize),
+                (latest, running_angle),
+            )
+            .ok_or_else(malformed)?,
+        );
         let mid = pts.iter().fold(Pos::zero(), |acc, item| acc + *item) * 0.2;
-        Movable::from(pts.into_iter().map(|p| p - mid).collect::<Vec<_>>())
+        Ok(Movable::from(
+            pts.into_iter().map(|p| p - mid).collect::<Vec<_>>(),
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const FRAME: Frame = Frame {
+        x: 0,
+        y: 0,
+        w: 300,
+        h: 200,
+    };
+
+    /// Even-odd rule
+    fn contains(poly: &[Pos], p: Pos) -> bool {
+        let mut inside = false;
+        for (i, &a) in poly.iter().enumerate() {
+            let b = poly[(i + 1) % poly.len()];
+            if (a.1 > p.1) != (b.1 > p.1) && p.0 < a.0 + (p.1 - a.1) / (b.1 - a.1) * (b.0 - a.0) {
+                inside = !inside;
+            }
+        }
+        inside
+    }
+
+    /// Every point of the frame, including its corners, belongs to some tile
+    fn assert_covers(name: &str, f: &Frame, tiles: &[(Pos, Path)]) {
+        // Offsets keep sample points away from the edges of tiles
+        let (x0, y0) = (f.x as f64, f.y as f64);
+        let xs = (0..=30).map(|i| x0 + (i as f64 * f.w as f64 / 30. + 0.37).min(f.w as f64 - 0.3));
+        for x in xs {
+            let ys =
+                (0..=20).map(|j| y0 + (j as f64 * f.h as f64 / 20. + 0.53).min(f.h as f64 - 0.3));
+            for y in ys {
+                assert!(
+                    tiles
+                        .iter()
+                        .any(|(_, path)| contains(&path.data.0, Pos(x, y))),
+                    "{}: ({}, {}) is not covered",
+                    name,
+                    x,
+                    y
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn lattice_covers_frame() {
+        // A parallelogram spanned by the two directions tiles the plane exactly,
+        // any cell missing from the walk leaves a hole
+        let frames = [
+            FRAME,
+            Frame {
+                x: 50,
+                y: 20,
+                w: 80,
+                h: 400,
+            },
+        ];
+        let dirs = [
+            (Pos(10., 0.), Pos(0., 10.)),
+            (Pos::polar(17, 12.), Pos::polar(77, 12.)),
+            (Pos::polar(-30, 25.), Pos::polar(30, 25.)),
+            // Very flat cells
+            (Pos(40., 1.), Pos(39., 2.)),
+            (Pos::polar(200, 150.), Pos::polar(95, 9.)),
+        ];
+        for f in &frames {
+            for &(idir, jdir) in &dirs {
+                let tiles = periodic_grid_tiling(
+                    f,
+                    |p| {
+                        let d = Data::new(p)
+                            .with_line_to(p + idir)
+                            .with_line_to(p + idir + jdir)
+                            .with_line_to(p + jdir);
+                        vec![(p, Path::new(d))]
+                    },
+                    idir,
+                    jdir,
+                );
+                assert_covers(&format!("{:?} {:?}", idir, jdir), f, &tiles);
+            }
+        }
+    }
+
+    #[test]
+    fn tilings_cover_frame() {
+        // Hexagons and triangles, and pentagons of type 6, leave small gaps between tiles
+        let pentagons = [
+            pentagons_type1,
+            pentagons_type2,
+            pentagons_type3,
+            pentagons_type4,
+            pentagons_type5,
+        ];
+        for &rot in &[0, 17, 45, 90, 133] {
+            for &size in &[7., 30., 120.] {
+                let name = |s: &str| format!("{} size {} rot {}", s, size, rot);
+                assert_covers(&name("hexagons"), &FRAME, &tile_hexagons(&FRAME, size, rot));
+                assert_covers(
+                    &name("triangles"),
+                    &FRAME,
+                    &tile_triangles(&FRAME, size, rot),
+                );
+                assert_covers(
+                    &name("squares&triangles"),
+                    &FRAME,
+                    &tile_hybrid_squares_triangles(&FRAME, size, rot),
+                );
+                assert_covers(
+                    &name("rhombus"),
+                    &FRAME,
+                    &tile_rhombus(&FRAME, size, size * 0.6, rot),
+                );
+                for (i, tiler) in pentagons.iter().enumerate() {
+                    let tiles = tiler(&FRAME, size, rot).unwrap();
+                    assert_covers(&name(&format!("pentagons-{}", i + 1)), &FRAME, &tiles);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn tilings_stay_near_frame() {
+        // Anchors are at most one tile away from the frame
+        let size = 30.;
+        let tiles = tile_hexagons(&FRAME, size, 17);
+        for (pos, _) in &tiles {
+            assert!(-2. * size <= pos.0 && pos.0 <= 300. + 2. * size);
+            assert!(-2. * size <= pos.1 && pos.1 <= 200. + 2. * size);
+        }
+        // About as many as fit in the frame
+        let area = 3. * 3_f64.sqrt() / 2. * size * size;
+        assert!((tiles.len() as f64) < 300. * 200. / area * 2.);
     }
 }
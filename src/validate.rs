@@ -0,0 +1,947 @@
+use crate::deserializer::{color_from_value, MetaConfig, Shape};
+use crate::prelude::*;
+use crate::schedule::{parse_span, Cyclic, SELECTORS};
+use crate::sun::parse_sun_span;
+use crate::svg::MAX_PRECISION;
+use crate::{layers, Error, Result};
+use std::collections::HashMap;
+use std::fmt;
+use std::path::Path;
+use toml::{map::Map, Value};
+
+/// How serious a problem is
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Severity {
+    /// Part of the configuration is ignored or replaced by a default
+    Error,
+    /// The configuration is accepted but probably not what was intended
+    Warning,
+}
+
+/// A single problem found in a configuration file
+#[derive(Debug, Clone)]
+pub struct Diagnostic {
+    pub severity: Severity,
+    /// TOML key path of the offending value, empty for the whole file
+    pub path: String,
+    pub message: String,
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let severity = match self.severity {
+            Severity::Error => "error",
+            Severity::Warning => "warning",
+        };
+        if self.path.is_empty() {
+            write!(f, "{}: {}", severity, self.message)
+        } else {
+            write!(f, "{}: {}: {}", severity, self.path, self.message)
+        }
+    }
+}
+
+/// Expected type of a configuration value
+#[derive(Clone, Copy)]
+enum Kind {
+    Integer,
+    Float,
+    /// Like `Integer`, also a range `[min, max]` or a list of `[value, weight]` pairs
+    Integers,
+    /// Like `Float`, also a range `[min, max]` or a list of `[value, weight]` pairs
+    Floats,
+    /// Integer between 0 and the bound
+    UpTo(usize),
+    /// Like `Integers`, with all values between 0 and the bound
+    IntegersUpTo(usize),
+    Str,
+    Bool,
+    StrArray,
+    /// A string or an array of strings
+    StrOrArray,
+    /// An array of names or of `[name, weight]` pairs
+    Names,
+    Table,
+}
+
+#[rustfmt::skip]
+const GLOBAL_KEYS: &[(&str, Kind)] = &[
+    ("deviation", Kind::Integers), ("weight", Kind::IntegersUpTo(100)),
+    ("distance", Kind::IntegersUpTo(100)),
+    ("size", Kind::Floats), ("width", Kind::Integers), ("height", Kind::Integers),
+    ("output_width", Kind::Integers), ("output_height", Kind::Integers),
+    ("supersample", Kind::Integers), ("clip", Kind::Bool), ("precision", Kind::IntegersUpTo(MAX_PRECISION)),
+    ("compact", Kind::Bool), ("recolorable", Kind::Bool),
+];
+
+#[rustfmt::skip]
+const LINES_KEYS: &[(&str, Kind)] = &[
+    ("width", Kind::Float), ("color", Kind::Str),
+    ("del_width", Kind::Float), ("del_color", Kind::Str),
+    ("hex_width", Kind::Float), ("hex_color", Kind::Str),
+    ("tri_width", Kind::Float), ("tri_color", Kind::Str),
+    ("rho_width", Kind::Float), ("rho_color", Kind::Str),
+    ("hex_and_tri_width", Kind::Float), ("hex_and_tri_color", Kind::Str),
+    ("squ_and_tri_width", Kind::Float), ("squ_and_tri_color", Kind::Str),
+    ("pen_width", Kind::Float), ("pen_color", Kind::Str),
+];
+
+#[rustfmt::skip]
+const DATA_KEYS: &[(&str, Kind)] = &[("patterns", Kind::Table), ("tilings", Kind::Table)];
+
+#[rustfmt::skip]
+const PATTERNS_KEYS: &[(&str, Kind)] = &[
+    ("nb_free_circles", Kind::Integers), ("nb_free_spirals", Kind::Integers),
+    ("nb_free_stripes", Kind::Integers), ("nb_crossed_stripes", Kind::Integers),
+    ("nb_parallel_stripes", Kind::Integers), ("nb_concentric_circles", Kind::Integers),
+    ("nb_free_triangles", Kind::Integers), ("nb_parallel_waves", Kind::Integers),
+    ("nb_parallel_sawteeth", Kind::Integers), ("var_parallel_stripes", Kind::Integers),
+    ("var_crossed_stripes", Kind::Integers), ("width_spiral", Kind::Floats),
+    ("width_stripe", Kind::Floats), ("width_wave", Kind::Floats),
+    ("width_sawtooth", Kind::Floats), ("tightness_spiral", Kind::Floats),
+];
+
+#[rustfmt::skip]
+const TILINGS_KEYS: &[(&str, Kind)] = &[
+    ("size_hex", Kind::Floats), ("size_tri", Kind::Floats), ("size_hex_and_tri", Kind::Floats),
+    ("size_squ_and_tri", Kind::Floats), ("size_rho", Kind::Floats), ("size_pen", Kind::Floats),
+    ("nb_delaunay", Kind::Integers),
+];
+
+#[rustfmt::skip]
+const LOCATION_KEYS: &[(&str, Kind)] = &[("latitude", Kind::Float), ("longitude", Kind::Float)];
+
+#[rustfmt::skip]
+const ENTRY_KEYS: &[(&str, Kind)] = &[
+    ("span", Kind::StrOrArray), ("distance", Kind::UpTo(100)), ("themes", Kind::Names),
+    ("shapes", Kind::Names), ("line_color", Kind::Str), ("weekdays", Kind::StrArray),
+    ("months", Kind::StrArray), ("dates", Kind::StrArray), ("seasons", Kind::StrArray),
+    ("transition", Kind::Integer), ("sun", Kind::StrOrArray), ("weight", Kind::Integer),
+    ("global", Kind::Table), ("lines", Kind::Table), ("data", Kind::Table),
+];
+
+const THEME_ITEM_KEYS: &[&str] = &["color", "variability", "distance", "weight", "salt"];
+const SALT_ITEM_KEYS: &[&str] = &["color", "likeliness", "variability"];
+
+/// Collects diagnostics while walking the configuration
+#[derive(Default)]
+struct Linter {
+    diagnostics: Vec<Diagnostic>,
+    /// A value has the wrong type, the configuration cannot be read as a whole
+    unreadable: bool,
+}
+
+impl Linter {
+    fn error(&mut self, path: &str, message: String) {
+        self.diagnostics.push(Diagnostic {
+            severity: Severity::Error,
+            path: path.to_string(),
+            message,
+        });
+    }
+
+    fn warning(&mut self, path: &str, message: String) {
+        self.diagnostics.push(Diagnostic {
+            severity: Severity::Warning,
+            path: path.to_string(),
+            message,
+        });
+    }
+
+    /// Check that all keys of a table are known and have the right type
+    fn check_table(&mut self, path: &str, table: &Map<String, Value>, keys: &[(&str, Kind)]) {
+        for (name, val) in table {
+            let path = key(path, name);
+            match keys.iter().find(|(k, _)| k == name) {
+                None => self.warning(&path, String::from("unknown key, it will be ignored")),
+                Some((_, kind)) => self.check_kind(&path, val, *kind),
+            }
+        }
+    }
+
+    fn check_kind(&mut self, path: &str, val: &Value, kind: Kind) {
+        let (ok, expected) = match kind {
+            Kind::Integer | Kind::UpTo(_) => (
+                matches!(val, Value::Integer(n) if *n >= 0),
+                "a nonnegative integer",
+            ),
+            Kind::Float => (
+                matches!(val, Value::Float(_) | Value::Integer(_)),
+                "a number",
+            ),
+            Kind::Integers | Kind::IntegersUpTo(_) => (
+                is_param(val, |x| matches!(x, Value::Integer(n) if *n >= 0)),
+                "a nonnegative integer, a range [min, max] or a list of [value, weight] pairs",
+            ),
+            Kind::Floats => (
+                is_param(val, |x| matches!(x, Value::Float(_) | Value::Integer(_))),
+                "a number, a range [min, max] or a list of [value, weight] pairs",
+            ),
+            Kind::Str => (matches!(val, Value::String(_)), "a string"),
+            Kind::Bool => (matches!(val, Value::Boolean(_)), "true or false"),
+            Kind::StrArray => (
+                matches!(val, Value::Array(a) if a.iter().all(Value::is_str)),
+                "an array of strings",
+            ),
+            Kind::StrOrArray => (
+                match val {
+                    Value::String(_) => true,
+                    Value::Array(a) => a.iter().all(Value::is_str),
+                    _ => false,
+                },
+                "a string or an array of strings",
+            ),
+            Kind::Names => (
+                match val {
+                    Value::Array(a) => a.iter().all(|x| match x {
+                        Value::String(_) => true,
+                        Value::Array(p) => {
+                            matches!(&p[..], [Value::String(_), Value::Integer(w)] if *w >= 0)
+                        }
+                        _ => false,
+                    }),
+                    _ => false,
+                },
+                "an array of names or of [name, weight] pairs",
+            ),
+            Kind::Table => (matches!(val, Value::Table(_)), "a table"),
+        };
+        if !ok {
+            self.unreadable = true;
+            self.error(path, format!("expected {}, found {}", expected, val));
+        } else if matches!(kind, Kind::Integers | Kind::Floats | Kind::IntegersUpTo(_))
+            && matches!(val, Value::Array(a) if a.is_empty())
+        {
+            self.warning(path, String::from("empty list, the default value is used"));
+        } else if let Kind::UpTo(max) | Kind::IntegersUpTo(max) = kind {
+            self.check_max(path, val, max);
+        }
+    }
+
+    /// Numbers that must be between 0 and `max`, such as distances which mix two colors
+    /// (for ranges and lists, all values but not the weights)
+    fn check_max(&mut self, path: &str, val: &Value, max: usize) {
+        let values = match val {
+            Value::Array(a) => a
+                .iter()
+                .filter_map(|x| match x {
+                    Value::Array(pair) => pair.first(),
+                    x => Some(x),
+                })
+                .collect(),
+            x => vec![x],
+        };
+        for x in values {
+            let n = x.as_float().or_else(|| x.as_integer().map(|n| n as f64));
+            if matches!(n, Some(n) if !(0. ..=max as f64).contains(&n)) {
+                self.error(path, format!("{} is not between 0 and {}", x, max));
+            }
+        }
+    }
+
+    fn check_color(&mut self, path: &str, val: &Value, colors: &ColorList) {
+        if let Err(e) = color_from_value(val, colors) {
+            self.error(path, undefined_color(val, e));
+        }
+    }
+
+    fn check_colors(&mut self, list: &Map<String, Value>) -> ColorList {
+        // Colors are read in order, so a color can only refer to the previous ones
+        let mut colors = HashMap::new();
+        for (name, val) in list {
+            let path = key("colors", name);
+            match color_from_value(val, &colors) {
+                Ok(c) => {
+                    colors.insert(name.clone(), c);
+                }
+                Err(e) => match val.as_str() {
+                    Some(s) if list.contains_key(s) => self.error(
+                        &path,
+                        format!(
+                            "color {:?} is defined after {:?} and cannot be used here",
+                            s, name
+                        ),
+                    ),
+                    _ => self.error(&path, undefined_color(val, e)),
+                },
+            }
+        }
+        colors
+    }
+
+    fn check_themes(&mut self, list: &Map<String, Value>, colors: &ColorList) {
+        // Same as colors: a theme can only include themes that come before it
+        let mut seen = Vec::new();
+        for (name, val) in list {
+            let path = key("themes", name);
+            match val {
+                Value::Array(items) => {
+                    if items.is_empty() {
+                        self.warning(&path, String::from("theme is empty"));
+                    }
+                    for (i, item) in items.iter().enumerate() {
+                        let path = format!("{}[{}]", path, i);
+                        match item {
+                            Value::String(s) if seen.contains(&s) => (),
+                            Value::String(s) if list.contains_key(s) => self.error(
+                                &path,
+                                format!(
+                                    "theme {:?} is defined after {:?} and cannot be included here",
+                                    s, name
+                                ),
+                            ),
+                            Value::String(s) => self.check_theme_string(&path, s, colors),
+                            Value::Table(t) => self.check_theme_table(&path, t, colors),
+                            _ => self.error(
+                                &path,
+                                format!(
+                                    "expected a color, a theme name or a table, found {}",
+                                    item
+                                ),
+                            ),
+                        }
+                    }
+                }
+                _ => self.error(
+                    &path,
+                    format!("expected an array of theme items, found {}", val),
+                ),
+            }
+            seen.push(name);
+        }
+    }
+
+    /// Check the "<COLOR> xWEIGHT ~VAR !DISTANCE" format
+    fn check_theme_string(&mut self, path: &str, s: &str, colors: &ColorList) {
+        let mut has_color = false;
+        for item in s.split(' ').filter(|item| !item.is_empty()) {
+            let (what, arg) = match item.chars().next() {
+                Some('x') => ("weight", &item[1..]),
+                Some('~') => ("variability", &item[1..]),
+                Some('!') => ("distance", &item[1..]),
+                _ => {
+                    if has_color {
+                        self.warning(path, format!("several colors given, {:?} wins", item));
+                    }
+                    has_color = true;
+                    self.check_color(path, &Value::String(item.to_string()), colors);
+                    continue;
+                }
+            };
+            match arg.parse::<usize>() {
+                Err(_) => self.error(path, format!("{:?} is not a valid {}", arg, what)),
+                Ok(n) if what == "distance" && n > 100 => {
+                    self.error(path, format!("distance {} is not between 0 and 100", n))
+                }
+                Ok(_) => (),
+            }
+        }
+        if !has_color {
+            self.warning(path, String::from("no color given, black will be used"));
+        }
+    }
+
+    fn check_theme_table(&mut self, path: &str, table: &Map<String, Value>, colors: &ColorList) {
+        for (name, val) in table {
+            let path = key(path, name);
+            match name.as_str() {
+                "color" => self.check_color(&path, val, colors),
+                "variability" | "weight" => self.check_kind(&path, val, Kind::Float),
+                "distance" => {
+                    self.check_kind(&path, val, Kind::Float);
+                    self.check_max(&path, val, 100);
+                }
+                "salt" => match val {
+                    Value::Array(items) => {
+                        for (i, item) in items.iter().enumerate() {
+                            let path = format!("{}[{}]", path, i);
+                            match item {
+                                Value::Table(t) => self.check_salt(&path, t, colors),
+                                _ => self.error(&path, format!("expected a table, found {}", item)),
+                            }
+                        }
+                    }
+                    _ => self.error(&path, format!("expected an array, found {}", val)),
+                },
+                _ => self.warning(
+                    &path,
+                    format!(
+                        "unknown key, it will be ignored (expected one of {})",
+                        THEME_ITEM_KEYS.join(", ")
+                    ),
+                ),
+            }
+        }
+        if !table.contains_key("color") {
+            self.warning(path, String::from("no color given, black will be used"));
+        }
+    }
+
+    fn check_salt(&mut self, path: &str, table: &Map<String, Value>, colors: &ColorList) {
+        for (name, val) in table {
+            let path = key(path, name);
+            match name.as_str() {
+                "color" => self.check_color(&path, val, colors),
+                "likeliness" | "variability" => self.check_kind(&path, val, Kind::Float),
+                _ => self.warning(
+                    &path,
+                    format!(
+                        "unknown key, it will be ignored (expected one of {})",
+                        SALT_ITEM_KEYS.join(", ")
+                    ),
+                ),
+            }
+        }
+    }
+
+    fn check_shapes(&mut self, list: &Map<String, Value>) {
+        let mut seen = Vec::new();
+        for (name, val) in list {
+            let path = key("shapes", name);
+            match val {
+                Value::Array(items) => {
+                    for (i, item) in items.iter().enumerate() {
+                        let path = format!("{}[{}]", path, i);
+                        match item {
+                            Value::String(s) if seen.contains(&s) => (),
+                            Value::String(s) => self.check_shape_name(&path, s, name, list),
+                            Value::Array(a) => match &a[..] {
+                                [Value::String(s), Value::Integer(w)] if *w > 0 => {
+                                    self.check_shape_name(&path, s, name, list)
+                                }
+                                _ => self.error(
+                                    &path,
+                                    format!("expected [<SHAPE>, <WEIGHT>], found {}", item),
+                                ),
+                            },
+                            _ => {
+                                self.error(&path, format!("expected a shape name, found {}", item))
+                            }
+                        }
+                    }
+                }
+                _ => self.error(&path, format!("expected an array of shapes, found {}", val)),
+            }
+            seen.push(name);
+        }
+    }
+
+    fn check_shape_name(&mut self, path: &str, s: &str, current: &str, list: &Map<String, Value>) {
+        if Shape::from_name(s).is_some() {
+            return;
+        }
+        if list.contains_key(s) {
+            self.error(
+                path,
+                format!(
+                    "shapes {:?} are defined after {:?} and cannot be included here",
+                    s, current
+                ),
+            );
+        } else {
+            self.error(
+                path,
+                format!("{:?} is not a known shape (see `wallrnd list shapes`)", s),
+            );
+        }
+    }
+
+    /// Items of a key that accepts a string or an array of strings, with their paths
+    fn string_items<'v>(&mut self, path: &str, val: &'v Value) -> Vec<(String, &'v str)> {
+        match val {
+            Value::String(s) => vec![(path.to_string(), s.as_str())],
+            Value::Array(a) => {
+                if a.is_empty() {
+                    self.warning(path, String::from("empty list, this entry is never used"));
+                }
+                // Items that are not strings were already reported by `check_table`
+                a.iter()
+                    .enumerate()
+                    .filter_map(|(j, s)| Some((format!("{}[{}]", path, j), s.as_str()?)))
+                    .collect()
+            }
+            // Already reported by `check_table`
+            _ => Vec::new(),
+        }
+    }
+
+    fn check_location(&mut self, table: &Map<String, Value>) {
+        for (name, bound) in &[("latitude", 90.), ("longitude", 180.)] {
+            match table.get(*name) {
+                None => self.error(
+                    "location",
+                    format!("missing {}, sun positions are unavailable", name),
+                ),
+                Some(val) => {
+                    let x = val
+                        .as_float()
+                        .or_else(|| val.as_integer().map(|n| n as f64));
+                    if let Some(x) = x.filter(|x| x.abs() > *bound) {
+                        self.error(
+                            &key("location", name),
+                            format!("{} is not between -{} and {}", x, bound, bound),
+                        );
+                    }
+                }
+            }
+        }
+    }
+
+    /// Types in the `[global]`, `[lines]` and `[data]` tables, at the root or in an entry
+    fn check_settings(&mut self, path: &str, table: &Map<String, Value>) {
+        if let Some(Value::Table(t)) = table.get("global") {
+            self.check_table(&key(path, "global"), t, GLOBAL_KEYS);
+        }
+        if let Some(Value::Table(t)) = table.get("lines") {
+            self.check_table(&key(path, "lines"), t, LINES_KEYS);
+        }
+        if let Some(Value::Table(t)) = table.get("data") {
+            let data = key(path, "data");
+            self.check_table(&data, t, DATA_KEYS);
+            if let Some(Value::Table(p)) = t.get("patterns") {
+                self.check_table(&key(&data, "patterns"), p, PATTERNS_KEYS);
+            }
+            if let Some(Value::Table(p)) = t.get("tilings") {
+                self.check_table(&key(&data, "tilings"), p, TILINGS_KEYS);
+            }
+        }
+    }
+
+    /// Colors of the `[lines]` table, at the root or in an entry
+    fn check_line_colors(&mut self, path: &str, table: &Map<String, Value>, colors: &ColorList) {
+        if let Some(Value::Table(t)) = table.get("lines") {
+            let lines = key(path, "lines");
+            for (name, val) in t {
+                if name.ends_with("color") && val.is_str() {
+                    self.check_color(&key(&lines, name), val, colors);
+                }
+            }
+        }
+    }
+
+    /// Types in the entries, before the configuration is read as a whole
+    fn check_entry_tables(&mut self, entries: &[Value]) {
+        for (i, entry) in entries.iter().enumerate() {
+            let path = format!("entry[{}]", i);
+            match entry {
+                Value::Table(t) => {
+                    self.check_table(&path, t, ENTRY_KEYS);
+                    self.check_settings(&path, t);
+                }
+                _ => {
+                    self.unreadable = true;
+                    self.error(&path, format!("expected a table, found {}", entry));
+                }
+            }
+        }
+    }
+
+    fn check_entries(&mut self, entries: &[Value], cfg: &MetaConfig, colors: &ColorList) {
+        let themes = cfg.themes.as_ref().map(|t| &t.list);
+        let shapes = cfg.shapes.as_ref().map(|s| &s.list);
+        let mut spans = Vec::new();
+        let mut sun = false;
+        for (i, entry) in entries.iter().enumerate() {
+            let path = format!("entry[{}]", i);
+            let table = match entry {
+                Value::Table(t) => t,
+                // Already reported by `check_entry_tables`
+                _ => continue,
+            };
+            // Entries restricted to some dates do not cover the rest of the year
+            let mut always = true;
+            for sel in SELECTORS.iter() {
+                if let Some(Value::Array(items)) = table.get(sel.key()) {
+                    always = false;
+                    if items.is_empty() {
+                        self.warning(
+                            &key(&path, sel.key()),
+                            String::from("empty list, this entry is never used"),
+                        );
+                    }
+                    for (j, item) in items.iter().enumerate() {
+                        if let Some(Err(e)) = item.as_str().map(|s| sel.parse(s)) {
+                            self.error(&format!("{}.{}[{}]", path, sel.key(), j), e);
+                        }
+                    }
+                }
+            }
+            if let Some(Value::Integer(0)) = table.get("weight") {
+                self.warning(
+                    &key(&path, "weight"),
+                    String::from("weight is 0, this entry is never used"),
+                );
+            }
+            if table.contains_key("transition")
+                && !table.contains_key("span")
+                && !table.contains_key("sun")
+            {
+                self.warning(
+                    &key(&path, "transition"),
+                    String::from("transitions happen at the start of a span, this entry has none"),
+                );
+            }
+            if let Some(val) = table.get("sun") {
+                sun = true;
+                if cfg.location.is_none() {
+                    self.error(
+                        &key(&path, "sun"),
+                        String::from("no [location] is given, these spans are ignored"),
+                    );
+                }
+                for (sun_path, s) in self.string_items(&key(&path, "sun"), val) {
+                    if let Err(e) = parse_sun_span(s) {
+                        self.error(&sun_path, e);
+                    }
+                }
+            }
+            let items = match table.get("span") {
+                // Sun spans change every day, they do not count towards coverage
+                None if table.contains_key("sun") => Vec::new(),
+                None => vec![(key(&path, "span"), None)],
+                Some(val) => self
+                    .string_items(&key(&path, "span"), val)
+                    .into_iter()
+                    .map(|(p, s)| (p, Some(s)))
+                    .collect(),
+            };
+            for (span_path, s) in items {
+                let span = match s.map(parse_span) {
+                    None => Cyclic {
+                        start: 0,
+                        end: 2400,
+                    },
+                    Some(Ok(span)) => span,
+                    Some(Err(e)) => {
+                        self.error(&span_path, e);
+                        continue;
+                    }
+                };
+                if span.start == span.end {
+                    self.warning(
+                        &span_path,
+                        format!("{:?} only covers a single minute", s.unwrap_or("")),
+                    );
+                }
+                if always {
+                    spans.push(span);
+                }
+            }
+            for (field, defined, what) in
+                &[("themes", themes, "theme"), ("shapes", shapes, "shapes")]
+            {
+                if let Some(Value::Array(names)) = table.get(*field) {
+                    if names.is_empty() {
+                        self.warning(
+                            &key(&path, field),
+                            format!("no {} given, a random one will be used", what),
+                        );
+                    }
+                    for (j, item) in names.iter().enumerate() {
+                        let item_path = format!("{}.{}[{}]", path, field, j);
+                        let name = match item {
+                            Value::Array(pair) => {
+                                if let Some(Value::Integer(0)) = pair.get(1) {
+                                    self.warning(
+                                        &item_path,
+                                        String::from("weight is 0, this is never chosen"),
+                                    );
+                                }
+                                pair.first().and_then(Value::as_str)
+                            }
+                            _ => item.as_str(),
+                        };
+                        if let Some(name) = name {
+                            if !defined.map(|d| d.contains_key(name)).unwrap_or(false) {
+                                self.error(
+                                    &item_path,
+                                    format!(
+                                        "undefined {} {:?}, a random one will be used",
+                                        what, name
+                                    ),
+                                );
+                            }
+                        }
+                    }
+                }
+            }
+            if let Some(c) = table.get("line_color") {
+                if c.is_str() {
+                    self.check_color(&key(&path, "line_color"), c, colors);
+                }
+            }
+            self.check_line_colors(&path, table, colors);
+        }
+        if !entries.is_empty() {
+            for (start, end) in coverage_gaps(&spans) {
+                let message = if sun {
+                    format!(
+                        "no span covers {:04}-{:04}, a random theme will be used unless a sun span does",
+                        start, end
+                    )
+                } else {
+                    format!(
+                        "no entry covers {:04}-{:04}, a random theme will be used",
+                        start, end
+                    )
+                };
+                self.warning("entry", message);
+            }
+        }
+    }
+}
+
+/// Read all problems in the contents of a configuration file,
+/// files that it includes are relative to the current directory
+pub fn validate(src: &str) -> Vec<Diagnostic> {
+    validate_in(src, Path::new("."))
+}
+
+/// Read all problems in a configuration file and the files that it includes
+pub fn validate_file(path: &Path) -> Result<Vec<Diagnostic>> {
+    let src = std::fs::read_to_string(path)?;
+    Ok(validate_in(
+        &src,
+        path.parent().unwrap_or_else(|| Path::new(".")),
+    ))
+}
+
+fn validate_in(src: &str, dir: &Path) -> Vec<Diagnostic> {
+    let mut lint = Linter::default();
+    let mut root = match src.parse::<Value>() {
+        Ok(Value::Table(t)) => t,
+        Ok(_) => unreachable!("A TOML document is always a table"),
+        Err(e) => {
+            lint.error("", e.to_string());
+            return lint.diagnostics;
+        }
+    };
+    // Everything below applies to the configuration with its includes merged in
+    let included = root.contains_key("include");
+    if included {
+        match layers::parse(src, dir) {
+            Ok(merged) => root = merged,
+            Err(e) => {
+                lint.error("include", e.to_string());
+                return lint.diagnostics;
+            }
+        }
+    }
+    let sections: &[(&str, Kind)] = &[
+        ("global", Kind::Table),
+        ("lines", Kind::Table),
+        ("colors", Kind::Table),
+        ("themes", Kind::Table),
+        ("shapes", Kind::Table),
+        ("data", Kind::Table),
+        ("location", Kind::Table),
+    ];
+    for (name, val) in &root {
+        if name == "entry" {
+            if !val.is_array() {
+                lint.unreadable = true;
+                lint.error(name, format!("expected [[entry]] tables, found {}", val));
+            }
+        } else if let Some((_, kind)) = sections.iter().find(|(k, _)| k == name) {
+            lint.check_kind(name, val, *kind);
+        } else {
+            lint.warning(name, String::from("unknown section, it will be ignored"));
+        }
+    }
+    let section = |name| root.get(name).and_then(Value::as_table);
+    lint.check_settings("", &root);
+    if let Some(Value::Array(entries)) = root.get("entry") {
+        lint.check_entry_tables(entries);
+    }
+    if let Some(t) = section("location") {
+        lint.check_table("location", t, LOCATION_KEYS);
+    }
+    // Anything below depends on the configuration being readable as a whole
+    if lint.unreadable {
+        return lint.diagnostics;
+    }
+    let cfg = if included {
+        MetaConfig::from_table(root.clone())
+    } else {
+        MetaConfig::parse(src)
+    };
+    let cfg = match cfg {
+        Ok(cfg) => cfg,
+        Err(e) => {
+            lint.error("", e.to_string());
+            return lint.diagnostics;
+        }
+    };
+    let colors = match section("colors") {
+        Some(t) => lint.check_colors(t),
+        None => HashMap::new(),
+    };
+    lint.check_line_colors("", &root, &colors);
+    if let Some(t) = section("themes") {
+        lint.check_themes(t, &colors);
+    }
+    if let Some(t) = section("shapes") {
+        lint.check_shapes(t);
+    }
+    if let Some(t) = section("location") {
+        lint.check_location(t);
+    }
+    if let Some(Value::Array(entries)) = root.get("entry") {
+        lint.check_entries(entries, &cfg, &colors);
+    }
+    lint.diagnostics
+}
+
+/// Append a key to a path, quoting it if necessary
+fn key(path: &str, name: &str) -> String {
+    let bare = !name.is_empty()
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-');
+    let name = if bare {
+        name.to_string()
+    } else {
+        format!("{:?}", name)
+    };
+    if path.is_empty() {
+        name
+    } else {
+        format!("{}.{}", path, name)
+    }
+}
+
+/// A single number, a range `[min, max]` or a list of `[value, weight]` pairs
+fn is_param(val: &Value, number: fn(&Value) -> bool) -> bool {
+    match val {
+        Value::Array(a) => match &a[..] {
+            [min, max] if number(min) && number(max) => true,
+            items => items.iter().all(|x| match x.as_array().map(Vec::as_slice) {
+                Some([v, Value::Integer(w)]) => number(v) && *w >= 0,
+                _ => false,
+            }),
+        },
+        x => number(x),
+    }
+}
+
+fn undefined_color(val: &Value, e: Error) -> String {
+    match val {
+        Value::String(s) if !s.starts_with('#') => format!("undefined color {:?}", s),
+        _ => e.to_string(),
+    }
+}
+
+/// Ranges of times of the day (HHMM) that belong to none of the spans
+fn coverage_gaps(spans: &[Cyclic]) -> Vec<(u32, u32)> {
+    let mut gaps: Vec<(u32, u32)> = Vec::new();
+    let mut prev = None;
+    for t in (0..2400).filter(|t| t % 100 < 60) {
+        if !spans.iter().any(|span| span.contains(t)) {
+            match gaps.last_mut() {
+                Some(gap) if Some(gap.1) == prev => gap.1 = t,
+                _ => gaps.push((t, t)),
+            }
+        }
+        prev = Some(t);
+    }
+    // A gap around midnight is reported once
+    if gaps.len() > 1 && gaps[0].0 == 0 && gaps[gaps.len() - 1].1 == 2359 {
+        let (start, _) = gaps.pop().unwrap();
+        gaps[0].0 = start;
+    }
+    gaps
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Path and severity of every problem found
+    fn problems(src: &str) -> Vec<(Severity, String)> {
+        validate(src)
+            .into_iter()
+            .map(|d| (d.severity, d.path))
+            .collect()
+    }
+
+    fn errors(src: &str) -> Vec<String> {
+        problems(src)
+            .into_iter()
+            .filter(|(s, _)| *s == Severity::Error)
+            .map(|(_, p)| p)
+            .collect()
+    }
+
+    #[test]
+    fn default_config_is_valid() {
+        assert!(problems(include_str!("../assets/default.toml")).is_empty());
+    }
+
+    #[test]
+    fn wrong_type() {
+        assert_eq!(errors("[global]\nwidth = \"wide\""), ["global.width"]);
+        assert_eq!(errors("[lines]\nwidth = true"), ["lines.width"]);
+        assert_eq!(errors("[[entry]]\nthemes = \"night\""), ["entry[0].themes"]);
+    }
+
+    #[test]
+    fn unknown_key() {
+        assert_eq!(
+            problems("[global]\nwidht = 10\n[colors]\n[[entry]]\ncolour = \"red\""),
+            [
+                (Severity::Warning, String::from("global.widht")),
+                (Severity::Warning, String::from("entry[0].colour")),
+            ]
+        );
+        assert_eq!(
+            problems("[wallpaper]"),
+            [(Severity::Warning, String::from("wallpaper"))]
+        );
+    }
+
+    #[test]
+    fn bad_span() {
+        assert_eq!(errors("[[entry]]\nspan = \"0800-2500\""), ["entry[0].span"]);
+        assert_eq!(
+            errors("[[entry]]\nspan = \"0000-2359\"\n[[entry]]\nspan = [\"0000-1200\", \"noon\"]"),
+            ["entry[1].span[1]"]
+        );
+    }
+
+    #[test]
+    fn distance_out_of_range() {
+        let src = "
+[global]
+distance = 150
+weight = [[20, 1], [120, 3]]
+[colors]
+a = \"#000000\"
+[themes]
+t = [\"a !150\", { color = \"a\", distance = 101 }, \"a !100\"]
+[[entry]]
+distance = 200
+themes = [\"t\"]
+[entry.global]
+distance = [0, 101]
+";
+        assert_eq!(
+            errors(src),
+            [
+                "global.distance",
+                "global.weight",
+                "entry[0].distance",
+                "entry[0].global.distance",
+                "themes.t[0]",
+                "themes.t[1].distance",
+            ]
+        );
+        // Weights are not distances
+        assert!(errors("[global]\ndistance = [[100, 500]]").is_empty());
+    }
+
+    #[test]
+    fn precision_out_of_range() {
+        assert!(errors("[global]\nprecision = 15").is_empty());
+        assert_eq!(errors("[global]\nprecision = 400"), ["global.precision"]);
+    }
+}
@@ -0,0 +1,74 @@
+//! Behavior of the command line: scene files reproduce the image that was written,
+//! configuration problems are reported
+
+mod common;
+
+use std::process::Command;
+use tempfile::TempDir;
+
+fn wallrnd(args: &[&str]) {
+    let status = Command::new(env!("CARGO_BIN_EXE_wallrnd"))
+        .args(args)
+        .status()
+        .unwrap();
+    assert!(status.success(), "wallrnd {:?}", args);
+}
+
+/// Generate with `options`, then replay the scene file without them
+fn assert_replays(options: &[&str]) {
+    let dir = TempDir::new().unwrap();
+    let path = |name: &str| dir.path().join(name).to_str().unwrap().to_string();
+    let config = common::small_config(dir.path());
+    let config = config.to_str().unwrap();
+    let (log, image, replayed) = (path("scene.toml"), path("image.svg"), path("replayed.svg"));
+    let mut args = vec![
+        "--config", config, "--seed", "5", "--time", "1200", "--log", &log, "--image", &image,
+    ];
+    args.extend(options);
+    wallrnd(&args);
+    wallrnd(&["replay", &log, "--image", &replayed]);
+    assert_eq!(
+        std::fs::read_to_string(&image).unwrap(),
+        std::fs::read_to_string(&replayed).unwrap(),
+        "{:?}",
+        options
+    );
+}
+
+#[test]
+fn replay_plain() {
+    assert_replays(&[]);
+}
+
+#[test]
+fn replay_recolorable() {
+    assert_replays(&["--recolorable"]);
+}
+
+#[test]
+fn replay_compact() {
+    assert_replays(&["--compact", "--precision", "1", "--scale", "0.5"]);
+}
+
+#[test]
+fn broken_includes_are_reported() {
+    let dir = TempDir::new().unwrap();
+    let config = dir.path().join("wallrnd.toml");
+    std::fs::write(&config, "include = \"missing.toml\"\n").unwrap();
+    let image = dir.path().join("image.svg");
+    // Without --verbose
+    let output = Command::new(env!("CARGO_BIN_EXE_wallrnd"))
+        .args(["--config", config.to_str().unwrap(), "--seed", "5"])
+        .args(["--image", image.to_str().unwrap()])
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(stderr.contains("missing.toml"), "{}", stderr);
+    assert!(
+        stderr.contains("Switching to default settings"),
+        "{}",
+        stderr
+    );
+    assert!(output.stdout.is_empty());
+}
@@ -0,0 +1,26 @@
+//! Shared by the integration tests
+
+use std::path::{Path, PathBuf};
+
+pub const CONFIG: &str = include_str!("../../assets/default.toml");
+
+/// Frame of the images in tests, small to keep them fast
+pub const WIDTH: usize = 400;
+pub const HEIGHT: usize = 300;
+
+/// Write the default configuration to `dir`, along with a configuration
+/// that includes it with the frame of the tests. Returns the path of the latter.
+#[allow(dead_code)]
+pub fn small_config(dir: &Path) -> PathBuf {
+    std::fs::write(dir.join("default.toml"), CONFIG).unwrap();
+    let config = dir.join("wallrnd.toml");
+    std::fs::write(
+        &config,
+        format!(
+            "include = \"default.toml\"\n[global]\nwidth = {}\nheight = {}\n",
+            WIDTH, HEIGHT
+        ),
+    )
+    .unwrap();
+    config
+}
@@ -0,0 +1,64 @@
+//! Images only depend on the configuration, the time and the seed
+
+mod common;
+
+use common::{CONFIG, HEIGHT, WIDTH};
+use wallrnd::log::Logger;
+use wallrnd::{Generator, Verbosity, Wallpaper};
+
+fn generator(seed: u64) -> Generator<'static> {
+    Generator::new()
+        .config_str(CONFIG)
+        .time(1200)
+        .seed(seed)
+        .width(WIDTH)
+        .height(HEIGHT)
+}
+
+fn svg(wallpaper: &Wallpaper) -> String {
+    wallpaper.render(&Verbosity::default()).unwrap().to_string()
+}
+
+#[test]
+fn same_seed_same_image() {
+    for seed in 0..3 {
+        let first = generator(seed).render_svg().unwrap();
+        let second = generator(seed).render_svg().unwrap();
+        assert_eq!(first, second, "seed {}", seed);
+    }
+}
+
+#[test]
+fn different_seeds_different_images() {
+    let first = generator(1).render_svg().unwrap();
+    let second = generator(2).render_svg().unwrap();
+    assert_ne!(first, second);
+}
+
+#[test]
+fn replay_gives_same_image() {
+    for seed in 0..3 {
+        let wallpaper = generator(seed).build().unwrap();
+        let file = tempfile::NamedTempFile::new().unwrap();
+        let log = file.path().to_str().unwrap();
+        wallpaper.record().save(log).unwrap();
+        let replayed = Wallpaper::replay(Logger::load(log).unwrap());
+        assert_eq!(svg(&wallpaper), svg(&replayed), "seed {}", seed);
+    }
+}
+
+#[test]
+fn thread_count_does_not_matter() {
+    let pool = |n| {
+        rayon::ThreadPoolBuilder::new()
+            .num_threads(n)
+            .build()
+            .unwrap()
+    };
+    let (single, several) = (pool(1), pool(4));
+    for seed in 0..3 {
+        let first = single.install(|| generator(seed).render_svg().unwrap());
+        let second = several.install(|| generator(seed).render_svg().unwrap());
+        assert_eq!(first, second, "seed {}", seed);
+    }
+}